@@ -0,0 +1,678 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::{FromRow, Pool, Sqlite, SqliteConnection};
+use tracing::{info, warn};
+
+use crate::db::Timestamp;
+use crate::error::{Error, Result};
+use crate::net::NetMask;
+use crate::srp::StoredVerifier;
+use crate::util::nullable;
+
+/// Who is making a request, as far as permission checks are concerned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Principal {
+    /// `accounts.id`; `None` for anonymous visitors.
+    pub id: Option<i64>,
+    pub username: Option<String>,
+    /// Set on the account or on any of its groups.
+    pub admin: bool,
+    /// Names of every group the account belongs to, directly or through nested groups.
+    pub groups: Vec<String>,
+    /// Network masks the client must match, all of them: the account's own
+    /// `allow_net`, or when it has none, those of its groups.
+    pub allow_net: Vec<String>,
+    /// Address the request came from, once known.
+    pub ip: Option<IpAddr>,
+    /// The account has to set a new password before doing anything else.
+    #[serde(default)]
+    pub must_change_password: bool,
+}
+
+impl Principal {
+    pub fn anonymous() -> Self {
+        Self::default()
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.id.is_none()
+    }
+
+    /// True if `name` is this account or one of its groups.
+    pub fn is(&self, name: &str) -> bool {
+        self.username.as_deref() == Some(name) || self.groups.iter().any(|g| g == name)
+    }
+
+    /// Whether the account may be used from `ip`; an unknown address only
+    /// passes when no mask applies.
+    ///
+    /// A mask that does not parse refuses every address.
+    pub fn net_allowed(&self, ip: Option<IpAddr>) -> bool {
+        self.allow_net.iter().all(|m| match m.parse::<NetMask>() {
+            Ok(mask) => ip.is_some_and(|ip| mask.contains(ip)),
+            Err(e) => {
+                warn!(account = ?self.username, "refusing login: {e}");
+                false
+            }
+        })
+    }
+
+    /// Load an account and resolve its group membership transitively.
+    ///
+    /// Returns `None` if the account does not exist, is disabled or has expired.
+    pub async fn load(pool: &Pool<Sqlite>, account_id: i64) -> Result<Option<Self>> {
+        let row = sqlx::query!(
+            r#"SELECT username, admin AS "admin: bool", allow_net, disabled AS "disabled: bool",
+                      expire AS "expire: Timestamp",
+                      require_password_change AS "require_password_change: bool"
+               FROM accounts WHERE id = ?"#,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        if row.disabled || row.expire.is_some_and(|e| e <= Timestamp::now()) {
+            return Ok(None);
+        }
+
+        // UNION (not UNION ALL) drops rows already seen, so a membership cycle terminates.
+        let groups = sqlx::query!(
+            r#"WITH RECURSIVE g(id) AS (
+                   SELECT group_id FROM account_memberships WHERE account_id = ?1
+                   UNION
+                   SELECT m.group_id FROM account_memberships m JOIN g ON m.account_id = g.id
+               )
+               SELECT a.username AS "username!", a.allow_net, a.admin AS "admin!: bool"
+               FROM accounts a JOIN g ON a.id = g.id
+               ORDER BY a.username"#,
+            account_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let set = |m: &Option<String>| m.clone().filter(|m| !m.trim().is_empty());
+        let allow_net = match set(&row.allow_net) {
+            Some(own) => vec![own],
+            None => groups.iter().filter_map(|g| set(&g.allow_net)).collect(),
+        };
+        let admin = row.admin || groups.iter().any(|g| g.admin);
+
+        Ok(Some(Self {
+            id: Some(account_id),
+            username: Some(row.username),
+            admin,
+            groups: groups.into_iter().map(|g| g.username).collect(),
+            allow_net,
+            ip: None,
+            must_change_password: row.require_password_change,
+        }))
+    }
+}
+
+/// The columns deciding whether, and for how long, an account may log in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifecycle {
+    pub disabled: bool,
+    /// `None` = never.
+    pub expire: Option<Timestamp>,
+    /// Days from the first login until the account expires.
+    pub days_to_live: Option<i64>,
+    pub require_password_change: bool,
+    pub disable_password_change: bool,
+}
+
+impl Lifecycle {
+    /// Returns `None` if there is no such account (groups included).
+    pub async fn load(pool: &Pool<Sqlite>, account_id: i64) -> Result<Option<Self>> {
+        Ok(sqlx::query_as!(
+            Lifecycle,
+            r#"SELECT disabled AS "disabled: bool", expire AS "expire: Timestamp", days_to_live,
+                      require_password_change AS "require_password_change: bool",
+                      disable_password_change AS "disable_password_change: bool"
+               FROM accounts WHERE id = ? AND is_group = 0"#,
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    pub fn is_expired(&self, now: Timestamp) -> bool {
+        self.expire.is_some_and(|e| e <= now)
+    }
+
+    /// Refuse disabled and expired accounts.
+    pub fn check(&self, now: Timestamp) -> Result<()> {
+        if self.disabled {
+            return Err(Error::AccountDisabled);
+        }
+        if self.is_expired(now) {
+            return Err(Error::AccountExpired);
+        }
+        Ok(())
+    }
+}
+
+/// Check that an account may log in now, and on its first login turn
+/// `days_to_live` into an absolute `expire`.
+pub async fn begin_login(pool: &Pool<Sqlite>, account_id: i64) -> Result<Lifecycle> {
+    let mut lifecycle = Lifecycle::load(pool, account_id)
+        .await?
+        .ok_or(Error::AuthFailed)?;
+    let now = Timestamp::now();
+    lifecycle.check(now)?;
+
+    if lifecycle.expire.is_none()
+        && let Some(days) = lifecycle.days_to_live
+    {
+        let expire = now + Duration::from_secs(days.max(0) as u64 * 24 * 60 * 60);
+        sqlx::query!(
+            "UPDATE accounts SET expire = ? WHERE id = ? AND expire IS NULL",
+            expire,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+        info!(account_id, %expire, "first login; account expiry set");
+        lifecycle.expire = Some(expire);
+    }
+    Ok(lifecycle)
+}
+
+/// Change an account's own password, unless `disable_password_change` is set.
+/// Clears `require_password_change`.
+pub async fn change_password(
+    pool: &Pool<Sqlite>,
+    account_id: i64,
+    verifier: &StoredVerifier,
+) -> Result<()> {
+    let lifecycle = Lifecycle::load(pool, account_id)
+        .await?
+        .ok_or(Error::AuthFailed)?;
+    if lifecycle.disable_password_change {
+        return Err(Error::PasswordChangeDisabled);
+    }
+    let (srp, now) = (verifier.encode(), Timestamp::now());
+    sqlx::query!(
+        "UPDATE accounts SET srp = ?, require_password_change = 0, updated_at = ? WHERE id = ?",
+        srp,
+        now,
+        account_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Disable every account whose `expire` has passed and end its sessions.
+///
+/// Returns the ids of the accounts disabled.
+pub async fn disable_expired(pool: &Pool<Sqlite>) -> Result<Vec<i64>> {
+    let mut tx = pool.begin().await?;
+    let now = Timestamp::now();
+    let ids = sqlx::query_scalar!(
+        r#"UPDATE accounts SET disabled = 1
+           WHERE disabled = 0 AND is_group = 0 AND expire IS NOT NULL AND expire <= ?
+           RETURNING id AS "id!""#,
+        now
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for id in &ids {
+        sqlx::query!("DELETE FROM sessions WHERE account_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(ids)
+}
+
+/// Store a new SRP verifier for an account (registration or password change).
+///
+/// Returns `false` if there is no such account.
+pub async fn set_verifier(
+    conn: &mut SqliteConnection,
+    account_id: i64,
+    verifier: &StoredVerifier,
+) -> Result<bool> {
+    let (srp, now) = (verifier.encode(), Timestamp::now());
+    let done = sqlx::query!(
+        "UPDATE accounts SET srp = ?, updated_at = ? WHERE id = ? AND is_group = 0",
+        srp,
+        now,
+        account_id
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// A row of `accounts`; groups are accounts with `is_group` set.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize)]
+pub struct AccountRecord {
+    pub id: i64,
+    pub username: String,
+    pub is_group: bool,
+    pub admin: bool,
+    pub ignore_limits: bool,
+    pub redirect: Option<String>,
+    /// Encoded SRP verifier; `None` for groups and accounts without a
+    /// password. Never handed out: it serializes as `has_password`.
+    #[serde(rename = "has_password", serialize_with = "is_set")]
+    pub srp: Option<String>,
+    pub disabled: bool,
+    pub expire: Option<Timestamp>,
+    /// Turned into `expire` on the first login.
+    pub days_to_live: Option<i64>,
+    pub disable_password_change: bool,
+    pub require_password_change: bool,
+    pub allow_net: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl AccountRecord {
+    pub fn has_password(&self) -> bool {
+        self.srp.is_some()
+    }
+}
+
+fn is_set<S: Serializer>(srp: &Option<String>, s: S) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_bool(srp.is_some())
+}
+
+/// A row of `account_memberships`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromRow, Serialize)]
+pub struct Membership {
+    pub account_id: i64,
+    pub group_id: i64,
+}
+
+/// Narrows [`list_accounts`]; unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AccountFilter {
+    /// Part of the username.
+    pub q: Option<String>,
+    pub is_group: Option<bool>,
+    pub admin: Option<bool>,
+    pub disabled: Option<bool>,
+    /// Direct members of this group only.
+    pub group: Option<String>,
+}
+
+/// Matching accounts by username, and how many match in total.
+pub async fn list_accounts(
+    pool: &Pool<Sqlite>,
+    filter: &AccountFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<AccountRecord>, i64)> {
+    let q = filter.q.as_deref().map(|q| {
+        q.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    });
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "n!: i64" FROM accounts a
+           WHERE (?1 IS NULL OR a.username LIKE '%' || ?1 || '%' ESCAPE '\')
+             AND (?2 IS NULL OR a.is_group = ?2)
+             AND (?3 IS NULL OR a.admin = ?3)
+             AND (?4 IS NULL OR a.disabled = ?4)
+             AND (?5 IS NULL OR a.id IN (
+                 SELECT m.account_id FROM account_memberships m
+                 JOIN accounts g ON g.id = m.group_id WHERE g.username = ?5))"#,
+        q,
+        filter.is_group,
+        filter.admin,
+        filter.disabled,
+        filter.group
+    )
+    .fetch_one(pool)
+    .await?;
+    let rows = sqlx::query_as!(
+        AccountRecord,
+        r#"SELECT a.id AS "id!", a.username, a.is_group AS "is_group: bool",
+                  a.admin AS "admin: bool", a.ignore_limits AS "ignore_limits: bool", a.redirect,
+                  a.srp, a.disabled AS "disabled: bool", a.expire AS "expire: Timestamp",
+                  a.days_to_live, a.disable_password_change AS "disable_password_change: bool",
+                  a.require_password_change AS "require_password_change: bool", a.allow_net,
+                  a.created_at AS "created_at: Timestamp", a.updated_at AS "updated_at: Timestamp"
+           FROM accounts a
+           WHERE (?1 IS NULL OR a.username LIKE '%' || ?1 || '%' ESCAPE '\')
+             AND (?2 IS NULL OR a.is_group = ?2)
+             AND (?3 IS NULL OR a.admin = ?3)
+             AND (?4 IS NULL OR a.disabled = ?4)
+             AND (?5 IS NULL OR a.id IN (
+                 SELECT m.account_id FROM account_memberships m
+                 JOIN accounts g ON g.id = m.group_id WHERE g.username = ?5))
+           ORDER BY a.username LIMIT ?6 OFFSET ?7"#,
+        q,
+        filter.is_group,
+        filter.admin,
+        filter.disabled,
+        filter.group,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok((rows, total))
+}
+
+pub async fn find_record(conn: &mut SqliteConnection, id: i64) -> Result<Option<AccountRecord>> {
+    Ok(sqlx::query_as!(
+        AccountRecord,
+        r#"SELECT id AS "id!", username, is_group AS "is_group: bool", admin AS "admin: bool",
+                  ignore_limits AS "ignore_limits: bool", redirect, srp,
+                  disabled AS "disabled: bool", expire AS "expire: Timestamp", days_to_live,
+                  disable_password_change AS "disable_password_change: bool",
+                  require_password_change AS "require_password_change: bool", allow_net,
+                  created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+           FROM accounts WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await?)
+}
+
+pub async fn find_by_name(
+    conn: &mut SqliteConnection,
+    username: &str,
+) -> Result<Option<AccountRecord>> {
+    Ok(sqlx::query_as!(
+        AccountRecord,
+        r#"SELECT id AS "id!", username, is_group AS "is_group: bool", admin AS "admin: bool",
+                  ignore_limits AS "ignore_limits: bool", redirect, srp,
+                  disabled AS "disabled: bool", expire AS "expire: Timestamp", days_to_live,
+                  disable_password_change AS "disable_password_change: bool",
+                  require_password_change AS "require_password_change: bool", allow_net,
+                  created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+           FROM accounts WHERE username = ?"#,
+        username
+    )
+    .fetch_optional(conn)
+    .await?)
+}
+
+/// A group or member, by id and name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountRef {
+    pub id: i64,
+    pub username: String,
+}
+
+/// Groups `id` belongs to directly.
+pub async fn groups_of(conn: &mut SqliteConnection, id: i64) -> Result<Vec<AccountRef>> {
+    Ok(sqlx::query_as!(
+        AccountRef,
+        r#"SELECT a.id AS "id!", a.username FROM account_memberships m
+           JOIN accounts a ON a.id = m.group_id WHERE m.account_id = ? ORDER BY a.username"#,
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// The memberships of `id` in the groups it belongs to directly.
+pub async fn memberships(conn: &mut SqliteConnection, id: i64) -> Result<Vec<Membership>> {
+    Ok(sqlx::query_as!(
+        Membership,
+        "SELECT account_id, group_id FROM account_memberships WHERE account_id = ?",
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Direct members of group `id`.
+pub async fn members_of(conn: &mut SqliteConnection, id: i64) -> Result<Vec<AccountRef>> {
+    Ok(sqlx::query_as!(
+        AccountRef,
+        r#"SELECT a.id AS "id!", a.username FROM account_memberships m
+           JOIN accounts a ON a.id = m.account_id WHERE m.group_id = ? ORDER BY a.username"#,
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Column changes for [`create_account`] and [`update_account`]; `None` keeps
+/// the current (or default) value, and for nullable columns `Some(None)`
+/// clears it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AccountChanges {
+    pub username: Option<String>,
+    pub admin: Option<bool>,
+    pub ignore_limits: Option<bool>,
+    pub disabled: Option<bool>,
+    pub disable_password_change: Option<bool>,
+    pub require_password_change: Option<bool>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub redirect: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub expire: Option<Option<Timestamp>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub days_to_live: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub allow_net: Option<Option<String>>,
+}
+
+/// Usernames are also used in permissions and SRP proofs; keep them plain.
+pub fn validate_username(name: &str) -> Result<()> {
+    let ok = !name.is_empty()
+        && name.len() <= 64
+        && name == name.trim()
+        && !name
+            .chars()
+            .any(|c| c.is_control() || matches!(c, '/' | '\\' | ':'));
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::InvalidUsername(name.to_string()))
+    }
+}
+
+/// Create an account or group and return its id.
+pub async fn create_account(
+    conn: &mut SqliteConnection,
+    username: &str,
+    is_group: bool,
+    changes: &AccountChanges,
+) -> Result<i64> {
+    validate_username(username)?;
+    let taken = sqlx::query_scalar!("SELECT id FROM accounts WHERE username = ?", username)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if taken.is_some() {
+        return Err(Error::UsernameTaken(username.to_string()));
+    }
+    let id = sqlx::query_scalar!(
+        r#"INSERT INTO accounts (username, is_group) VALUES (?, ?) RETURNING id AS "id!""#,
+        username,
+        is_group
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let changes = AccountChanges {
+        username: None,
+        ..changes.clone()
+    };
+    update_account(conn, id, &changes).await?;
+    Ok(id)
+}
+
+/// Apply `changes`; returns `false` if there is no such account.
+///
+/// Renaming does not touch the password, whose verifier is bound to the old
+/// name; callers set a new one alongside.
+pub async fn update_account(
+    conn: &mut SqliteConnection,
+    id: i64,
+    changes: &AccountChanges,
+) -> Result<bool> {
+    if let Some(name) = &changes.username {
+        validate_username(name)?;
+        let taken = sqlx::query_scalar!(
+            "SELECT id FROM accounts WHERE username = ? AND id != ?",
+            name,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        if taken.is_some() {
+            return Err(Error::UsernameTaken(name.clone()));
+        }
+    }
+    if let Some(Some(mask)) = &changes.allow_net
+        && !mask.trim().is_empty()
+    {
+        mask.parse::<NetMask>()?;
+    }
+
+    let (set_redirect, redirect) = (
+        changes.redirect.is_some(),
+        changes.redirect.clone().flatten(),
+    );
+    let (set_expire, expire) = (changes.expire.is_some(), changes.expire.flatten());
+    let (set_days, days_to_live) = (
+        changes.days_to_live.is_some(),
+        changes.days_to_live.flatten(),
+    );
+    let (set_net, allow_net) = (
+        changes.allow_net.is_some(),
+        changes.allow_net.clone().flatten(),
+    );
+    let now = Timestamp::now();
+    let done = sqlx::query!(
+        "UPDATE accounts SET
+             username = COALESCE(?, username),
+             admin = COALESCE(?, admin),
+             ignore_limits = COALESCE(?, ignore_limits),
+             disabled = COALESCE(?, disabled),
+             disable_password_change = COALESCE(?, disable_password_change),
+             require_password_change = COALESCE(?, require_password_change),
+             redirect = CASE WHEN ? THEN ? ELSE redirect END,
+             expire = CASE WHEN ? THEN ? ELSE expire END,
+             days_to_live = CASE WHEN ? THEN ? ELSE days_to_live END,
+             allow_net = CASE WHEN ? THEN ? ELSE allow_net END,
+             updated_at = ?
+         WHERE id = ?",
+        changes.username,
+        changes.admin,
+        changes.ignore_limits,
+        changes.disabled,
+        changes.disable_password_change,
+        changes.require_password_change,
+        set_redirect,
+        redirect,
+        set_expire,
+        expire,
+        set_days,
+        days_to_live,
+        set_net,
+        allow_net,
+        now,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// Delete an account or group; memberships and sessions go with it.
+pub async fn delete_account(conn: &mut SqliteConnection, id: i64) -> Result<bool> {
+    let done = sqlx::query!("DELETE FROM accounts WHERE id = ?", id)
+        .execute(conn)
+        .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// Put account `member` into `group`. Nested groups are fine, as long as no
+/// group ends up containing itself.
+///
+/// Returns `false` if either account does not exist.
+pub async fn add_membership(conn: &mut SqliteConnection, member: i64, group: i64) -> Result<bool> {
+    let names = sqlx::query!(
+        r#"SELECT id AS "id!", username, is_group AS "is_group: bool"
+           FROM accounts WHERE id IN (?, ?)"#,
+        member,
+        group
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let name = |id| {
+        names
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| (a.username.clone(), a.is_group))
+    };
+    let (Some((member_name, _)), Some((group_name, is_group))) = (name(member), name(group)) else {
+        return Ok(false);
+    };
+    if !is_group {
+        return Err(Error::NotAGroup(group_name));
+    }
+
+    // Would `member` be reachable from `group` by going up through its groups?
+    let cycle = sqlx::query_scalar!(
+        r#"WITH RECURSIVE up(id) AS (
+               SELECT ?1
+               UNION
+               SELECT m.group_id FROM account_memberships m JOIN up ON m.account_id = up.id
+           )
+           SELECT EXISTS (SELECT 1 FROM up WHERE id = ?2) AS "cycle!: bool""#,
+        group,
+        member
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    if cycle {
+        return Err(Error::MembershipCycle {
+            member: member_name,
+            group: group_name,
+        });
+    }
+
+    sqlx::query!(
+        "INSERT OR IGNORE INTO account_memberships (account_id, group_id) VALUES (?, ?)",
+        member,
+        group
+    )
+    .execute(conn)
+    .await?;
+    Ok(true)
+}
+
+/// Take account `member` out of `group`; returns `false` if it was not in it.
+pub async fn remove_membership(
+    conn: &mut SqliteConnection,
+    member: i64,
+    group: i64,
+) -> Result<bool> {
+    let done = sqlx::query!(
+        "DELETE FROM account_memberships WHERE account_id = ? AND group_id = ?",
+        member,
+        group
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// Look up groups by name, failing on the first that is not one.
+pub async fn group_ids(conn: &mut SqliteConnection, names: &[String]) -> Result<Vec<i64>> {
+    let mut ids = Vec::with_capacity(names.len());
+    for name in names {
+        let id = sqlx::query_scalar!(
+            r#"SELECT id AS "id!" FROM accounts WHERE username = ? AND is_group = 1"#,
+            name
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+        ids.push(id.ok_or_else(|| Error::NotAGroup(name.clone()))?);
+    }
+    Ok(ids)
+}
@@ -0,0 +1,589 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
+
+use crate::account::Principal;
+use crate::vfs::list::ListOptions;
+use crate::vfs::perm::Permission;
+use crate::vfs::{Entry, EntryKind, Vfs};
+
+/// How deep an archive follows folders.
+const ARCHIVE_DEPTH: usize = 64;
+const READ_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ArchiveFormat {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz", alias = "tgz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+impl Vfs {
+    /// Stream an archive of `base`, or of `selection` (entries below `base`) when given.
+    ///
+    /// Entries the caller cannot `can_read` are left out, and folders are only
+    /// followed where they may `can_list`. File contents are read and sent one
+    /// chunk at a time; only the ZIP central directory is kept until the end.
+    pub fn archive_stream(
+        self: Arc<Self>,
+        who: Principal,
+        base: Entry,
+        selection: Option<Vec<Entry>>,
+        format: ArchiveFormat,
+    ) -> ReceiverStream<io::Result<Vec<u8>>> {
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            let mut out = Sink::new(tx.clone(), format);
+            let res = write_archive(&self, &who, &base, selection, format, &mut out).await;
+            let res = match res {
+                Ok(()) => out.finish().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res
+                && e.kind() != io::ErrorKind::BrokenPipe
+            {
+                warn!(path = %base.url_path, "archive aborted: {e}");
+                let _ = tx.send(Err(e)).await;
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+}
+
+async fn write_archive(
+    vfs: &Arc<Vfs>,
+    who: &Principal,
+    base: &Entry,
+    selection: Option<Vec<Entry>>,
+    format: ArchiveFormat,
+    out: &mut Sink,
+) -> io::Result<()> {
+    let mut writer = match format {
+        ArchiveFormat::Zip => Writer::Zip(Vec::new()),
+        ArchiveFormat::Tar | ArchiveFormat::TarGz => Writer::Tar,
+    };
+    let opts = ListOptions {
+        depth: ARCHIVE_DEPTH,
+        require: Permission::CanRead,
+        descend_if: Permission::CanList,
+    };
+
+    let folders = match selection {
+        None => vec![base.clone()],
+        Some(entries) => {
+            let mut folders = Vec::new();
+            for entry in entries {
+                if !vfs.can(who, Permission::CanRead, &entry) {
+                    continue;
+                }
+                writer.add(base, &entry, out).await?;
+                if entry.is_folder() && vfs.can(who, Permission::CanList, &entry) {
+                    folders.push(entry);
+                }
+            }
+            folders
+        }
+    };
+
+    for folder in folders {
+        let mut entries = vfs.clone().list_stream(who.clone(), folder, opts);
+        while let Some(item) = entries.next().await {
+            match item {
+                Ok(entry) => writer.add(base, &entry, out).await?,
+                Err(e) => warn!(path = %base.url_path, "skipping unreadable entry in archive: {e}"),
+            }
+        }
+    }
+    writer.finish(out).await
+}
+
+/// Path of `entry` inside an archive of `base`: decoded URL segments joined by `/`.
+fn archive_path(base: &Entry, entry: &Entry) -> Option<String> {
+    let rel = entry.url_path.strip_prefix(&base.url_path)?;
+    let mut segments = Vec::new();
+    for raw in rel.split('/').filter(|s| !s.is_empty()) {
+        segments.push(urlencoding::decode(raw).ok()?.into_owned());
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+fn mtime_secs(entry: &Entry) -> u64 {
+    entry
+        .metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Where archive bytes go: optionally gzipped, then into the response channel.
+struct Sink {
+    tx: mpsc::Sender<io::Result<Vec<u8>>>,
+    gzip: Option<GzEncoder<Vec<u8>>>,
+    /// Uncompressed bytes written so far, i.e. the current archive offset.
+    written: u64,
+}
+
+impl Sink {
+    fn new(tx: mpsc::Sender<io::Result<Vec<u8>>>, format: ArchiveFormat) -> Self {
+        let gzip = (format == ArchiveFormat::TarGz)
+            .then(|| GzEncoder::new(Vec::new(), Compression::default()));
+        Self {
+            tx,
+            gzip,
+            written: 0,
+        }
+    }
+
+    async fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.written += data.len() as u64;
+        let chunk = match &mut self.gzip {
+            Some(gz) => {
+                gz.write_all(data)?;
+                std::mem::take(gz.get_mut())
+            }
+            None => data.to_vec(),
+        };
+        self.send(chunk).await
+    }
+
+    async fn finish(mut self) -> io::Result<()> {
+        if let Some(gz) = self.gzip.take() {
+            let rest = gz.finish()?;
+            self.send(rest).await?;
+        }
+        Ok(())
+    }
+
+    /// A closed channel means the client went away; reported as `BrokenPipe`.
+    async fn send(&mut self, chunk: Vec<u8>) -> io::Result<()> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.tx
+            .send(Ok(chunk))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+enum Writer {
+    /// Central directory records, written after the last entry.
+    Zip(Vec<ZipRecord>),
+    Tar,
+}
+
+impl Writer {
+    async fn add(&mut self, base: &Entry, entry: &Entry, out: &mut Sink) -> io::Result<()> {
+        let Some(name) = archive_path(base, entry) else {
+            return Ok(());
+        };
+        let mtime = mtime_secs(entry);
+        match (entry.kind, &entry.source) {
+            (EntryKind::Folder, _) => match self {
+                Writer::Zip(records) => {
+                    let record = ZipRecord::new(format!("{name}/"), 0, mtime, out.written);
+                    out.write(&record.local_header()).await?;
+                    records.push(record);
+                }
+                Writer::Tar => {
+                    out.write(&tar_header(&format!("{name}/"), 0, mtime, true))
+                        .await?
+                }
+            },
+            (EntryKind::File, Some(path)) => {
+                let mut file = match tokio::fs::File::open(path).await {
+                    Ok(f) => f,
+                    Err(e) => {
+                        warn!(path = %path.display(), "skipping file in archive: {e}");
+                        return Ok(());
+                    }
+                };
+                let size = file.metadata().await?.len();
+                match self {
+                    Writer::Zip(records) => {
+                        let mut record = ZipRecord::new(name, size, mtime, out.written);
+                        out.write(&record.local_header()).await?;
+                        let mut hasher = crc32fast::Hasher::new();
+                        record.size = copy_file(&mut file, size, out, |b| hasher.update(b)).await?;
+                        record.crc = hasher.finalize();
+                        out.write(&record.data_descriptor()).await?;
+                        records.push(record);
+                    }
+                    Writer::Tar => {
+                        out.write(&tar_header(&name, size, mtime, false)).await?;
+                        let copied = copy_file(&mut file, size, out, |_| {}).await?;
+                        // The header promised `size` bytes; make up for a file that shrank.
+                        let pad = (size - copied) + tar_padding(size);
+                        out.write(&vec![0; pad as usize]).await?;
+                    }
+                }
+            }
+            // Links have no content.
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn finish(self, out: &mut Sink) -> io::Result<()> {
+        match self {
+            Writer::Zip(records) => {
+                let start = out.written;
+                for record in &records {
+                    out.write(&record.central_header()).await?;
+                }
+                let end = zip_end(
+                    records.len() as u64,
+                    start,
+                    out.written - start,
+                    out.written,
+                );
+                out.write(&end).await
+            }
+            Writer::Tar => out.write(&[0; 1024]).await,
+        }
+    }
+}
+
+/// Copy at most `limit` bytes of `file` into `out`, returning how many were copied.
+async fn copy_file(
+    file: &mut tokio::fs::File,
+    limit: u64,
+    out: &mut Sink,
+    mut inspect: impl FnMut(&[u8]),
+) -> io::Result<u64> {
+    let mut buf = vec![0; READ_CHUNK];
+    let mut copied = 0;
+    while copied < limit {
+        let want = (limit - copied).min(READ_CHUNK as u64) as usize;
+        let n = file.read(&mut buf[..want]).await?;
+        if n == 0 {
+            break;
+        }
+        inspect(&buf[..n]);
+        out.write(&buf[..n]).await?;
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+// ZIP (stored, streamed with data descriptors, ZIP64 where needed).
+
+const ZIP64_LIMIT: u64 = 0xFFFF_FFFF;
+const FLAG_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+/// Made by Unix, spec version 4.5.
+const MADE_BY: u16 = (3 << 8) | 45;
+
+struct ZipRecord {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+    dos_time: u16,
+    dos_date: u16,
+    dir: bool,
+    /// Sizes go in a ZIP64 extra field; decided before the data is sent.
+    zip64: bool,
+}
+
+impl ZipRecord {
+    fn new(name: String, size: u64, mtime: u64, offset: u64) -> Self {
+        let (dos_date, dos_time) = dos_datetime(mtime);
+        Self {
+            dir: name.ends_with('/'),
+            name,
+            crc: 0,
+            size,
+            offset,
+            dos_time,
+            dos_date,
+            zip64: size >= ZIP64_LIMIT,
+        }
+    }
+
+    fn flags(&self) -> u16 {
+        if self.dir {
+            FLAG_UTF8
+        } else {
+            FLAG_UTF8 | FLAG_DESCRIPTOR
+        }
+    }
+
+    fn version_needed(&self) -> u16 {
+        if self.zip64 || self.offset >= ZIP64_LIMIT {
+            45
+        } else {
+            20
+        }
+    }
+
+    fn local_header(&self) -> Vec<u8> {
+        let mut extra = Vec::new();
+        if self.zip64 {
+            // Real sizes follow in the data descriptor.
+            put16(&mut extra, 0x0001);
+            put16(&mut extra, 16);
+            put64(&mut extra, 0);
+            put64(&mut extra, 0);
+        }
+        let masked = if self.zip64 { u32::MAX } else { 0 };
+
+        let mut b = Vec::with_capacity(30 + self.name.len() + extra.len());
+        put32(&mut b, 0x0403_4b50);
+        put16(&mut b, self.version_needed());
+        put16(&mut b, self.flags());
+        put16(&mut b, 0); // stored
+        put16(&mut b, self.dos_time);
+        put16(&mut b, self.dos_date);
+        put32(&mut b, 0); // crc, in the descriptor
+        put32(&mut b, masked);
+        put32(&mut b, masked);
+        put16(&mut b, self.name.len() as u16);
+        put16(&mut b, extra.len() as u16);
+        b.extend_from_slice(self.name.as_bytes());
+        b.extend_from_slice(&extra);
+        b
+    }
+
+    fn data_descriptor(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(24);
+        put32(&mut b, 0x0807_4b50);
+        put32(&mut b, self.crc);
+        if self.zip64 {
+            put64(&mut b, self.size);
+            put64(&mut b, self.size);
+        } else {
+            put32(&mut b, self.size as u32);
+            put32(&mut b, self.size as u32);
+        }
+        b
+    }
+
+    fn central_header(&self) -> Vec<u8> {
+        let big_offset = self.offset >= ZIP64_LIMIT;
+        let mut extra = Vec::new();
+        if self.zip64 || big_offset {
+            let mut fields = Vec::new();
+            if self.zip64 {
+                put64(&mut fields, self.size);
+                put64(&mut fields, self.size);
+            }
+            if big_offset {
+                put64(&mut fields, self.offset);
+            }
+            put16(&mut extra, 0x0001);
+            put16(&mut extra, fields.len() as u16);
+            extra.extend_from_slice(&fields);
+        }
+        let size = if self.zip64 {
+            u32::MAX
+        } else {
+            self.size as u32
+        };
+        let offset = if big_offset {
+            u32::MAX
+        } else {
+            self.offset as u32
+        };
+        let mode: u32 = if self.dir { 0o040755 } else { 0o100644 };
+        let dos_attrs: u32 = if self.dir { 0x10 } else { 0 };
+
+        let mut b = Vec::with_capacity(46 + self.name.len() + extra.len());
+        put32(&mut b, 0x0201_4b50);
+        put16(&mut b, MADE_BY);
+        put16(&mut b, self.version_needed());
+        put16(&mut b, self.flags());
+        put16(&mut b, 0);
+        put16(&mut b, self.dos_time);
+        put16(&mut b, self.dos_date);
+        put32(&mut b, self.crc);
+        put32(&mut b, size);
+        put32(&mut b, size);
+        put16(&mut b, self.name.len() as u16);
+        put16(&mut b, extra.len() as u16);
+        put16(&mut b, 0); // comment
+        put16(&mut b, 0); // disk
+        put16(&mut b, 0); // internal attributes
+        put32(&mut b, (mode << 16) | dos_attrs);
+        put32(&mut b, offset);
+        b.extend_from_slice(self.name.as_bytes());
+        b.extend_from_slice(&extra);
+        b
+    }
+}
+
+/// End of central directory, preceded by the ZIP64 record and locator when any
+/// field overflows.
+fn zip_end(count: u64, cd_offset: u64, cd_size: u64, end_offset: u64) -> Vec<u8> {
+    let mut b = Vec::new();
+    if count >= 0xFFFF || cd_offset >= ZIP64_LIMIT || cd_size >= ZIP64_LIMIT {
+        put32(&mut b, 0x0606_4b50);
+        put64(&mut b, 44);
+        put16(&mut b, MADE_BY);
+        put16(&mut b, 45);
+        put32(&mut b, 0);
+        put32(&mut b, 0);
+        put64(&mut b, count);
+        put64(&mut b, count);
+        put64(&mut b, cd_size);
+        put64(&mut b, cd_offset);
+
+        put32(&mut b, 0x0706_4b50);
+        put32(&mut b, 0);
+        put64(&mut b, end_offset);
+        put32(&mut b, 1);
+    }
+    put32(&mut b, 0x0605_4b50);
+    put16(&mut b, 0);
+    put16(&mut b, 0);
+    put16(&mut b, count.min(0xFFFF) as u16);
+    put16(&mut b, count.min(0xFFFF) as u16);
+    put32(&mut b, cd_size.min(ZIP64_LIMIT) as u32);
+    put32(&mut b, cd_offset.min(ZIP64_LIMIT) as u32);
+    put16(&mut b, 0);
+    b
+}
+
+/// MS-DOS date and time (UTC) of a Unix timestamp, clamped to 1980.
+fn dos_datetime(secs: u64) -> (u16, u16) {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (y, m, d) = civil_from_days(days);
+    if y < 1980 {
+        return ((1 << 5) | 1, 0);
+    }
+    let date = (((y - 1980).min(127) as u16) << 9) | ((m as u16) << 5) | d as u16;
+    let time = (((rem / 3600) as u16) << 11)
+        | ((((rem % 3600) / 60) as u16) << 5)
+        | ((rem % 60) / 2) as u16;
+    (date, time)
+}
+
+/// Proleptic Gregorian date from days since 1970-01-01 (Howard Hinnant's algorithm).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+fn put16(b: &mut Vec<u8>, v: u16) {
+    b.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put32(b: &mut Vec<u8>, v: u32) {
+    b.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put64(b: &mut Vec<u8>, v: u64) {
+    b.extend_from_slice(&v.to_le_bytes());
+}
+
+// TAR (ustar, with a PAX header for long names and huge files).
+
+const TAR_BLOCK: u64 = 512;
+/// Largest size an 11-digit octal field holds.
+const TAR_MAX_SIZE: u64 = 0o77777777777;
+
+fn tar_padding(size: u64) -> u64 {
+    (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK
+}
+
+fn tar_header(name: &str, size: u64, mtime: u64, dir: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pax = String::new();
+    if name.len() > 100 {
+        pax.push_str(&pax_record("path", name));
+    }
+    if size > TAR_MAX_SIZE {
+        pax.push_str(&pax_record("size", &size.to_string()));
+    }
+    if !pax.is_empty() {
+        out.extend(ustar_block("PaxHeader", pax.len() as u64, mtime, b'x'));
+        out.extend_from_slice(pax.as_bytes());
+        out.resize(out.len() + tar_padding(pax.len() as u64) as usize, 0);
+    }
+    let typeflag = if dir { b'5' } else { b'0' };
+    out.extend(ustar_block(name, size.min(TAR_MAX_SIZE), mtime, typeflag));
+    out
+}
+
+/// `"<len> key=value\n"`, where `<len>` counts the whole record including itself.
+fn pax_record(key: &str, value: &str) -> String {
+    let body = format!(" {key}={value}\n");
+    let mut len = body.len() + 1;
+    while len.to_string().len() + body.len() != len {
+        len += 1;
+    }
+    format!("{len}{body}")
+}
+
+fn ustar_block(name: &str, size: u64, mtime: u64, typeflag: u8) -> [u8; 512] {
+    let mut h = [0u8; 512];
+    // Over-long names were already put in a PAX record; keep a truncated copy here.
+    let mut cut = name.len().min(100);
+    while !name.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    h[..cut].copy_from_slice(&name.as_bytes()[..cut]);
+    let mode = if typeflag == b'5' { 0o755 } else { 0o644 };
+    octal(&mut h[100..108], mode);
+    octal(&mut h[108..116], 0);
+    octal(&mut h[116..124], 0);
+    octal(&mut h[124..136], size);
+    octal(&mut h[136..148], mtime.min(TAR_MAX_SIZE));
+    h[156] = typeflag;
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+
+    h[148..156].fill(b' ');
+    let sum: u32 = h.iter().map(|&b| u32::from(b)).sum();
+    h[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    h
+}
+
+/// Zero-padded octal with a trailing NUL, filling `field`.
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{value:0width$o}");
+    field[..width].copy_from_slice(&digits.as_bytes()[digits.len() - width..]);
+    field[width] = 0;
+}
@@ -0,0 +1,148 @@
+//! Audit trail of administrative changes, kept in `audit_log`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, Pool, Sqlite, SqliteConnection};
+
+use crate::account::Principal;
+use crate::db::{JsonText, Timestamp};
+use crate::error::Result;
+
+/// What was changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target<'a> {
+    /// `account`, `vfs_node`, ...
+    pub kind: &'a str,
+    pub id: Option<i64>,
+    pub name: Option<&'a str>,
+}
+
+impl<'a> Target<'a> {
+    pub fn account(id: i64, name: &'a str) -> Self {
+        Self {
+            kind: "account",
+            id: Some(id),
+            name: Some(name),
+        }
+    }
+
+    pub fn vfs_node(id: i64, name: &'a str) -> Self {
+        Self {
+            kind: "vfs_node",
+            id: Some(id),
+            name: Some(name),
+        }
+    }
+
+    /// The server's configuration.
+    pub fn config() -> Self {
+        Self {
+            kind: "config",
+            id: None,
+            name: None,
+        }
+    }
+}
+
+/// Record `action` by `actor`. Call it on the transaction making the change,
+/// so the entry and the change stand or fall together.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    actor: &Principal,
+    action: &str,
+    target: Target<'_>,
+    detail: Value,
+) -> Result<()> {
+    let detail = (!detail.is_null()).then_some(JsonText::Valid(detail));
+    let (now, ip) = (Timestamp::now(), actor.ip.map(|ip| ip.to_string()));
+    sqlx::query!(
+        "INSERT INTO audit_log (at, actor_id, actor, ip, action, target_kind, target_id, target, detail)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        now,
+        actor.id,
+        actor.username,
+        ip,
+        action,
+        target.kind,
+        target.id,
+        target.name,
+        detail
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// A row of `audit_log`.
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: Timestamp,
+    pub actor_id: Option<i64>,
+    pub actor: Option<String>,
+    pub ip: Option<String>,
+    pub action: String,
+    pub target_kind: String,
+    pub target_id: Option<i64>,
+    pub target: Option<String>,
+    pub detail: Option<JsonText<Value>>,
+}
+
+/// Narrows [`list`]; unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor_id: Option<i64>,
+    pub target_kind: Option<String>,
+    pub target_id: Option<i64>,
+    /// Exact action, or a prefix ending in `.` (`account.`).
+    pub action: Option<String>,
+    pub since: Option<Timestamp>,
+}
+
+/// Matching entries, newest first, and how many match in total.
+pub async fn list(
+    pool: &Pool<Sqlite>,
+    filter: &AuditFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<AuditEntry>, i64)> {
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "n!: i64" FROM audit_log
+           WHERE (?1 IS NULL OR actor_id = ?1)
+             AND (?2 IS NULL OR target_kind = ?2)
+             AND (?3 IS NULL OR target_id = ?3)
+             AND (?4 IS NULL OR action = ?4
+                  OR (substr(?4, -1) = '.' AND substr(action, 1, length(?4)) = ?4))
+             AND (?5 IS NULL OR at >= ?5)"#,
+        filter.actor_id,
+        filter.target_kind,
+        filter.target_id,
+        filter.action,
+        filter.since
+    )
+    .fetch_one(pool)
+    .await?;
+    let rows = sqlx::query_as!(
+        AuditEntry,
+        r#"SELECT id AS "id!", at AS "at: Timestamp", actor_id, actor, ip, action, target_kind,
+                  target_id, target, detail AS "detail: JsonText<Value>"
+           FROM audit_log
+           WHERE (?1 IS NULL OR actor_id = ?1)
+             AND (?2 IS NULL OR target_kind = ?2)
+             AND (?3 IS NULL OR target_id = ?3)
+             AND (?4 IS NULL OR action = ?4
+                  OR (substr(?4, -1) = '.' AND substr(action, 1, length(?4)) = ?4))
+             AND (?5 IS NULL OR at >= ?5)
+           ORDER BY id DESC LIMIT ?6 OFFSET ?7"#,
+        filter.actor_id,
+        filter.target_kind,
+        filter.target_id,
+        filter.action,
+        filter.since,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok((rows, total))
+}
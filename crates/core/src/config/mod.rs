@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ::config::{ConfigError, Source, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::oidc::OidcProvider;
+use crate::paths::{Dirs, SYSTEM_CONFIG};
+use crate::upload::CollisionPolicy;
+
+mod validate;
+
+pub use validate::{Issue, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Daily,
+    Hourly,
+}
+
+/// A PEM certificate chain and its private key.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TlsCert {
+    pub cert: String,
+    pub key: String,
+}
+
+/// Fields missing from a config file take their values from [`Config::default`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub addr: String,
+    pub port: u16,
+    pub https_port: u16,
+    /// PEM certificate chain served for HTTPS on `https_port`.
+    pub tls_cert: Option<String>,
+    /// PEM private key for `tls_cert`.
+    pub tls_key: Option<String>,
+    /// More certificates, each served to clients asking (through SNI) for a
+    /// host name it was issued for; `tls_cert` is served to the rest.
+    pub tls_certs: Vec<TlsCert>,
+    /// Answer plain HTTP on `port` with a redirect to HTTPS on `https_port`.
+    pub https_redirect: bool,
+    pub log_path: Option<String>,
+    pub log_error_path: Option<String>,
+    pub log_level: String,
+    pub log_rotation: LogRotation,
+    pub title: Option<String>,
+    pub db_path: String,
+    /// Reverse proxies (addresses, CIDR blocks, ranges or wildcards) whose
+    /// `X-Forwarded-*` headers are trusted.
+    pub trusted_proxies: Vec<String>,
+    /// When not empty, only clients matching one of these network masks get in.
+    pub allow_net: Vec<String>,
+    /// Clients matching these network masks are refused.
+    pub deny_net: Vec<String>,
+    /// Largest upload accepted per request, in bytes; `None` = unlimited.
+    pub max_upload_size: Option<u64>,
+    /// What to do when an uploaded file's name is taken.
+    pub upload_collision: CollisionPolicy,
+    /// Seconds an idle resumable upload is kept before it is discarded.
+    pub upload_expiry: u64,
+    /// Seconds a login session lasts at most.
+    pub session_max_age: u64,
+    /// Seconds of inactivity after which a session ends.
+    pub session_idle_timeout: u64,
+    /// Seconds between writes of session activity to the database.
+    pub session_touch_interval: u64,
+    /// Seconds between sweeps that disable expired accounts.
+    pub account_sweep_interval: u64,
+    /// Mark the session cookie `Secure`; unset = only for HTTPS requests.
+    pub cookie_secure: Option<bool>,
+    /// OpenID Connect providers offered on the login page.
+    pub oidc_providers: Vec<OidcProvider>,
+    /// Base64 master key sealing stored provider tokens; `FERRI_MASTER_KEY`
+    /// takes precedence.
+    pub master_key: Option<String>,
+    /// File holding the master key, used when `master_key` is unset.
+    pub master_key_file: Option<String>,
+    /// Retired master keys, still read until `ferri keys rotate` has run.
+    pub old_master_keys: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::for_dirs(&Dirs::standard())
+    }
+}
+
+impl Config {
+    /// The defaults, keeping the database and logs in `dirs`.
+    pub fn for_dirs(dirs: &Dirs) -> Self {
+        Self {
+            addr: "0.0.0.0".to_string(),
+            port: 8080,
+            https_port: 8443,
+            tls_cert: None,
+            tls_key: None,
+            tls_certs: Vec::new(),
+            https_redirect: false,
+            log_level: "info".to_string(),
+            log_rotation: LogRotation::Daily,
+            title: Some("Ferri".to_string()),
+            db_path: dirs.db_path().to_string_lossy().to_string(),
+            log_path: Some(dirs.log_path().to_string_lossy().to_string()),
+            log_error_path: Some(dirs.log_error_path().to_string_lossy().to_string()),
+            trusted_proxies: Vec::new(),
+            allow_net: Vec::new(),
+            deny_net: Vec::new(),
+            max_upload_size: None,
+            upload_collision: CollisionPolicy::default(),
+            upload_expiry: 24 * 60 * 60,
+            session_max_age: 30 * 24 * 60 * 60,
+            session_idle_timeout: 7 * 24 * 60 * 60,
+            session_touch_interval: 60,
+            account_sweep_interval: 5 * 60,
+            cookie_secure: None,
+            oidc_providers: Vec::new(),
+            master_key: None,
+            master_key_file: None,
+            old_master_keys: Vec::new(),
+        }
+    }
+
+    /// Keep the database and the logs under `dir`.
+    pub fn set_data_dir(&mut self, dir: &Path) {
+        let defaults = Self::for_dirs(&Dirs::standard().with_data_dir(dir));
+        self.db_path = defaults.db_path;
+        self.log_path = defaults.log_path;
+        self.log_error_path = defaults.log_error_path;
+    }
+
+    /// Ensure log and DB parent directories exist.
+    pub fn ensure_dirs(&self) -> Result<()> {
+        if let Some(ref p) = self.log_path {
+            fs::create_dir_all(p)?;
+        }
+        if let Some(ref p) = self.log_error_path {
+            fs::create_dir_all(p)?;
+        }
+        // Ensure DB parent directory exists (if any)
+        if let Some(parent) = std::path::Path::new(&self.db_path).parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Environment variables that override config keys: `FERRI_PORT=9000`
+/// sets `port`. Lists take comma-separated values.
+pub const ENV_PREFIX: &str = "FERRI";
+
+/// Keys read from the environment and the command line as comma-separated
+/// lists.
+const LIST_KEYS: &[&str] = &[
+    "trusted_proxies",
+    "allow_net",
+    "deny_net",
+    "old_master_keys",
+];
+
+/// The config files read, lowest precedence first: the system file, unless
+/// portable, then `config.toml` in the config directory, or `file` instead.
+pub fn config_files(dirs: &Dirs, file: Option<&Path>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if cfg!(unix) && !dirs.portable {
+        files.push(PathBuf::from(SYSTEM_CONFIG));
+    }
+    files.push(file.map_or_else(|| dirs.config_file(), Path::to_path_buf));
+    files
+}
+
+/// Load the config in layers, each overriding the one before: the defaults
+/// for `dirs`, the [`config_files`] that exist, `FERRI_*` environment variables, then
+/// `overrides` (`key`, `value`) from the command line.
+///
+/// `file`, when given, must exist. Nothing is written to disk.
+pub fn load_config(
+    dirs: &Dirs,
+    file: Option<&Path>,
+    overrides: &[(String, String)],
+) -> Result<Config> {
+    let mut layers: Vec<(String, Box<dyn Source + Send + Sync>)> = Vec::new();
+    for path in config_files(dirs, file) {
+        let required = file == Some(path.as_path());
+        let source = ::config::File::from(path.as_path())
+            .format(::config::FileFormat::Toml)
+            .required(required);
+        layers.push((path.display().to_string(), Box::new(source)));
+    }
+    let mut env = ::config::Environment::with_prefix(ENV_PREFIX)
+        .prefix_separator("_")
+        .list_separator(",")
+        .try_parsing(true);
+    for key in LIST_KEYS {
+        env = env.with_list_parse_key(key);
+    }
+    layers.push(("the environment".to_string(), Box::new(env)));
+    layers.push((
+        "the command line".to_string(),
+        Box::new(Overrides(overrides.to_vec())),
+    ));
+
+    let sources: Vec<_> = layers.iter().map(|(_, s)| s.clone()).collect();
+    let cfg = ::config::Config::builder()
+        .add_source(::config::Config::try_from(&Config::for_dirs(dirs))?)
+        .add_source(sources)
+        .build()?;
+    cfg.try_deserialize()
+        .map_err(|e| with_origin(e, &layers).into())
+}
+
+/// Conversion errors name the key but not where its value came from; add the
+/// last layer that sets it.
+fn with_origin(
+    err: ConfigError,
+    layers: &[(String, Box<dyn Source + Send + Sync>)],
+) -> ConfigError {
+    let origin_of = |key: &str| {
+        let top = key.split(['.', '[']).next().unwrap_or(key);
+        layers
+            .iter()
+            .rev()
+            .find(|(_, source)| source.collect().is_ok_and(|m| m.contains_key(top)))
+            .map(|(name, _)| name.clone())
+    };
+    match err {
+        ConfigError::Type {
+            origin: None,
+            unexpected,
+            expected,
+            key: Some(key),
+        } => ConfigError::Type {
+            origin: origin_of(&key),
+            unexpected,
+            expected,
+            key: Some(key),
+        },
+        ConfigError::At {
+            error,
+            origin: None,
+            key: Some(key),
+        } => ConfigError::At {
+            error,
+            origin: origin_of(&key),
+            key: Some(key),
+        },
+        err => err,
+    }
+}
+
+/// Command-line settings, as a source so that errors point at them.
+#[derive(Debug, Clone)]
+struct Overrides(Vec<(String, String)>);
+
+impl Source for Overrides {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> std::result::Result<::config::Map<String, Value>, ConfigError> {
+        let origin = "the command line".to_string();
+        Ok(self
+            .0
+            .iter()
+            .map(|(key, value)| {
+                let value = if LIST_KEYS.contains(&key.as_str()) {
+                    let items = value
+                        .split(',')
+                        .filter(|s| !s.trim().is_empty())
+                        .map(|s| Value::new(Some(&origin), s.trim()))
+                        .collect::<Vec<_>>();
+                    Value::new(Some(&origin), items)
+                } else {
+                    Value::new(Some(&origin), value.as_str())
+                };
+                (key.clone(), value)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,297 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+
+use serde::Serialize;
+use tracing_subscriber::EnvFilter;
+
+use super::Config;
+use crate::envelope::Keyring;
+use crate::net::NetMask;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Works, but probably not as intended.
+    Warning,
+    /// The server refuses to start.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found by [`Config::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// The config key at fault.
+    pub key: &'static str,
+    pub message: String,
+    /// How to fix it.
+    pub hint: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}\n  hint: {}",
+            self.severity, self.key, self.message, self.hint
+        )
+    }
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn error(&mut self, key: &'static str, message: impl Into<String>, hint: impl Into<String>) {
+        self.push(Severity::Error, key, message.into(), hint.into());
+    }
+
+    fn warn(&mut self, key: &'static str, message: impl Into<String>, hint: impl Into<String>) {
+        self.push(Severity::Warning, key, message.into(), hint.into());
+    }
+
+    fn push(&mut self, severity: Severity, key: &'static str, message: String, hint: String) {
+        self.0.push(Issue {
+            severity,
+            key,
+            message,
+            hint,
+        });
+    }
+}
+
+impl Config {
+    /// Every problem with this config, errors first. The server starts only
+    /// when none is an error.
+    ///
+    /// Checks the file system, and resolves `addr` when it is a host name.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = Issues::default();
+        self.check_addr(&mut issues);
+        self.check_ports(&mut issues);
+        if let Err(e) = EnvFilter::try_new(&self.log_level) {
+            issues.error(
+                "log_level",
+                format!("{:?} is not a valid filter: {e}", self.log_level),
+                "use a level (error, warn, info, debug, trace) or directives such as \
+                 `info,ferri=debug`",
+            );
+        }
+        self.check_paths(&mut issues);
+        self.check_tls(&mut issues);
+        let lists = [
+            ("trusted_proxies", &self.trusted_proxies),
+            ("allow_net", &self.allow_net),
+            ("deny_net", &self.deny_net),
+        ];
+        for (key, list) in lists {
+            if let Err(e) = NetMask::from_list(list) {
+                issues.error(
+                    key,
+                    e.to_string(),
+                    "masks look like `10.0.0.1`, `10.0.0.0/8`, `192.168.1.*` or \
+                     `10.0.0.1-10.0.0.9`",
+                );
+            }
+        }
+        if let Err(e) = Keyring::from_config(self) {
+            issues.error(
+                "master_key",
+                e.to_string(),
+                "generate a key with `ferri keys generate`, and check master_key, \
+                 master_key_file, old_master_keys and FERRI_MASTER_KEY",
+            );
+        }
+        let mut issues = issues.0;
+        issues.sort_by_key(|i| Reverse(i.severity));
+        issues
+    }
+
+    fn check_addr(&self, issues: &mut Issues) {
+        if self.addr.parse::<IpAddr>().is_ok() {
+            return;
+        }
+        let hint = "use an IP address: 0.0.0.0 or :: for every interface, 127.0.0.1 for this \
+                    machine only";
+        match (self.addr.as_str(), self.port).to_socket_addrs() {
+            Ok(mut addrs) => {
+                if addrs.next().is_none() {
+                    issues.error(
+                        "addr",
+                        format!("{:?} resolves to no address", self.addr),
+                        hint,
+                    );
+                }
+            }
+            Err(e) => issues.error(
+                "addr",
+                format!(
+                    "{:?} is not an IP address and cannot be resolved: {e}",
+                    self.addr
+                ),
+                hint,
+            ),
+        }
+    }
+
+    fn check_ports(&self, issues: &mut Issues) {
+        if self.port == 0 {
+            issues.warn(
+                "port",
+                "port 0 listens on a random port",
+                "set a fixed port, such as 8080",
+            );
+        }
+        if self.port == self.https_port {
+            let message = format!("port and https_port are both {}", self.port);
+            let hint = "give https_port a different port, such as 8443";
+            if self.tls_enabled() {
+                issues.error("https_port", message, hint);
+            } else {
+                issues.warn("https_port", message, hint);
+            }
+        }
+    }
+
+    fn check_paths(&self, issues: &mut Issues) {
+        if self.db_path != ":memory:" {
+            let path = Path::new(&self.db_path);
+            let result = if path.is_dir() {
+                Err("is a directory".to_string())
+            } else if path.exists() {
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .map(drop)
+                    .map_err(|e| e.to_string())
+            } else {
+                writable_dir(path.parent().unwrap_or(Path::new(".")))
+            };
+            if let Err(e) = result {
+                issues.error(
+                    "db_path",
+                    format!("cannot write {}: {e}", path.display()),
+                    "point db_path at a writable location, fix the permissions, or pass \
+                     --data-dir",
+                );
+            }
+        }
+        let logs = [
+            ("log_path", &self.log_path),
+            ("log_error_path", &self.log_error_path),
+        ];
+        for (key, dir) in logs {
+            let Some(dir) = dir else { continue };
+            if let Err(e) = writable_dir(Path::new(dir)) {
+                issues.error(
+                    key,
+                    format!("cannot write logs to {dir}: {e}"),
+                    format!(
+                        "point {key} at a writable directory, fix the permissions, or remove \
+                         it to log to the console only"
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_tls(&self, issues: &mut Issues) {
+        match (&self.tls_cert, &self.tls_key) {
+            (None, None) => {}
+            (Some(_), None) => issues.error(
+                "tls_key",
+                "tls_cert is set without tls_key",
+                "set tls_key to the certificate's PEM private key",
+            ),
+            (None, Some(_)) => issues.error(
+                "tls_cert",
+                "tls_key is set without tls_cert",
+                "set tls_cert to a PEM certificate chain",
+            ),
+            (Some(cert), Some(key)) => {
+                for (name, path) in [("tls_cert", cert), ("tls_key", key)] {
+                    if let Err(e) = readable_pem(Path::new(path)) {
+                        issues.error(
+                            name,
+                            format!("cannot use {path}: {e}"),
+                            format!("point {name} at a readable PEM file"),
+                        );
+                    }
+                }
+            }
+        }
+        for pair in &self.tls_certs {
+            for path in [&pair.cert, &pair.key] {
+                if let Err(e) = readable_pem(Path::new(path)) {
+                    issues.error(
+                        "tls_certs",
+                        format!("cannot use {path}: {e}"),
+                        "point each entry's cert and key at readable PEM files",
+                    );
+                }
+            }
+        }
+        if !self.tls_enabled() {
+            if !self.tls_certs.is_empty() {
+                issues.warn(
+                    "tls_certs",
+                    "tls_certs is set but HTTPS is off",
+                    "set tls_cert and tls_key, which are served when no other certificate matches",
+                );
+            }
+            if self.https_redirect {
+                issues.warn(
+                    "https_redirect",
+                    "https_redirect is set but HTTPS is off; nothing is redirected",
+                    "set tls_cert and tls_key, or remove https_redirect",
+                );
+            }
+        }
+    }
+
+    /// Both a certificate and a key are set.
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert.is_some() && self.tls_key.is_some()
+    }
+}
+
+/// Whether files can be created in `dir`, or, if it does not exist yet, in
+/// the nearest parent that does, which is where it would be created.
+fn writable_dir(dir: &Path) -> Result<(), String> {
+    let existing = dir
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(format!("{} is not a directory", existing.display()));
+    }
+    // Permission bits do not tell the whole story (ACLs, read-only mounts).
+    let probe = existing.join(format!(".ferri-write-test-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|e| e.to_string())?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+fn readable_pem(path: &Path) -> Result<(), String> {
+    let content = fs::read(path).map_err(|e| e.to_string())?;
+    if !content.windows(11).any(|w| w == b"-----BEGIN ") {
+        return Err("not a PEM file".to_string());
+    }
+    Ok(())
+}
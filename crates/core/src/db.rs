@@ -1,11 +1,42 @@
+//! Opening the database, and the column types shared by the typed rows.
+//!
+//! Each table has one `FromRow` struct, kept beside the queries over it:
+//! `accounts` and `account_memberships` in [`account`](crate::account),
+//! `sessions` in [`session`](crate::session), `oauth_states`,
+//! `account_identities` and `provider_tokens` in [`oidc`](crate::oidc),
+//! `audit_log` in [`audit`](crate::audit) and the `vfs_*` tables in
+//! [`vfs`](crate::vfs). Queries go through `sqlx::query!`/`query_as!`, so they
+//! are checked against the schema when the crates are built. Offline builds
+//! read the metadata kept in `.sqlx/` at the workspace root; after touching a
+//! query or a migration, refresh it against a migrated database:
+//!
+//! ```sh
+//! DATABASE_URL=sqlite://ferri.db cargo sqlx prepare --workspace -- --all-targets
+//! ```
+//!
+//! Functions take a `&mut SqliteConnection` where they may be part of a larger
+//! change, so the same call works on a pooled connection and inside a
+//! transaction (see [`transaction`]).
+
+use std::fmt;
 use std::io; // for ErrorKind when enriching errors
+use std::ops::{Add, Sub};
 use std::path::Path;
 use std::time::Duration;
 
 use crate::config::Config;
 use crate::error::{Error, Result};
-use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
-use sqlx::{Pool, Sqlite};
+use crate::util::unix_now;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{
+    SqliteArgumentValue, SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions,
+    SqliteSynchronous, SqliteTypeInfo, SqliteValueRef,
+};
+use sqlx::types::time::{OffsetDateTime, PrimitiveDateTime};
+use sqlx::{Decode, Encode, Pool, Sqlite, SqliteConnection, Type};
 
 /// Create a *lazy* SQLite pool from config and make sure the on-disk path exists.
 ///
@@ -69,3 +100,189 @@ pub async fn bootstrap_db(pool: &Pool<Sqlite>) -> Result<()> {
     sqlx::migrate!("../../migrations").run(pool).await?;
     Ok(())
 }
+
+/// Run `f` in a transaction, committed when it returns `Ok` and rolled back
+/// otherwise.
+pub async fn transaction<T, E>(
+    db: &Pool<Sqlite>,
+    f: impl AsyncFnOnce(&mut SqliteConnection) -> std::result::Result<T, E>,
+) -> std::result::Result<T, E>
+where
+    E: From<Error>,
+{
+    let mut tx = db.begin().await.map_err(Error::from)?;
+    let out = f(&mut tx).await?;
+    tx.commit().await.map_err(Error::from)?;
+    Ok(out)
+}
+
+/// A point in time stored as Unix seconds in an INTEGER column.
+///
+/// Values outside the years -9999..=9999 are clamped when read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub OffsetDateTime);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self::from_unix(unix_now())
+    }
+
+    pub fn from_unix(secs: i64) -> Self {
+        Self(
+            OffsetDateTime::from_unix_timestamp(secs).unwrap_or_else(|_| {
+                if secs < 0 {
+                    PrimitiveDateTime::MIN.assume_utc()
+                } else {
+                    PrimitiveDateTime::MAX.assume_utc()
+                }
+            }),
+        )
+    }
+
+    pub fn unix(self) -> i64 {
+        self.0.unix_timestamp()
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self {
+        Self::from_unix(self.unix().saturating_add_unsigned(rhs.as_secs()))
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Self;
+
+    fn sub(self, rhs: Duration) -> Self {
+        Self::from_unix(self.unix().saturating_sub_unsigned(rhs.as_secs()))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Type<Sqlite> for Timestamp {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Sqlite> for Timestamp {
+    fn encode_by_ref(
+        &self,
+        buf: &mut Vec<SqliteArgumentValue<'_>>,
+    ) -> std::result::Result<IsNull, BoxDynError> {
+        Encode::<Sqlite>::encode(self.unix(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Timestamp {
+    fn decode(value: SqliteValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        Ok(Self::from_unix(<i64 as Decode<Sqlite>>::decode(value)?))
+    }
+}
+
+/// Serialized as Unix seconds, like the API's other times.
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_i64(self.unix())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        i64::deserialize(d).map(Self::from_unix)
+    }
+}
+
+/// A JSON TEXT column read as `T`.
+///
+/// Text that does not parse does not fail the query: it is kept with the
+/// error, so a caller can log and skip that one row and keep the rest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonText<T> {
+    Valid(T),
+    Invalid { text: String, error: String },
+}
+
+impl<T> JsonText<T> {
+    pub fn valid(&self) -> Option<&T> {
+        match self {
+            JsonText::Valid(v) => Some(v),
+            JsonText::Invalid { .. } => None,
+        }
+    }
+
+    pub fn into_result(self) -> std::result::Result<T, String> {
+        match self {
+            JsonText::Valid(v) => Ok(v),
+            JsonText::Invalid { error, .. } => Err(error),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> JsonText<T> {
+    pub fn parse(text: &str) -> Self {
+        match serde_json::from_str(text) {
+            Ok(v) => JsonText::Valid(v),
+            Err(e) => JsonText::Invalid {
+                text: text.to_string(),
+                error: e.to_string(),
+            },
+        }
+    }
+}
+
+impl<T> Type<Sqlite> for JsonText<T> {
+    fn type_info() -> SqliteTypeInfo {
+        <str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <str as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<T: Serialize> Encode<'_, Sqlite> for JsonText<T> {
+    fn encode_by_ref(
+        &self,
+        buf: &mut Vec<SqliteArgumentValue<'_>>,
+    ) -> std::result::Result<IsNull, BoxDynError> {
+        let text = match self {
+            JsonText::Valid(v) => serde_json::to_string(v)?,
+            JsonText::Invalid { text, .. } => text.clone(),
+        };
+        Encode::<Sqlite>::encode(text, buf)
+    }
+}
+
+impl<'r, T: DeserializeOwned> Decode<'r, Sqlite> for JsonText<T> {
+    fn decode(value: SqliteValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        Ok(Self::parse(<&str as Decode<Sqlite>>::decode(value)?))
+    }
+}
+
+/// Serialized as the parsed value; text that did not parse as the raw string.
+impl<T: Serialize> Serialize for JsonText<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            JsonText::Valid(v) => v.serialize(s),
+            JsonText::Invalid { text, .. } => s.serialize_str(text),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for JsonText<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        T::deserialize(d).map(JsonText::Valid)
+    }
+}
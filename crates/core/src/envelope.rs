@@ -0,0 +1,329 @@
+//! Envelope encryption for secrets kept in the database.
+//!
+//! Every row gets its own random data key, which seals the row's values with
+//! XChaCha20-Poly1305. The data key is stored next to them, wrapped by a
+//! master key that never touches the database; rows record the id of that
+//! master key, so rotating it only means re-wrapping the data keys.
+
+use std::fmt;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Environment variable that overrides the configured master key.
+pub const MASTER_KEY_ENV: &str = "FERRI_MASTER_KEY";
+pub const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// A key-encryption key. Its id is derived from the key, so it cannot get out
+/// of sync with the key material.
+#[derive(Clone)]
+pub struct MasterKey {
+    id: String,
+    key: Key,
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish()
+    }
+}
+
+impl MasterKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != KEY_LEN {
+            return Err(Error::Key(format!(
+                "master key must be {KEY_LEN} bytes, got {}",
+                bytes.len()
+            )));
+        }
+        let digest = Sha256::new()
+            .chain_update(b"ferri master key id\0")
+            .chain_update(bytes)
+            .finalize();
+        Ok(Self {
+            id: hex::encode(&digest[..8]),
+            key: *Key::from_slice(bytes),
+        })
+    }
+
+    /// Parse the base64 form used in the config and key files.
+    pub fn from_base64(text: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(text.trim())
+            .map_err(|e| Error::Key(format!("master key is not valid base64: {e}")))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Read a key file: base64 text, or exactly [`KEY_LEN`] raw bytes.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        match std::str::from_utf8(&bytes) {
+            Ok(text) if !text.trim().is_empty() && bytes.len() != KEY_LEN => {
+                Self::from_base64(text)
+            }
+            _ => Self::from_bytes(&bytes),
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; KEY_LEN];
+        rand::rng().fill_bytes(&mut bytes);
+        Self::from_bytes(&bytes).expect("key has the right length")
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.key)
+    }
+}
+
+/// The current master key and the retired ones still needed for reading.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    current: Option<MasterKey>,
+    old: Vec<MasterKey>,
+}
+
+impl Keyring {
+    pub fn new(current: Option<MasterKey>, old: Vec<MasterKey>) -> Self {
+        Self { current, old }
+    }
+
+    /// Keys from `FERRI_MASTER_KEY`, else `master_key`, else `master_key_file`,
+    /// plus `old_master_keys`.
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let current = match std::env::var(MASTER_KEY_ENV) {
+            Ok(v) if !v.trim().is_empty() => Some(MasterKey::from_base64(&v)?),
+            _ => match (&cfg.master_key, &cfg.master_key_file) {
+                (Some(k), _) => Some(MasterKey::from_base64(k)?),
+                (None, Some(path)) => {
+                    Some(MasterKey::from_file(Path::new(path)).map_err(|e| {
+                        Error::Key(format!("cannot read master key file {path}: {e}"))
+                    })?)
+                }
+                (None, None) => None,
+            },
+        };
+        let old = cfg
+            .old_master_keys
+            .iter()
+            .map(|k| MasterKey::from_base64(k))
+            .collect::<Result<_>>()?;
+        Ok(Self::new(current, old))
+    }
+
+    /// The key new rows are sealed under; `None` if none is configured.
+    pub fn current(&self) -> Option<&MasterKey> {
+        self.current.as_ref()
+    }
+
+    fn get(&self, id: &str) -> Result<&MasterKey> {
+        self.current
+            .iter()
+            .chain(&self.old)
+            .find(|k| k.id == id)
+            .ok_or_else(|| Error::Key(format!("master key {id} is not configured")))
+    }
+
+    /// A fresh data key, wrapped under the current master key.
+    pub fn new_envelope(&self) -> Result<Envelope> {
+        let master = self
+            .current
+            .as_ref()
+            .ok_or_else(|| Error::Key("no master key configured".to_string()))?;
+        let mut dek = Key::default();
+        rand::rng().fill_bytes(&mut dek);
+        let wrapped = seal(&master.key, &dek, master.id.as_bytes());
+        Ok(Envelope {
+            key_id: master.id.clone(),
+            wrapped_key: wrapped,
+            dek,
+        })
+    }
+
+    /// Unwrap a stored data key.
+    pub fn open_envelope(&self, key_id: &str, wrapped_key: &[u8]) -> Result<Envelope> {
+        let master = self.get(key_id)?;
+        let dek = open(&master.key, wrapped_key, key_id.as_bytes())?;
+        if dek.len() != KEY_LEN {
+            return Err(Error::Key(
+                "wrapped data key has the wrong length".to_string(),
+            ));
+        }
+        Ok(Envelope {
+            key_id: key_id.to_string(),
+            wrapped_key: wrapped_key.to_vec(),
+            dek: *Key::from_slice(&dek),
+        })
+    }
+
+    /// Wrap an existing data key under the current master key.
+    pub fn rewrap(&self, envelope: &Envelope) -> Result<Envelope> {
+        let master = self
+            .current
+            .as_ref()
+            .ok_or_else(|| Error::Key("no master key configured".to_string()))?;
+        Ok(Envelope {
+            key_id: master.id.clone(),
+            wrapped_key: seal(&master.key, &envelope.dek, master.id.as_bytes()),
+            dek: envelope.dek,
+        })
+    }
+}
+
+/// A row's data key, with the form it is stored in.
+pub struct Envelope {
+    pub key_id: String,
+    pub wrapped_key: Vec<u8>,
+    dek: Key,
+}
+
+impl fmt::Debug for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Envelope")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Envelope {
+    /// Encrypt a value. `aad` names what the value is (table, row, column) so
+    /// a ciphertext copied elsewhere fails to open.
+    pub fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        seal(&self.dek, plaintext, aad)
+    }
+
+    pub fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        open(&self.dek, sealed, aad)
+    }
+}
+
+/// `nonce || ciphertext`, with a random 192-bit nonce.
+fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut nonce = XNonce::default();
+    rand::rng().fill_bytes(&mut nonce);
+    let ciphertext = XChaCha20Poly1305::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("XChaCha20-Poly1305 accepts any message length used here");
+    let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    out
+}
+
+fn open(key: &Key, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::Key("ciphertext is truncated".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key)
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| Error::Key("decryption failed (wrong key or tampered data)".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(current: &MasterKey, old: &[&MasterKey]) -> Keyring {
+        Keyring::new(
+            Some(current.clone()),
+            old.iter().map(|&k| k.clone()).collect(),
+        )
+    }
+
+    #[test]
+    fn seal_open_round_trip() {
+        let keys = ring(&MasterKey::generate(), &[]);
+        let envelope = keys.new_envelope().unwrap();
+        let sealed = envelope.seal(b"hunter2", b"t:1:c");
+        assert_ne!(&sealed[NONCE_LEN..], b"hunter2");
+        assert_eq!(envelope.open(&sealed, b"t:1:c").unwrap(), b"hunter2");
+
+        let stored = keys
+            .open_envelope(&envelope.key_id, &envelope.wrapped_key)
+            .unwrap();
+        assert_eq!(stored.open(&sealed, b"t:1:c").unwrap(), b"hunter2");
+    }
+
+    #[test]
+    fn aad_mismatch_fails() {
+        let envelope = ring(&MasterKey::generate(), &[]).new_envelope().unwrap();
+        let sealed = envelope.seal(b"hunter2", b"t:1:c");
+        assert!(envelope.open(&sealed, b"t:2:c").is_err());
+        assert!(envelope.open(&sealed[..NONCE_LEN - 1], b"t:1:c").is_err());
+    }
+
+    #[test]
+    fn wrong_or_unknown_key_id_fails() {
+        let (a, b) = (MasterKey::generate(), MasterKey::generate());
+        let envelope = ring(&a, &[]).new_envelope().unwrap();
+
+        let Err(Error::Key(msg)) = ring(&b, &[]).open_envelope(a.id(), &envelope.wrapped_key)
+        else {
+            panic!("opened under an unknown key id");
+        };
+        assert!(msg.contains("not configured"), "{msg}");
+        // The id is bound to the wrapped key, so naming another key fails too.
+        assert!(
+            ring(&b, &[&a])
+                .open_envelope(b.id(), &envelope.wrapped_key)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rewrap_moves_to_the_current_key() {
+        let (old, new) = (MasterKey::generate(), MasterKey::generate());
+        let before = ring(&old, &[]).new_envelope().unwrap();
+        let sealed = before.seal(b"hunter2", b"aad");
+
+        let during = ring(&new, &[&old]);
+        let after = during
+            .rewrap(
+                &during
+                    .open_envelope(&before.key_id, &before.wrapped_key)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(after.key_id, new.id());
+
+        let reopened = ring(&new, &[])
+            .open_envelope(&after.key_id, &after.wrapped_key)
+            .unwrap();
+        assert_eq!(reopened.open(&sealed, b"aad").unwrap(), b"hunter2");
+    }
+
+    #[test]
+    fn key_ids_follow_the_key() {
+        let key = MasterKey::generate();
+        let parsed = MasterKey::from_base64(&key.to_base64()).unwrap();
+        assert_eq!(parsed.id(), key.id());
+        assert_ne!(MasterKey::generate().id(), key.id());
+        assert!(MasterKey::from_bytes(&[0; KEY_LEN - 1]).is_err());
+    }
+}
@@ -12,6 +12,59 @@ pub enum Error {
     TomlDe(#[from] toml::de::Error),
     #[error("TOML serialization error: {0}")]
     TomlSer(#[from] toml::ser::Error),
+    #[error("config error: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("invalid log filter: {0}")]
+    InvalidLogFilter(String),
+    #[error("invalid path: {0}")]
+    InvalidPath(String),
+    #[error("invalid file name: {0:?}")]
+    InvalidName(String),
+    // Paths stay out of the messages, which reach clients; only names are shown.
+    #[error("{} already exists", .0.file_name().unwrap_or_default().to_string_lossy())]
+    AlreadyExists(std::path::PathBuf),
+    #[error("{} is not empty", .0.file_name().unwrap_or_default().to_string_lossy())]
+    NotEmpty(std::path::PathBuf),
+    #[error("path leads outside the shared folder")]
+    OutsideRoot(std::path::PathBuf),
+    #[error("invalid username or password")]
+    AuthFailed,
+    #[error("account is disabled")]
+    AccountDisabled,
+    #[error("account has expired")]
+    AccountExpired,
+    #[error("password changes are disabled for this account")]
+    PasswordChangeDisabled,
+    #[error("invalid username: {0:?}")]
+    InvalidUsername(String),
+    #[error("username {0:?} is taken")]
+    UsernameTaken(String),
+    #[error("{0:?} is not a group")]
+    NotAGroup(String),
+    #[error("adding {member:?} to {group:?} would make a group contain itself")]
+    MembershipCycle { member: String, group: String },
+    #[error("invalid VFS node: {0}")]
+    InvalidNode(String),
+    #[error("invalid SRP {0}")]
+    InvalidSrp(String),
+    #[error("identity provider error: {0}")]
+    Oidc(String),
+    #[error("invalid ID token: {0}")]
+    InvalidToken(String),
+    #[error("sign-in request expired, was already used, or was started elsewhere")]
+    OidcState,
+    #[error("no account is linked to this identity")]
+    NotLinked,
+    #[error("this identity is linked to another account")]
+    IdentityInUse,
+    #[error("key error: {0}")]
+    Key(String),
+    #[error("invalid network mask: {0}")]
+    InvalidNetMask(String),
+    #[error("login is not allowed from this address")]
+    NetDenied,
+    #[error("upload exceeds the {limit} byte limit")]
+    TooLarge { limit: u64 },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,6 +1,17 @@
+pub mod account;
+pub mod archive;
+pub mod audit;
 pub mod config;
 pub mod db;
+pub mod envelope;
 pub mod error;
 pub mod logger;
+pub mod net;
+pub mod oidc;
+pub mod paths;
+pub mod session;
+pub mod srp;
+pub mod upload;
 pub mod util;
+pub mod vfs;
 pub mod walkdir;
@@ -1,5 +1,5 @@
 use crate::config::{Config, LogRotation};
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 use std::io::{self, IsTerminal};
 use tracing::error;
@@ -8,22 +8,43 @@ use tracing_appender::{
     rolling::{self, Rotation},
 };
 use tracing_subscriber::{
-    EnvFilter, Registry, filter::LevelFilter, fmt, layer::SubscriberExt, prelude::*,
+    EnvFilter, Layer, Registry, filter::LevelFilter, fmt, layer::SubscriberExt, prelude::*, reload,
 };
 
 /// Guards for non-blocking writers so they flush on shutdown.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct LoggingGuards {
     pub file_guard: Option<WorkerGuard>,
     pub error_file_guard: Option<WorkerGuard>,
 }
 
-impl Default for LoggingGuards {
-    fn default() -> Self {
-        Self {
-            file_guard: None,
-            error_file_guard: None,
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Changes the `log_level` filter of the console and app log while running.
+/// The error log always takes every error.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    handles: Vec<reload::Handle<EnvFilter, Registry>>,
+}
+
+impl LogFilter {
+    /// Wrap `filter` so that [`LogFilter::set`] can replace it later.
+    fn layer(&mut self, filter: EnvFilter) -> reload::Layer<EnvFilter, Registry> {
+        let (layer, handle) = reload::Layer::new(filter);
+        self.handles.push(handle);
+        layer
+    }
+
+    /// Apply `directives` (a `log_level` value) from now on.
+    pub fn set(&self, directives: &str) -> Result<()> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| Error::InvalidLogFilter(format!("{directives:?}: {e}")))?;
+        for handle in &self.handles {
+            handle
+                .reload(filter.clone())
+                .map_err(|e| Error::InvalidLogFilter(e.to_string()))?;
         }
+        Ok(())
     }
 }
 
@@ -34,8 +55,9 @@ impl Default for LoggingGuards {
 /// - Optional rolling app log at `log_path`
 /// - Optional rolling error-only log at `log_error_path`
 ///
-/// Returns guards that must be kept alive to ensure logs are flushed.
-pub fn init_logger(cfg: &Config) -> Result<LoggingGuards> {
+/// Returns guards that must be kept alive to ensure logs are flushed, and the
+/// handle to change the filter.
+pub fn init_logger(cfg: &Config) -> Result<(LoggingGuards, LogFilter)> {
     // Ensure all directories exist per config.
     cfg.ensure_dirs()?;
 
@@ -43,12 +65,19 @@ pub fn init_logger(cfg: &Config) -> Result<LoggingGuards> {
     let env_filter =
         EnvFilter::try_new(cfg.log_level.clone()).unwrap_or_else(|_| EnvFilter::new("info"));
     let use_ansi = io::stdout().is_terminal();
+    let mut filter = LogFilter::default();
+    // Boxed, so that every layer sits directly on the registry and the
+    // reload handles share one type.
+    let mut layers: Vec<BoxedLayer> = Vec::new();
 
     // Console layer (human-friendly formatting to stdout).
-    let console_layer = fmt::layer()
-        .with_target(true)
-        .with_ansi(use_ansi)
-        .with_filter(env_filter.clone());
+    layers.push(
+        fmt::layer()
+            .with_target(true)
+            .with_ansi(use_ansi)
+            .with_filter(filter.layer(env_filter.clone()))
+            .boxed(),
+    );
 
     let rotation = match cfg.log_rotation {
         LogRotation::Daily => Rotation::DAILY,
@@ -56,76 +85,67 @@ pub fn init_logger(cfg: &Config) -> Result<LoggingGuards> {
     };
 
     // Optional: app log file layer
-    let (file_layer_opt, file_guard_opt): (Option<_>, Option<WorkerGuard>) =
-        if let Some(dir) = &cfg.log_path {
-            let appender = rolling::Builder::new()
-                .rotation(rotation.clone())
-                .filename_prefix("ferri")
-                .filename_suffix("log")
-                .build(dir)
-                .map_err(|e| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("failed to create error log appender: {e}"),
-                    )
-                })?;
-
-            let (nb, guard) = tracing_appender::non_blocking(appender);
-            let layer = fmt::layer()
+    let file_guard_opt: Option<WorkerGuard> = if let Some(dir) = &cfg.log_path {
+        let appender = rolling::Builder::new()
+            .rotation(rotation.clone())
+            .filename_prefix("ferri")
+            .filename_suffix("log")
+            .build(dir)
+            .map_err(|e| io::Error::other(format!("failed to create log appender: {e}")))?;
+
+        let (nb, guard) = tracing_appender::non_blocking(appender);
+        layers.push(
+            fmt::layer()
                 .with_ansi(false)
                 .with_target(true)
                 .with_writer(nb)
-                .with_filter(env_filter.clone());
-            (Some(layer), Some(guard))
-        } else {
-            (None, None)
-        };
+                .with_filter(filter.layer(env_filter))
+                .boxed(),
+        );
+        Some(guard)
+    } else {
+        None
+    };
 
     // Optional: error-only log file layer
-    let (error_layer_opt, error_guard_opt): (Option<_>, Option<WorkerGuard>) =
-        if let Some(dir) = &cfg.log_error_path {
-            let appender = rolling::Builder::new()
-                .rotation(rotation)
-                .filename_prefix("ferri-error")
-                .filename_suffix("log")
-                .build(dir)
-                .map_err(|e| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("failed to create error log appender: {e}"),
-                    )
-                })?;
-
-            let (nb, guard) = tracing_appender::non_blocking(appender);
-            let layer = fmt::layer()
+    let error_guard_opt: Option<WorkerGuard> = if let Some(dir) = &cfg.log_error_path {
+        let appender = rolling::Builder::new()
+            .rotation(rotation)
+            .filename_prefix("ferri-error")
+            .filename_suffix("log")
+            .build(dir)
+            .map_err(|e| io::Error::other(format!("failed to create error log appender: {e}")))?;
+
+        let (nb, guard) = tracing_appender::non_blocking(appender);
+        layers.push(
+            fmt::layer()
                 .with_ansi(false)
                 .with_target(true)
                 .with_writer(nb)
-                .with_filter(LevelFilter::ERROR);
-            (Some(layer), Some(guard))
-        } else {
-            (None, None)
-        };
-
-    // Compose subscriber with optional layers.
-    let subscriber = Registry::default()
-        .with(console_layer)
-        .with(file_layer_opt)
-        .with(error_layer_opt);
+                .with_filter(LevelFilter::ERROR)
+                .boxed(),
+        );
+        Some(guard)
+    } else {
+        None
+    };
+
+    let subscriber = Registry::default().with(layers);
 
     // Install globally. Use try_init so we return an io::Error instead of panicking
     // if someone else already initialized a subscriber.
     subscriber
         .try_init()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("failed to init logger: {e}")))?;
+        .map_err(|e| io::Error::other(format!("failed to init logger: {e}")))?;
 
     // Panic hook to route panics through tracing (to reach error log).
     install_panic_hook();
 
-    Ok(LoggingGuards {
+    let guards = LoggingGuards {
         file_guard: file_guard_opt,
         error_file_guard: error_guard_opt,
-    })
+    };
+    Ok((guards, filter))
 }
 
 /// Install a panic hook that logs panics via `tracing::error!`.
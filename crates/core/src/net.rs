@@ -0,0 +1,485 @@
+//! Network masks, as used by `accounts.allow_net`, the global allow/deny lists,
+//! node restrictions and `trusted_proxies`.
+//!
+//! A mask is a list of entries separated by `,`, `;`, `|` or whitespace. Each
+//! entry is an address (`10.0.0.1`, `::1`), a CIDR block (`192.168.0.0/16`,
+//! `fd00::/8`), a range (`10.0.0.10-10.0.0.20`) or a wildcard on the textual
+//! form (`192.168.*`, `10.0.0.?`, `*`). Entries starting with `!` exclude.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NetRule {
+    /// Address and prefix length, in the address's own family.
+    Cidr(IpAddr, u8),
+    Range(IpAddr, IpAddr),
+    Wildcard(String),
+}
+
+impl NetRule {
+    fn parse(s: &str) -> Result<Self> {
+        let bad = || Error::InvalidNetMask(s.to_string());
+        if s.contains(['*', '?']) {
+            if !s
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || matches!(c, '.' | ':' | '*' | '?'))
+            {
+                return Err(bad());
+            }
+            return Ok(NetRule::Wildcard(s.to_ascii_lowercase()));
+        }
+        if let Some((addr, bits)) = s.split_once('/') {
+            let addr = parse_ip(addr).ok_or_else(bad)?;
+            let bits: u8 = bits.parse().map_err(|_| bad())?;
+            if bits > max_prefix(addr) {
+                return Err(bad());
+            }
+            return Ok(NetRule::Cidr(addr, bits));
+        }
+        if let Some((from, to)) = s.split_once('-') {
+            let (from, to) = (
+                parse_ip(from).ok_or_else(bad)?,
+                parse_ip(to).ok_or_else(bad)?,
+            );
+            if from.is_ipv4() != to.is_ipv4() || to_bits(from) > to_bits(to) {
+                return Err(bad());
+            }
+            return Ok(NetRule::Range(from, to));
+        }
+        let addr = parse_ip(s).ok_or_else(bad)?;
+        Ok(NetRule::Cidr(addr, max_prefix(addr)))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match self {
+            NetRule::Cidr(net, bits) => {
+                if net.is_ipv4() != ip.is_ipv4() {
+                    return false;
+                }
+                let shift = u32::from(max_prefix(ip) - bits);
+                let mask = u128::MAX.checked_shl(shift).unwrap_or(0);
+                to_bits(*net) & mask == to_bits(ip) & mask
+            }
+            NetRule::Range(from, to) => {
+                from.is_ipv4() == ip.is_ipv4()
+                    && (to_bits(*from)..=to_bits(*to)).contains(&to_bits(ip))
+            }
+            NetRule::Wildcard(pattern) => wildcard_match(pattern, &ip.to_string()),
+        }
+    }
+}
+
+/// `::ffff:a.b.c.d` is matched as the IPv4 address it carries.
+fn canonical(ip: IpAddr) -> IpAddr {
+    ip.to_canonical()
+}
+
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_start_matches('[').trim_end_matches(']');
+    s.parse().ok().map(canonical)
+}
+
+fn max_prefix(ip: IpAddr) -> u8 {
+    if ip.is_ipv4() { 32 } else { 128 }
+}
+
+fn to_bits(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4).into(),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+/// `*` matches any run of characters, `?` exactly one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some(b'*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == b'?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    pi = bp + 1;
+                    ti = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == b'*')
+}
+
+/// A parsed network mask.
+///
+/// An address matches when it is covered by an including entry and by no
+/// excluding one; a mask made only of exclusions includes everything else.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NetMask {
+    source: String,
+    include: Vec<NetRule>,
+    exclude: Vec<NetRule>,
+}
+
+impl NetMask {
+    /// Join several masks (e.g. config list items) into one.
+    pub fn from_list<S: AsRef<str>>(items: &[S]) -> Result<Self> {
+        let joined: Vec<&str> = items.iter().map(AsRef::as_ref).collect();
+        joined.join(",").parse()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        let included = self.include.is_empty() && !self.exclude.is_empty()
+            || self.include.iter().any(|r| r.contains(ip));
+        included && !self.exclude.iter().any(|r| r.contains(ip))
+    }
+}
+
+impl FromStr for NetMask {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut mask = NetMask {
+            source: s.trim().to_string(),
+            ..Default::default()
+        };
+        for entry in s
+            .split([',', ';', '|', ' ', '\t', '\n'])
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            match entry.strip_prefix('!') {
+                Some(rest) => mask.exclude.push(NetRule::parse(rest.trim())?),
+                None => mask.include.push(NetRule::parse(entry)?),
+            }
+        }
+        Ok(mask)
+    }
+}
+
+impl TryFrom<String> for NetMask {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<NetMask> for String {
+    fn from(mask: NetMask) -> Self {
+        mask.source
+    }
+}
+
+impl fmt::Display for NetMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Global allow and deny lists.
+#[derive(Debug, Clone, Default)]
+pub struct AccessList {
+    /// When set, only these addresses get in.
+    pub allow: Option<NetMask>,
+    pub deny: Option<NetMask>,
+}
+
+impl AccessList {
+    pub fn new<S: AsRef<str>>(allow: &[S], deny: &[S]) -> Result<Self> {
+        let parse = |items: &[S]| {
+            (!items.is_empty())
+                .then(|| NetMask::from_list(items))
+                .transpose()
+        };
+        Ok(Self {
+            allow: parse(allow)?,
+            deny: parse(deny)?,
+        })
+    }
+
+    /// Whether `ip` passes; an unknown address only passes lists that do not
+    /// require an allow match.
+    pub fn permits(&self, ip: Option<IpAddr>) -> bool {
+        match ip {
+            Some(ip) => {
+                self.allow.as_ref().is_none_or(|m| m.contains(ip))
+                    && !self.deny.as_ref().is_some_and(|m| m.contains(ip))
+            }
+            None => self.allow.is_none(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_none() && self.deny.is_none()
+    }
+}
+
+/// The network settings of the config, parsed.
+#[derive(Debug, Clone, Default)]
+pub struct NetPolicy {
+    pub trusted_proxies: NetMask,
+    pub access: AccessList,
+}
+
+impl NetPolicy {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        Ok(Self {
+            trusted_proxies: NetMask::from_list(&cfg.trusted_proxies)?,
+            access: AccessList::new(&cfg.allow_net, &cfg.deny_net)?,
+        })
+    }
+
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        !self.trusted_proxies.is_empty() && self.trusted_proxies.contains(ip)
+    }
+
+    /// The client's address: the peer, or when the peer is a trusted proxy,
+    /// the nearest untrusted hop in `X-Forwarded-For`.
+    ///
+    /// `forwarded_for` holds the header values in order; proxies append, so
+    /// they are walked from the right.
+    pub fn client_ip<'a>(
+        &self,
+        peer: IpAddr,
+        forwarded_for: impl DoubleEndedIterator<Item = &'a str>,
+    ) -> IpAddr {
+        let mut client = canonical(peer);
+        if !self.is_trusted_proxy(client) {
+            return client;
+        }
+        let hops = forwarded_for.rev().flat_map(|v| v.rsplit(','));
+        for hop in hops {
+            // A hop that does not parse cannot be vouched for; stop at the last good one.
+            let Some(ip) = parse_ip(hop) else { break };
+            client = ip;
+            if !self.is_trusted_proxy(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn mask(s: &str) -> NetMask {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_each_entry_kind() {
+        assert_eq!(
+            NetRule::parse("10.0.0.1").unwrap(),
+            NetRule::Cidr(ip("10.0.0.1"), 32)
+        );
+        assert_eq!(
+            NetRule::parse("[::1]").unwrap(),
+            NetRule::Cidr(ip("::1"), 128)
+        );
+        assert_eq!(
+            NetRule::parse("fd00::/8").unwrap(),
+            NetRule::Cidr(ip("fd00::"), 8)
+        );
+        assert_eq!(
+            NetRule::parse("::ffff:10.0.0.0/8").unwrap(),
+            NetRule::Cidr(ip("10.0.0.0"), 8)
+        );
+        assert_eq!(
+            NetRule::parse("10.0.0.10-10.0.0.20").unwrap(),
+            NetRule::Range(ip("10.0.0.10"), ip("10.0.0.20"))
+        );
+        assert_eq!(
+            NetRule::parse("FE80::*").unwrap(),
+            NetRule::Wildcard("fe80::*".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_entries() {
+        for bad in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/x",
+            "10.0.0.20-10.0.0.10",
+            "10.0.0.1-::2",
+            "10.0.0.*/8",
+            "host*",
+            "example.com",
+            "10.0.0.256",
+        ] {
+            assert!(bad.parse::<NetMask>().is_err(), "{bad} accepted");
+        }
+    }
+
+    #[test]
+    fn ipv4_prefixes() {
+        let m = mask("192.168.0.0/16, 10.1.2.3");
+        assert!(m.contains(ip("192.168.40.1")));
+        assert!(!m.contains(ip("192.169.0.1")));
+        assert!(m.contains(ip("10.1.2.3")));
+        assert!(!m.contains(ip("10.1.2.4")));
+        assert!(mask("0.0.0.0/0").contains(ip("203.0.113.9")));
+        assert!(!mask("0.0.0.0/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn ipv6_prefixes() {
+        let m = mask("fd00::/8 2001:db8::/32");
+        assert!(m.contains(ip("fd12:3456::1")));
+        assert!(m.contains(ip("2001:db8:ffff::1")));
+        assert!(!m.contains(ip("2001:db9::1")));
+        assert!(!m.contains(ip("10.0.0.1")));
+        assert!(mask("::/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn mapped_addresses_match_as_ipv4() {
+        let m = mask("10.0.0.0/8");
+        assert!(m.contains(ip("::ffff:10.9.8.7")));
+        assert!(!m.contains(ip("::ffff:11.0.0.1")));
+        assert!(mask("::ffff:10.0.0.1").contains(ip("10.0.0.1")));
+        assert!(mask("10.0.0.*").contains(ip("::ffff:10.0.0.5")));
+    }
+
+    #[test]
+    fn ranges_and_wildcards() {
+        let m = mask("10.0.0.10-10.0.0.20 | 192.168.*; 172.16.0.?");
+        assert!(m.contains(ip("10.0.0.10")));
+        assert!(m.contains(ip("10.0.0.20")));
+        assert!(!m.contains(ip("10.0.0.21")));
+        assert!(m.contains(ip("192.168.1.200")));
+        assert!(m.contains(ip("172.16.0.7")));
+        assert!(!m.contains(ip("172.16.0.17")));
+        assert!(mask("*").contains(ip("::1")));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+        assert!(!wildcard_match("a*b?c", "abc"));
+    }
+
+    #[test]
+    fn exclusions() {
+        let m = mask("10.0.0.0/8, !10.1.0.0/16");
+        assert!(m.contains(ip("10.2.0.1")));
+        assert!(!m.contains(ip("10.1.0.1")));
+
+        // Only exclusions: everything else is in.
+        let m = mask("!10.0.0.0/8 !192.168.0.1");
+        assert!(m.contains(ip("8.8.8.8")));
+        assert!(m.contains(ip("::1")));
+        assert!(!m.contains(ip("10.3.3.3")));
+        assert!(!m.contains(ip("192.168.0.1")));
+
+        // And an empty mask holds nothing.
+        assert!(mask("").is_empty());
+        assert!(!mask("").contains(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn keeps_its_source_text() {
+        let m = NetMask::from_list(&["10.0.0.0/8", "!10.1.0.0/16"]).unwrap();
+        assert_eq!(m.to_string(), "10.0.0.0/8,!10.1.0.0/16");
+        assert_eq!(String::from(m.clone()).parse::<NetMask>().unwrap(), m);
+    }
+
+    #[test]
+    fn access_lists() {
+        let list = AccessList::new(&["10.0.0.0/8"], &["10.0.0.66"]).unwrap();
+        assert!(list.permits(Some(ip("10.0.0.1"))));
+        assert!(!list.permits(Some(ip("10.0.0.66"))));
+        assert!(!list.permits(Some(ip("192.168.0.1"))));
+        assert!(!list.permits(None));
+
+        let deny_only = AccessList::new(&[], &["10.0.0.66"]).unwrap();
+        assert!(deny_only.permits(None));
+        assert!(deny_only.permits(Some(ip("192.168.0.1"))));
+    }
+
+    fn policy(trusted: &str) -> NetPolicy {
+        NetPolicy {
+            trusted_proxies: mask(trusted),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn client_ip_without_trusted_proxies() {
+        let p = policy("");
+        assert!(!p.is_trusted_proxy(ip("127.0.0.1")));
+        assert_eq!(
+            p.client_ip(ip("203.0.113.9"), ["1.2.3.4"].into_iter()),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            p.client_ip(ip("::ffff:203.0.113.9"), [].into_iter()),
+            ip("203.0.113.9")
+        );
+    }
+
+    #[test]
+    fn client_ip_behind_trusted_proxies() {
+        let p = policy("127.0.0.1, 10.0.0.0/8");
+        let peer = ip("127.0.0.1");
+        assert_eq!(
+            p.client_ip(peer, ["198.51.100.7"].into_iter()),
+            ip("198.51.100.7")
+        );
+        // Walked from the right, across header lines, past trusted hops.
+        assert_eq!(
+            p.client_ip(peer, ["198.51.100.7, 10.0.0.2", "10.0.0.3"].into_iter()),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            p.client_ip(peer, ["[2001:db8::7]"].into_iter()),
+            ip("2001:db8::7")
+        );
+        // No header: the proxy itself.
+        assert_eq!(p.client_ip(peer, [].into_iter()), peer);
+        // Garbage stops the walk at the last hop that parsed.
+        assert_eq!(
+            p.client_ip(peer, ["198.51.100.7, junk, 10.0.0.2"].into_iter()),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn spoofed_left_hops_are_ignored() {
+        let p = policy("127.0.0.1");
+        // The client sent its own X-Forwarded-For; the proxy appended the real peer.
+        assert_eq!(
+            p.client_ip(ip("127.0.0.1"), ["10.0.0.1, 203.0.113.9"].into_iter()),
+            ip("203.0.113.9")
+        );
+        // The same header straight from an untrusted peer is not read at all.
+        assert_eq!(
+            p.client_ip(ip("203.0.113.9"), ["10.0.0.1"].into_iter()),
+            ip("203.0.113.9")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Where ferri keeps its config, database and logs.
+//!
+//! By default these follow the XDG base directories (`~/.config/ferri`,
+//! `~/.local/share/ferri`, `~/.local/state/ferri`), or their platform
+//! equivalents, falling back to `/etc/ferri`, `/var/lib/ferri` and
+//! `/var/log/ferri` when there is no home directory. Portable mode keeps
+//! everything beside the executable, as earlier versions did.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::util::get_running_path;
+
+const APP: &str = "ferri";
+const DB_FILE: &str = "ferri.db";
+const CONFIG_FILE: &str = "config.toml";
+
+/// Config file read before the user's, whatever the mode.
+pub const SYSTEM_CONFIG: &str = "/etc/ferri/config.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs {
+    /// Holds `config.toml`.
+    pub config: PathBuf,
+    /// Holds the database.
+    pub data: PathBuf,
+    /// Holds the logs; errors go to `error/` below it.
+    pub logs: PathBuf,
+    /// Everything is beside the executable.
+    pub portable: bool,
+}
+
+impl Dirs {
+    /// The per-user locations, or the system ones without a home directory.
+    pub fn standard() -> Self {
+        let under = |base: Option<PathBuf>, fallback: &str| {
+            base.map(|d| d.join(APP))
+                .unwrap_or_else(|| PathBuf::from(fallback))
+        };
+        Self {
+            config: under(dirs::config_dir(), "/etc/ferri"),
+            data: under(dirs::data_dir(), "/var/lib/ferri"),
+            logs: dirs::state_dir()
+                .or_else(dirs::data_dir)
+                .map(|d| d.join(APP).join("logs"))
+                .unwrap_or_else(|| PathBuf::from("/var/log/ferri")),
+            portable: false,
+        }
+    }
+
+    /// Everything beside the executable.
+    pub fn portable() -> Result<Self> {
+        let dir = get_running_path()?;
+        Ok(Self {
+            config: dir.clone(),
+            logs: dir.join("logs"),
+            data: dir,
+            portable: true,
+        })
+    }
+
+    /// [`Dirs::portable`] or [`Dirs::standard`], with the database and logs
+    /// moved under `data_dir` when given.
+    pub fn resolve(portable: bool, data_dir: Option<&Path>) -> Result<Self> {
+        let mut dirs = if portable {
+            Self::portable()?
+        } else {
+            Self::standard()
+        };
+        if let Some(dir) = data_dir {
+            dirs = dirs.with_data_dir(dir);
+        }
+        Ok(dirs)
+    }
+
+    /// The same config directory, with the database and logs under `dir`.
+    pub fn with_data_dir(self, dir: &Path) -> Self {
+        Self {
+            data: dir.to_path_buf(),
+            logs: dir.join("logs"),
+            ..self
+        }
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config.join(CONFIG_FILE)
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.data.join(DB_FILE)
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.logs.clone()
+    }
+
+    pub fn log_error_path(&self) -> PathBuf {
+        self.logs.join("error")
+    }
+
+    /// Move the database and config from beside the executable, where earlier
+    /// versions kept them, into these directories. Does nothing once the
+    /// database is in place, in portable mode, or when there is nothing to
+    /// move. Returns what was moved, as (from, to) pairs.
+    pub fn migrate_legacy(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        if self.portable || self.db_path().exists() {
+            return Ok(Vec::new());
+        }
+        let Ok(old) = Self::portable() else {
+            return Ok(Vec::new());
+        };
+        if old.data == self.data {
+            return Ok(Vec::new());
+        }
+        let mut moved = Vec::new();
+
+        if old.db_path().exists() {
+            fs::create_dir_all(&self.data)?;
+            // The write-ahead log holds committed data not yet in the main file.
+            for suffix in ["", "-wal", "-shm"] {
+                let from = PathBuf::from(format!("{}{suffix}", old.db_path().display()));
+                let to = PathBuf::from(format!("{}{suffix}", self.db_path().display()));
+                if from.exists() {
+                    move_file(&from, &to)?;
+                    moved.push((from, to));
+                }
+            }
+        }
+
+        let (from, to) = (old.config_file(), self.config_file());
+        if from.exists() && !to.exists() {
+            let content = fs::read_to_string(&from)?;
+            let mut table: toml::Table = toml::from_str(&content)?;
+            // The old first run wrote out its defaults, which point back
+            // beside the executable; drop those left unchanged.
+            let defaults = [
+                ("db_path", old.db_path()),
+                ("log_path", old.log_path()),
+                ("log_error_path", old.log_error_path()),
+            ];
+            for (key, default) in defaults {
+                if table.get(key).and_then(|v| v.as_str()) == Some(&*default.to_string_lossy()) {
+                    table.remove(key);
+                }
+            }
+            fs::create_dir_all(&self.config)?;
+            fs::write(&to, toml::to_string_pretty(&table)?)?;
+            fs::remove_file(&from)?;
+            moved.push((from, to));
+        }
+
+        let (from, to) = (old.log_path(), self.log_path());
+        if from.is_dir() && !to.exists() {
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Old logs are not worth copying across file systems.
+            if fs::rename(&from, &to).is_ok() {
+                moved.push((from, to));
+            }
+        }
+        Ok(moved)
+    }
+}
+
+/// Rename, or copy and delete when `to` is on another file system.
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).map_err(|e| {
+        Error::Io(io::Error::new(
+            e.kind(),
+            format!("cannot move {} to {}: {e}", from.display(), to.display()),
+        ))
+    })?;
+    fs::remove_file(from)?;
+    Ok(())
+}
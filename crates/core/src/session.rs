@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, Pool, Sqlite};
+
+use crate::db::Timestamp;
+use crate::error::Result;
+
+const TOKEN_LEN: usize = 32;
+
+/// Where a session was opened from, recorded for the user's session list.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// `sessions.id_hash` for a token: base64(SHA-256(token)).
+///
+/// Only the hash is stored, so a leaked database does not leak live sessions.
+pub fn hash_token(token: &str) -> String {
+    STANDARD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Open a session for `account_id` and return its token, which is only ever
+/// given to the client.
+pub async fn create(
+    pool: &Pool<Sqlite>,
+    account_id: i64,
+    max_age: u64,
+    client: &ClientInfo,
+) -> Result<String> {
+    let mut bytes = [0u8; TOKEN_LEN];
+    rand::rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+
+    let (id_hash, now) = (hash_token(&token), Timestamp::now());
+    let expires_at = now + Duration::from_secs(max_age);
+    sqlx::query!(
+        "INSERT INTO sessions (id_hash, account_id, created_at, last_seen_at, expires_at, ip, user_agent)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        id_hash,
+        account_id,
+        now,
+        now,
+        expires_at,
+        client.ip,
+        client.user_agent
+    )
+    .execute(pool)
+    .await?;
+    Ok(token)
+}
+
+/// A row of `sessions`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, serde::Serialize)]
+pub struct SessionRow {
+    pub id_hash: String,
+    pub account_id: i64,
+    pub created_at: Timestamp,
+    pub last_seen_at: Timestamp,
+    pub expires_at: Option<Timestamp>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SessionRow {
+    /// Past its absolute expiry, or idle for longer than `idle_timeout` seconds
+    /// since `last_seen` (which may be more recent than the stored column).
+    pub fn is_expired(&self, now: Timestamp, last_seen: Timestamp, idle_timeout: u64) -> bool {
+        self.expires_at.is_some_and(|e| now >= e)
+            || now >= last_seen + Duration::from_secs(idle_timeout)
+    }
+}
+
+pub async fn find(pool: &Pool<Sqlite>, id_hash: &str) -> Result<Option<SessionRow>> {
+    Ok(sqlx::query_as!(
+        SessionRow,
+        r#"SELECT id_hash AS "id_hash!", account_id, created_at AS "created_at: Timestamp",
+                  last_seen_at AS "last_seen_at: Timestamp", expires_at AS "expires_at: Timestamp",
+                  ip, user_agent
+           FROM sessions WHERE id_hash = ?"#,
+        id_hash
+    )
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Sessions of an account, most recently used first.
+pub async fn list(pool: &Pool<Sqlite>, account_id: i64) -> Result<Vec<SessionRow>> {
+    Ok(sqlx::query_as!(
+        SessionRow,
+        r#"SELECT id_hash AS "id_hash!", account_id, created_at AS "created_at: Timestamp",
+                  last_seen_at AS "last_seen_at: Timestamp", expires_at AS "expires_at: Timestamp",
+                  ip, user_agent
+           FROM sessions WHERE account_id = ? ORDER BY last_seen_at DESC"#,
+        account_id
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn delete(pool: &Pool<Sqlite>, id_hash: &str) -> Result<()> {
+    sqlx::query!("DELETE FROM sessions WHERE id_hash = ?", id_hash)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Delete some of an account's sessions; ids belonging to others are ignored.
+pub async fn revoke(pool: &Pool<Sqlite>, account_id: i64, ids: &[String]) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let mut revoked = 0;
+    for id in ids {
+        revoked += sqlx::query!(
+            "DELETE FROM sessions WHERE id_hash = ? AND account_id = ?",
+            id,
+            account_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    tx.commit().await?;
+    Ok(revoked)
+}
+
+/// Delete every session of an account except `keep`.
+pub async fn revoke_others(pool: &Pool<Sqlite>, account_id: i64, keep: &str) -> Result<u64> {
+    Ok(sqlx::query!(
+        "DELETE FROM sessions WHERE account_id = ? AND id_hash <> ?",
+        account_id,
+        keep
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+/// Delete every session of an account.
+pub async fn revoke_all(pool: &Pool<Sqlite>, account_id: i64) -> Result<u64> {
+    Ok(
+        sqlx::query!("DELETE FROM sessions WHERE account_id = ?", account_id)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Write batched activity: `(id_hash, last seen)` pairs, in one transaction.
+pub async fn touch_many(pool: &Pool<Sqlite>, seen: &[(String, Timestamp)]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (id, at) in seen {
+        sqlx::query!(
+            "UPDATE sessions SET last_seen_at = max(last_seen_at, ?) WHERE id_hash = ?",
+            at,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Delete sessions past their absolute expiry or idle for `idle_timeout` seconds.
+pub async fn purge_expired(pool: &Pool<Sqlite>, idle_timeout: u64) -> Result<u64> {
+    let now = Timestamp::now();
+    let idle_since = now - Duration::from_secs(idle_timeout);
+    Ok(sqlx::query!(
+        "DELETE FROM sessions WHERE (expires_at IS NOT NULL AND expires_at <= ?) OR last_seen_at <= ?",
+        now,
+        idle_since
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
@@ -0,0 +1,468 @@
+use std::marker::PhantomData;
+
+use num_bigint::BigUint;
+use rand::RngCore;
+use sha2::Sha256;
+use sha2::digest::Digest;
+
+use crate::error::{Error, Result};
+
+/// Scheme tag stored in front of `accounts.srp` values.
+const SCHEME: &str = "srp6a-sha256-2048";
+const SALT_LEN: usize = 16;
+/// Size of the private ephemerals `a` and `b`.
+const SECRET_LEN: usize = 32;
+
+/// RFC 5054 appendix A, 1024-bit group. Only used by the test vectors.
+const N_1024: &str = "EEAF0AB9ADB38DD69C33F80AFA8FC5E86072618775FF3C0B9EA2314C9C256576D674DF74\
+96EA81D3383B4813D692C6E0E0D5D8E250B98BE48E495C1D6089DAD15DC7D7B46154D6B6CE8EF4AD69B15D4982559B29\
+7BCF1885C529F566660E57EC68EDBC3C05726CC02FD4CBF4976EAA9AFD5138FE8376435B9FC61D2FC0EB06E3";
+
+/// RFC 5054 appendix A, 2048-bit group.
+const N_2048: &str = "AC6BDB41324A9A9BF166DE5E1389582FAF72B6651987EE07FC3192943DB56050A37329CB\
+B4A099ED8193E0757767A13DD52312AB4B03310DCD7F48A9DA04FD50E8083969EDB767B0CF6095179A163AB3661A05FB\
+D5FAAAE82918A9962F0B93B855F97993EC975EEAA80D740ADBF4FF747359D041D5C33EA71D281E446B14773BCA97B43A\
+23FB801676BD207A436C6481F1D2B9078717461A5B9D32E688F87748544523B524B0D57D5EA77A2775D2ECFA032CFBDB\
+F52FB3786160279004E57AE6AF874E7303CE53299CCC041C7BC308D82A5698F3A8D0C38271AE35F8E9DBFBB694B5C803\
+D89F7AE435DE236D525F54759B65E372FCD68EF20FA7111F9E4AFF73";
+
+/// A safe prime `N` and generator `g`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrpGroup {
+    pub n: BigUint,
+    pub g: BigUint,
+}
+
+impl SrpGroup {
+    pub fn rfc5054_1024() -> Self {
+        Self::from_hex(N_1024, 2)
+    }
+
+    pub fn rfc5054_2048() -> Self {
+        Self::from_hex(N_2048, 2)
+    }
+
+    fn from_hex(n: &str, g: u32) -> Self {
+        Self {
+            n: BigUint::parse_bytes(n.as_bytes(), 16).expect("valid group prime"),
+            g: BigUint::from(g),
+        }
+    }
+
+    /// Length of `N` in bytes.
+    fn len(&self) -> usize {
+        self.n.to_bytes_be().len()
+    }
+
+    /// Big-endian bytes left-padded to the length of `N` (`PAD()` in RFC 5054).
+    fn pad(&self, x: &BigUint) -> Vec<u8> {
+        let bytes = x.to_bytes_be();
+        let mut out = vec![0; self.len().saturating_sub(bytes.len())];
+        out.extend_from_slice(&bytes);
+        out
+    }
+}
+
+/// SRP-6a computations (RFC 5054) for a group and hash function.
+#[derive(Debug, Clone)]
+pub struct Srp<D> {
+    pub group: SrpGroup,
+    _digest: PhantomData<D>,
+}
+
+impl<D: Digest> Srp<D> {
+    pub fn new(group: SrpGroup) -> Self {
+        Self {
+            group,
+            _digest: PhantomData,
+        }
+    }
+
+    fn hash(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut h = D::new();
+        for p in parts {
+            h.update(p);
+        }
+        h.finalize().to_vec()
+    }
+
+    fn hash_int(&self, parts: &[&[u8]]) -> BigUint {
+        BigUint::from_bytes_be(&self.hash(parts))
+    }
+
+    /// `k = H(N | PAD(g))`
+    pub fn k(&self) -> BigUint {
+        let n = self.group.n.to_bytes_be();
+        self.hash_int(&[&n, &self.group.pad(&self.group.g)])
+    }
+
+    /// `x = H(s | H(I | ":" | P))`
+    pub fn x(&self, salt: &[u8], username: &str, password: &str) -> BigUint {
+        let inner = self.hash(&[username.as_bytes(), b":", password.as_bytes()]);
+        self.hash_int(&[salt, &inner])
+    }
+
+    /// `v = g^x % N`
+    pub fn verifier(&self, salt: &[u8], username: &str, password: &str) -> BigUint {
+        let x = self.x(salt, username, password);
+        self.group.g.modpow(&x, &self.group.n)
+    }
+
+    /// `u = H(PAD(A) | PAD(B))`
+    pub fn u(&self, a_pub: &BigUint, b_pub: &BigUint) -> BigUint {
+        self.hash_int(&[&self.group.pad(a_pub), &self.group.pad(b_pub)])
+    }
+
+    /// `A = g^a % N`
+    pub fn client_public(&self, a: &BigUint) -> BigUint {
+        self.group.g.modpow(a, &self.group.n)
+    }
+
+    /// `B = k*v + g^b % N`
+    pub fn server_public(&self, v: &BigUint, b: &BigUint) -> BigUint {
+        let n = &self.group.n;
+        (self.k() * v + self.group.g.modpow(b, n)) % n
+    }
+
+    /// `S = (B - (k * g^x)) ^ (a + (u * x)) % N`
+    pub fn client_premaster(
+        &self,
+        b_pub: &BigUint,
+        a: &BigUint,
+        x: &BigUint,
+        u: &BigUint,
+    ) -> BigUint {
+        let n = &self.group.n;
+        let kgx = (self.k() * self.group.g.modpow(x, n)) % n;
+        let base = (b_pub % n + n - kgx) % n;
+        base.modpow(&(a + u * x), n)
+    }
+
+    /// `S = (A * v^u) ^ b % N`
+    pub fn server_premaster(
+        &self,
+        a_pub: &BigUint,
+        v: &BigUint,
+        u: &BigUint,
+        b: &BigUint,
+    ) -> BigUint {
+        let n = &self.group.n;
+        ((a_pub * v.modpow(u, n)) % n).modpow(b, n)
+    }
+
+    /// Session key `K = H(S)`.
+    pub fn key(&self, premaster: &BigUint) -> Vec<u8> {
+        self.hash(&[&premaster.to_bytes_be()])
+    }
+
+    /// `M1 = H(H(N) xor H(g) | H(I) | s | A | B | K)`, as in RFC 2945.
+    pub fn client_proof(
+        &self,
+        username: &str,
+        salt: &[u8],
+        a_pub: &BigUint,
+        b_pub: &BigUint,
+        key: &[u8],
+    ) -> Vec<u8> {
+        let hn = self.hash(&[&self.group.n.to_bytes_be()]);
+        let hg = self.hash(&[&self.group.g.to_bytes_be()]);
+        let xor: Vec<u8> = hn.iter().zip(&hg).map(|(a, b)| a ^ b).collect();
+        let hi = self.hash(&[username.as_bytes()]);
+        self.hash(&[
+            &xor,
+            &hi,
+            salt,
+            &a_pub.to_bytes_be(),
+            &b_pub.to_bytes_be(),
+            key,
+        ])
+    }
+
+    /// `M2 = H(A | M1 | K)`
+    pub fn server_proof(&self, a_pub: &BigUint, m1: &[u8], key: &[u8]) -> Vec<u8> {
+        self.hash(&[&a_pub.to_bytes_be(), m1, key])
+    }
+}
+
+/// The parameters ferri uses: SHA-256 over the 2048-bit RFC 5054 group.
+pub fn srp() -> Srp<Sha256> {
+    Srp::new(SrpGroup::rfc5054_2048())
+}
+
+/// A salt and verifier as stored in `accounts.srp`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredVerifier {
+    pub salt: Vec<u8>,
+    pub verifier: BigUint,
+}
+
+impl StoredVerifier {
+    /// Registration: derive a verifier with a fresh random salt.
+    pub fn generate(username: &str, password: &str) -> Self {
+        let mut salt = vec![0; SALT_LEN];
+        rand::rng().fill_bytes(&mut salt);
+        let verifier = srp().verifier(&salt, username, password);
+        Self { salt, verifier }
+    }
+
+    /// Accept a verifier computed elsewhere (e.g. by a client that never reveals
+    /// the password), given as hex.
+    pub fn from_hex(salt: &str, verifier: &str) -> Result<Self> {
+        let salt = hex::decode(salt).map_err(|_| Error::InvalidSrp("salt".to_string()))?;
+        let verifier = parse_int(verifier).ok_or_else(|| Error::InvalidSrp("verifier".into()))?;
+        if salt.is_empty() || verifier == BigUint::ZERO {
+            return Err(Error::InvalidSrp("verifier".to_string()));
+        }
+        Ok(Self { salt, verifier })
+    }
+
+    /// `srp6a-sha256-2048$<salt hex>$<verifier hex>`
+    pub fn encode(&self) -> String {
+        format!(
+            "{SCHEME}${}${}",
+            hex::encode(&self.salt),
+            self.verifier.to_str_radix(16)
+        )
+    }
+
+    pub fn decode(stored: &str) -> Result<Self> {
+        let mut parts = stored.split('$');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(SCHEME), Some(salt), Some(v), None) => Self::from_hex(salt, v),
+            _ => Err(Error::InvalidSrp("stored verifier".to_string())),
+        }
+    }
+}
+
+/// Server side of one login: created when the client sends its username.
+#[derive(Debug, Clone)]
+pub struct ServerLogin {
+    username: String,
+    salt: Vec<u8>,
+    verifier: BigUint,
+    b: BigUint,
+    b_pub: BigUint,
+}
+
+impl ServerLogin {
+    pub fn new(username: &str, stored: &StoredVerifier) -> Self {
+        let srp = srp();
+        let b = random_secret();
+        let b_pub = srp.server_public(&stored.verifier, &b);
+        Self {
+            username: username.to_string(),
+            salt: stored.salt.clone(),
+            verifier: stored.verifier.clone(),
+            b,
+            b_pub,
+        }
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// `B`, sent to the client with the salt.
+    pub fn public(&self) -> &BigUint {
+        &self.b_pub
+    }
+
+    /// Check the client's `A` and proof `M1`; on success returns `M2`.
+    pub fn verify(&self, a_pub: &BigUint, m1: &[u8]) -> Result<Vec<u8>> {
+        let srp = srp();
+        if (a_pub % &srp.group.n) == BigUint::ZERO {
+            return Err(Error::AuthFailed);
+        }
+        let u = srp.u(a_pub, &self.b_pub);
+        if u == BigUint::ZERO {
+            return Err(Error::AuthFailed);
+        }
+        let premaster = srp.server_premaster(a_pub, &self.verifier, &u, &self.b);
+        let key = srp.key(&premaster);
+        let expected = srp.client_proof(&self.username, &self.salt, a_pub, &self.b_pub, &key);
+        if !constant_time_eq(&expected, m1) {
+            return Err(Error::AuthFailed);
+        }
+        Ok(srp.server_proof(a_pub, m1, &key))
+    }
+}
+
+/// Client side of a login, for the CLI, the desktop frontend and tests.
+#[derive(Debug, Clone)]
+pub struct ClientLogin {
+    a: BigUint,
+    a_pub: BigUint,
+}
+
+/// What the client sends in the second step, and the `M2` it expects back.
+#[derive(Debug, Clone)]
+pub struct ClientProof {
+    pub a_pub: BigUint,
+    pub m1: Vec<u8>,
+    pub expected_m2: Vec<u8>,
+}
+
+impl Default for ClientLogin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientLogin {
+    pub fn new() -> Self {
+        let a = random_secret();
+        let a_pub = srp().client_public(&a);
+        Self { a, a_pub }
+    }
+
+    /// Answer the server's salt and `B`.
+    pub fn prove(
+        &self,
+        username: &str,
+        password: &str,
+        salt: &[u8],
+        b_pub: &BigUint,
+    ) -> Result<ClientProof> {
+        let srp = srp();
+        if (b_pub % &srp.group.n) == BigUint::ZERO {
+            return Err(Error::AuthFailed);
+        }
+        let u = srp.u(&self.a_pub, b_pub);
+        let x = srp.x(salt, username, password);
+        let premaster = srp.client_premaster(b_pub, &self.a, &x, &u);
+        let key = srp.key(&premaster);
+        let m1 = srp.client_proof(username, salt, &self.a_pub, b_pub, &key);
+        let expected_m2 = srp.server_proof(&self.a_pub, &m1, &key);
+        Ok(ClientProof {
+            a_pub: self.a_pub.clone(),
+            m1,
+            expected_m2,
+        })
+    }
+}
+
+/// Parse a hex big integer as exchanged over JSON.
+pub fn parse_int(hex: &str) -> Option<BigUint> {
+    BigUint::parse_bytes(hex.trim().as_bytes(), 16)
+}
+
+fn random_secret() -> BigUint {
+    let mut bytes = [0u8; SECRET_LEN];
+    rand::rng().fill_bytes(&mut bytes);
+    BigUint::from_bytes_be(&bytes)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::Sha1;
+
+    use super::*;
+
+    fn int(hex: &str) -> BigUint {
+        let clean: String = hex.split_whitespace().collect();
+        BigUint::parse_bytes(clean.as_bytes(), 16).unwrap()
+    }
+
+    // RFC 5054 appendix B.
+    const USER: &str = "alice";
+    const PASS: &str = "password123";
+    const SALT: &str = "BEB25379 D1A8581E B5A72767 3A2441EE";
+    const K: &str = "7556AA04 5AEF2CDD 07ABAF0F 665C3E81 8913186F";
+    const X: &str = "94B7555A ABE9127C C58CCF49 93DB6CF8 4D16C124";
+    const V: &str = "7E273DE8 696FFC4F 4E337D05 B4B375BE B0DDE156 9E8FA00A 9886D812
+        9BADA1F1 822223CA 1A605B53 0E379BA4 729FDC59 F105B478 7E5186F5
+        C671085A 1447B52A 48CF1970 B4FB6F84 00BBF4CE BFBB1681 52E08AB5
+        EA53D15C 1AFF87B2 B9DA6E04 E058AD51 CC72BFC9 033B564E 26480D78
+        E955A5E2 9E7AB245 DB2BE315 E2099AFB";
+    const A_PRIV: &str = "60975527 035CF2AD 1989806F 0407210B C81EDC04 E2762A56 AFD529DD DA2D4393";
+    const B_PRIV: &str = "E487CB59 D31AC550 471E81F0 0F6928E0 1DDA08E9 74A004F4 9E61F5D1 05284D20";
+    const A_PUB: &str = "61D5E490 F6F1B795 47B0704C 436F523D D0E560F0 C64115BB 72557EC4
+        4352E890 3211C046 92272D8B 2D1A5358 A2CF1B6E 0BFCF99F 921530EC
+        8E393561 79EAE45E 42BA92AE ACED8251 71E1E8B9 AF6D9C03 E1327F44
+        BE087EF0 6530E69F 66615261 EEF54073 CA11CF58 58F0EDFD FE15EFEA
+        B349EF5D 76988A36 72FAC47B 0769447B";
+    const B_PUB: &str = "BD0C6151 2C692C0C B6D041FA 01BB152D 4916A1E7 7AF46AE1 05393011
+        BAF38964 DC46A067 0DD125B9 5A981652 236F99D9 B681CBF8 7837EC99
+        6C6DA044 53728610 D0C6DDB5 8B318885 D7D82C7F 8DEB75CE 7BD4FBAA
+        37089E6F 9C6059F3 88838E7A 00030B33 1EB76840 910440B1 B27AAEAE
+        EB4012B7 D7665238 A8E3FB00 4B117B58";
+    const U: &str = "CE38B959 3487DA98 554ED47D 70A7AE5F 462EF019";
+    const PREMASTER: &str = "B0DC82BA BCF30674 AE450C02 87745E79 90A3381F 63B387AA F271A10D
+        233861E3 59B48220 F7C4693C 9AE12B0A 6F67809F 0876E2D0 13800D6C
+        41BB59B6 D5979B5C 00A172B4 A2A5903A 0BDCAF8A 709585EB 2AFAFA8F
+        3499B200 210DCC1F 10EB3394 3CD67FC8 8A2F39A4 BE5BEC4E C0A3212D
+        C346D7E4 74B29EDE 8A469FFE CA686E5A";
+
+    fn rfc() -> Srp<Sha1> {
+        Srp::new(SrpGroup::rfc5054_1024())
+    }
+
+    fn salt() -> Vec<u8> {
+        int(SALT).to_bytes_be()
+    }
+
+    #[test]
+    fn rfc5054_k_x_v() {
+        let srp = rfc();
+        assert_eq!(srp.k(), int(K));
+        assert_eq!(srp.x(&salt(), USER, PASS), int(X));
+        assert_eq!(srp.verifier(&salt(), USER, PASS), int(V));
+    }
+
+    #[test]
+    fn rfc5054_public_values() {
+        let srp = rfc();
+        assert_eq!(srp.client_public(&int(A_PRIV)), int(A_PUB));
+        assert_eq!(srp.server_public(&int(V), &int(B_PRIV)), int(B_PUB));
+        assert_eq!(srp.u(&int(A_PUB), &int(B_PUB)), int(U));
+    }
+
+    #[test]
+    fn rfc5054_premaster_secret() {
+        let srp = rfc();
+        let client = srp.client_premaster(&int(B_PUB), &int(A_PRIV), &int(X), &int(U));
+        let server = srp.server_premaster(&int(A_PUB), &int(V), &int(U), &int(B_PRIV));
+        assert_eq!(client, int(PREMASTER));
+        assert_eq!(server, int(PREMASTER));
+    }
+
+    #[test]
+    fn login_round_trip() {
+        let stored = StoredVerifier::generate("bob", "hunter2");
+        let stored = StoredVerifier::decode(&stored.encode()).unwrap();
+
+        let server = ServerLogin::new("bob", &stored);
+        let client = ClientLogin::new();
+        let proof = client
+            .prove("bob", "hunter2", server.salt(), server.public())
+            .unwrap();
+        let m2 = server.verify(&proof.a_pub, &proof.m1).unwrap();
+        assert_eq!(m2, proof.expected_m2);
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let stored = StoredVerifier::generate("bob", "hunter2");
+        let server = ServerLogin::new("bob", &stored);
+        let proof = ClientLogin::new()
+            .prove("bob", "hunter3", server.salt(), server.public())
+            .unwrap();
+        assert!(server.verify(&proof.a_pub, &proof.m1).is_err());
+    }
+
+    #[test]
+    fn zero_public_value_is_rejected() {
+        let stored = StoredVerifier::generate("bob", "hunter2");
+        let server = ServerLogin::new("bob", &stored);
+        let n = server_group_n();
+        assert!(server.verify(&n, &[0; 32]).is_err());
+    }
+
+    fn server_group_n() -> BigUint {
+        SrpGroup::rfc5054_2048().n
+    }
+}
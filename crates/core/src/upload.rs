@@ -0,0 +1,261 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::error::{Error, Result};
+
+/// Prefix of in-progress upload files; listings skip these.
+pub const TEMP_PREFIX: &str = ".ferri-upload-";
+
+/// What to do when an upload's name is already taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep both, saving the new one as `name (1).ext`, `name (2).ext`, …
+    #[default]
+    Rename,
+    /// Refuse the upload.
+    Reject,
+}
+
+pub fn is_temp_name(name: &str) -> bool {
+    name.starts_with(TEMP_PREFIX)
+}
+
+/// Reduce a client-supplied file name to a single safe path component.
+///
+/// Browsers may send `dir/file.txt` or `C:\dir\file.txt`; only the last part is kept.
+pub fn sanitize_name(name: &str) -> Result<String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    if base.is_empty() || base == "." || base == ".." || base.contains('\0') || is_temp_name(base) {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    Ok(base.to_string())
+}
+
+/// Check `name` against an `accept` pattern such as `.zip,.rar` or `image/*`.
+///
+/// Entries starting with `.` match extensions; entries with `/` match the MIME
+/// type guessed from the name; `*` matches anything.
+pub fn accepts(pattern: &str, name: &str) -> bool {
+    let lower = name.to_lowercase();
+    let guessed = mime_guess::from_path(name).first();
+    pattern
+        .split(',')
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty())
+        .any(|p| {
+            if p == "*" || p == "*/*" {
+                true
+            } else if p.starts_with('.') {
+                lower.ends_with(&p)
+            } else if let Some((ty, sub)) = p.split_once('/') {
+                guessed.as_ref().is_some_and(|m| {
+                    m.type_().as_str() == ty && (sub == "*" || m.subtype().as_str() == sub)
+                })
+            } else {
+                false
+            }
+        })
+}
+
+/// Temp file for the upload `id` in `dir`.
+pub fn temp_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{TEMP_PREFIX}{id}"))
+}
+
+/// An upload being streamed to a hidden temp file in its target directory.
+///
+/// One-shot uploads remove the temp file on drop unless [`PendingUpload::finish`]
+/// placed it; resumed uploads keep it so a later request can continue.
+#[derive(Debug)]
+pub struct PendingUpload {
+    dir: PathBuf,
+    name: String,
+    temp: PathBuf,
+    file: tokio::fs::File,
+    written: u64,
+    limit: Option<u64>,
+    placed: bool,
+    keep_partial: bool,
+}
+
+impl PendingUpload {
+    /// Start an upload of `name` into `dir`, refusing to write more than `limit` bytes.
+    pub async fn create(dir: &Path, name: &str, limit: Option<u64>) -> Result<Self> {
+        let name = sanitize_name(name)?;
+        let temp = temp_path(dir, &uuid::Uuid::new_v4().simple().to_string());
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name,
+            temp,
+            file,
+            written: 0,
+            limit,
+            placed: false,
+            keep_partial: false,
+        })
+    }
+
+    /// Continue writing the partial upload in `temp` at `offset`.
+    ///
+    /// The file is created when `offset` is 0 and cut back to `offset` if an
+    /// earlier request wrote more than was acknowledged.
+    pub async fn resume(
+        dir: &Path,
+        name: &str,
+        temp: &Path,
+        offset: u64,
+        limit: Option<u64>,
+    ) -> Result<Self> {
+        let name = sanitize_name(name)?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(offset == 0)
+            .truncate(false)
+            .open(temp)
+            .await?;
+        let on_disk = file.metadata().await?.len();
+        if on_disk < offset {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} has {on_disk} of {offset} bytes", temp.display()),
+            )));
+        }
+        file.set_len(offset).await?;
+        file.seek(io::SeekFrom::Start(offset)).await?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name,
+            temp: temp.to_path_buf(),
+            file,
+            written: offset,
+            limit,
+            placed: false,
+            keep_partial: true,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Flush written data without placing the file, so `written` survives a crash.
+    pub async fn sync(&mut self) -> Result<()> {
+        self.file.flush().await?;
+        self.file.sync_data().await?;
+        Ok(())
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        let total = self.written + chunk.len() as u64;
+        if let Some(limit) = self.limit
+            && total > limit
+        {
+            return Err(Error::TooLarge { limit });
+        }
+        self.file.write_all(chunk).await?;
+        self.written = total;
+        Ok(())
+    }
+
+    /// Flush the data and move it to its final name according to `policy`.
+    ///
+    /// Returns the path the file was saved under.
+    pub async fn finish(mut self, policy: CollisionPolicy) -> Result<PathBuf> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        let target = place(&self.temp, &self.dir, &self.name, policy).await?;
+        self.placed = true;
+        Ok(target)
+    }
+}
+
+impl Drop for PendingUpload {
+    fn drop(&mut self) {
+        if !self.placed && !self.keep_partial {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Move `temp` to `dir/name`, resolving a collision per `policy`.
+pub async fn place(
+    temp: &Path,
+    dir: &Path,
+    name: &str,
+    policy: CollisionPolicy,
+) -> Result<PathBuf> {
+    let target = dir.join(name);
+    match policy {
+        // rename(2) atomically replaces an existing file.
+        CollisionPolicy::Overwrite => {
+            tokio::fs::rename(temp, &target).await?;
+            Ok(target)
+        }
+        CollisionPolicy::Reject => {
+            link_no_replace(temp, &target).await?;
+            Ok(target)
+        }
+        CollisionPolicy::Rename => {
+            let (stem, ext) = split_ext(name);
+            for i in 0..10_000u32 {
+                let candidate = if i == 0 {
+                    target.clone()
+                } else {
+                    dir.join(format!("{stem} ({i}){ext}"))
+                };
+                match link_no_replace(temp, &candidate).await {
+                    Ok(()) => return Ok(candidate),
+                    Err(Error::AlreadyExists(_)) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(Error::AlreadyExists(target))
+        }
+    }
+}
+
+/// Give `temp` the name `target` only if `target` does not exist yet.
+///
+/// A hard link fails atomically on an existing name; filesystems without hard
+/// links fall back to a check-then-rename.
+async fn link_no_replace(temp: &Path, target: &Path) -> Result<()> {
+    match tokio::fs::hard_link(temp, target).await {
+        Ok(()) => {
+            tokio::fs::remove_file(temp).await?;
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            Err(Error::AlreadyExists(target.to_path_buf()))
+        }
+        Err(_) => {
+            if tokio::fs::try_exists(target).await? {
+                return Err(Error::AlreadyExists(target.to_path_buf()));
+            }
+            tokio::fs::rename(temp, target).await?;
+            Ok(())
+        }
+    }
+}
+
+/// "archive.tar.gz" -> ("archive.tar", ".gz"); dotfiles keep their name as stem.
+fn split_ext(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    }
+}
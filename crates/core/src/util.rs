@@ -1,7 +1,26 @@
-pub fn get_running_path() -> std::path::PathBuf {
-    std::env::current_exe()
-        .expect("Failed to get current exe path")
-        .parent()
-        .expect("Failed to get parent directory")
-        .to_path_buf()
+/// Directory holding the running executable.
+pub fn get_running_path() -> std::io::Result<std::path::PathBuf> {
+    let exe = std::env::current_exe()?;
+    exe.parent()
+        .map(|p| p.to_path_buf())
+        .ok_or_else(|| std::io::Error::other(format!("{} has no parent directory", exe.display())))
+}
+
+/// Current time as Unix seconds, the timestamp format used by every table.
+pub fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// For `#[serde(deserialize_with)]` on an `Option<Option<T>>` field, with
+/// `#[serde(default)]`: tells an explicit `null` (`Some(None)`) apart from a
+/// missing field (`None`).
+pub fn nullable<'de, D, T>(d: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    serde::Deserialize::deserialize(d).map(Some)
 }
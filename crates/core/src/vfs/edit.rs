@@ -0,0 +1,453 @@
+//! Edits to the `vfs_*` tables, for the admin API.
+//!
+//! Everything is checked against the database inside the caller's
+//! transaction; callers reload the [`Vfs`](super::Vfs) snapshot once it
+//! commits.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqliteConnection;
+
+use super::mask::{self, MaskProps, VfsMask};
+use super::perm::{Permission, VfsPermission, WhoCan};
+use super::{NodeId, VfsNode, VfsRename, is_valid_segment};
+use crate::db::{JsonText, Timestamp};
+use crate::error::{Error, Result};
+use crate::net::NetMask;
+use crate::util::nullable;
+
+/// Column changes for [`create_node`] and [`update_node`]; `None` keeps the
+/// current value, `Some(None)` clears it.
+///
+/// A node with a `source_path` is a disk file or folder, one with a `url` a
+/// link, and one with neither a virtual folder.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct NodeChanges {
+    /// Moves the node, with everything below it.
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_id: Option<Option<NodeId>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub source_path: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub mime: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub ord: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub target: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub accept: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_child_id: Option<Option<NodeId>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_child_path: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub allow_net: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub deny_net: Option<Option<String>>,
+}
+
+impl NodeChanges {
+    fn apply(&self, node: &mut VfsNode) {
+        fn set<T: Clone>(field: &mut Option<T>, change: &Option<Option<T>>) {
+            if let Some(v) = change {
+                *field = v.clone();
+            }
+        }
+        set(&mut node.parent_id, &self.parent_id);
+        set(&mut node.name, &self.name);
+        set(&mut node.source_path, &self.source_path);
+        set(&mut node.url, &self.url);
+        set(&mut node.mime, &self.mime);
+        set(&mut node.ord, &self.ord);
+        set(&mut node.target, &self.target);
+        set(&mut node.accept, &self.accept);
+        set(&mut node.default_child_id, &self.default_child_id);
+        set(&mut node.default_child_path, &self.default_child_path);
+        set(&mut node.allow_net, &self.allow_net);
+        set(&mut node.deny_net, &self.deny_net);
+    }
+}
+
+pub async fn find_node(conn: &mut SqliteConnection, id: NodeId) -> Result<Option<VfsNode>> {
+    Ok(sqlx::query_as!(
+        VfsNode,
+        r#"SELECT id AS "id!", parent_id, name, source_path, url, mime, ord, target, accept,
+                  default_child_id, default_child_path, allow_net, deny_net,
+                  created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+           FROM vfs_nodes WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await?)
+}
+
+/// Every node, parents before children where ids allow.
+pub async fn list_nodes(conn: &mut SqliteConnection) -> Result<Vec<VfsNode>> {
+    Ok(sqlx::query_as!(
+        VfsNode,
+        r#"SELECT id AS "id!", parent_id, name, source_path, url, mime, ord, target, accept,
+                  default_child_id, default_child_path, allow_net, deny_net,
+                  created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+           FROM vfs_nodes ORDER BY id"#
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Add a node and return its id.
+pub async fn create_node(conn: &mut SqliteConnection, changes: &NodeChanges) -> Result<NodeId> {
+    let mut node = VfsNode {
+        id: 0,
+        parent_id: None,
+        name: None,
+        source_path: None,
+        url: None,
+        mime: None,
+        ord: None,
+        target: None,
+        accept: None,
+        default_child_id: None,
+        default_child_path: None,
+        allow_net: None,
+        deny_net: None,
+        created_at: Timestamp::now(),
+        updated_at: Timestamp::now(),
+    };
+    changes.apply(&mut node);
+    validate(conn, &mut node).await?;
+    let id = sqlx::query_scalar!(
+        r#"INSERT INTO vfs_nodes (parent_id, name, source_path, url, mime, ord, target, accept,
+                default_child_id, default_child_path, allow_net, deny_net)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+           RETURNING id AS "id!""#,
+        node.parent_id,
+        node.name,
+        node.source_path,
+        node.url,
+        node.mime,
+        node.ord,
+        node.target,
+        node.accept,
+        node.default_child_id,
+        node.default_child_path,
+        node.allow_net,
+        node.deny_net
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(id)
+}
+
+/// Apply `changes`; returns the node as stored, or `None` if there is no
+/// such node.
+pub async fn update_node(
+    conn: &mut SqliteConnection,
+    id: NodeId,
+    changes: &NodeChanges,
+) -> Result<Option<VfsNode>> {
+    let Some(mut node) = find_node(conn, id).await? else {
+        return Ok(None);
+    };
+    changes.apply(&mut node);
+    validate(conn, &mut node).await?;
+    sqlx::query!(
+        "UPDATE vfs_nodes SET parent_id = ?, name = ?, source_path = ?, url = ?, mime = ?,
+                ord = ?, target = ?, accept = ?, default_child_id = ?, default_child_path = ?,
+                allow_net = ?, deny_net = ?
+         WHERE id = ?",
+        node.parent_id,
+        node.name,
+        node.source_path,
+        node.url,
+        node.mime,
+        node.ord,
+        node.target,
+        node.accept,
+        node.default_child_id,
+        node.default_child_path,
+        node.allow_net,
+        node.deny_net,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(Some(node))
+}
+
+/// Delete a node and everything below it.
+pub async fn delete_node(conn: &mut SqliteConnection, id: NodeId) -> Result<bool> {
+    // The parent may name it as its default child.
+    sqlx::query!(
+        "UPDATE vfs_nodes SET default_child_id = NULL WHERE default_child_id = ?",
+        id
+    )
+    .execute(&mut *conn)
+    .await?;
+    let done = sqlx::query!("DELETE FROM vfs_nodes WHERE id = ?", id)
+        .execute(conn)
+        .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// Check a node about to be stored, resolving `source_path` to an absolute
+/// path.
+async fn validate(conn: &mut SqliteConnection, node: &mut VfsNode) -> Result<()> {
+    let bad = |msg: &str| Err(Error::InvalidNode(msg.to_string()));
+
+    if node.name.is_none() && node.source_path.is_none() && node.url.is_none() {
+        return bad("a node needs a name, a source_path or a url");
+    }
+    if node.source_path.is_some() && node.url.is_some() {
+        return bad("a node cannot have both a source_path and a url");
+    }
+    if let Some(name) = &node.name
+        && !is_valid_segment(name)
+    {
+        return Err(Error::InvalidName(name.clone()));
+    }
+    if node.url.as_deref().is_some_and(|u| u.trim().is_empty()) {
+        return bad("url is empty");
+    }
+    if let Some(src) = &node.source_path {
+        node.source_path = Some(source_path(src).await?);
+    }
+    for mask in [&node.allow_net, &node.deny_net].into_iter().flatten() {
+        mask.parse::<NetMask>()?;
+    }
+
+    if let Some(parent_id) = node.parent_id {
+        let Some(parent) = find_node(conn, parent_id).await? else {
+            return bad("parent node does not exist");
+        };
+        if parent.is_link() {
+            return bad("a link cannot have children");
+        }
+        if let Some(src) = &parent.source_path
+            && !tokio::fs::metadata(src).await.is_ok_and(|m| m.is_dir())
+        {
+            return bad("a file cannot have children");
+        }
+        if node.id != 0 && is_within(conn, parent_id, node.id).await? {
+            return bad("a node cannot be moved below itself");
+        }
+    }
+
+    if let Some(child) = node.default_child_id {
+        let parent = sqlx::query_scalar!("SELECT parent_id FROM vfs_nodes WHERE id = ?", child)
+            .fetch_optional(&mut *conn)
+            .await?;
+        if node.id == 0 || parent.flatten() != Some(node.id) {
+            return bad("default_child_id must be a child of the node");
+        }
+    }
+    if let Some(path) = &node.default_child_path
+        && (path.starts_with('/') || path.split('/').any(|s| s == ".."))
+    {
+        return bad("default_child_path must be relative and stay inside the node");
+    }
+
+    // Siblings are told apart by name in URLs.
+    let name = node.display_name().to_lowercase();
+    let siblings = sqlx::query_as!(
+        VfsNode,
+        r#"SELECT id AS "id!", parent_id, name, source_path, url, mime, ord, target, accept,
+                  default_child_id, default_child_path, allow_net, deny_net,
+                  created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+           FROM vfs_nodes WHERE parent_id IS ? AND id != ?"#,
+        node.parent_id,
+        node.id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    if siblings
+        .iter()
+        .any(|s| s.display_name().to_lowercase() == name)
+    {
+        return Err(Error::AlreadyExists(PathBuf::from(node.display_name())));
+    }
+    Ok(())
+}
+
+/// An existing path on disk, made absolute.
+async fn source_path(src: &str) -> Result<String> {
+    let path = tokio::fs::canonicalize(src)
+        .await
+        .map_err(|e| Error::InvalidNode(format!("source_path {src:?}: {e}")))?;
+    path.into_os_string()
+        .into_string()
+        .map_err(|_| Error::InvalidNode(format!("source_path {src:?} is not valid UTF-8")))
+}
+
+/// Whether `node` is `ancestor` or lies below it.
+async fn is_within(conn: &mut SqliteConnection, node: NodeId, ancestor: NodeId) -> Result<bool> {
+    // UNION stops on an existing cycle rather than looping.
+    Ok(sqlx::query_scalar!(
+        r#"WITH RECURSIVE up(id) AS (
+               SELECT ?1
+               UNION
+               SELECT n.parent_id FROM vfs_nodes n JOIN up ON n.id = up.id
+               WHERE n.parent_id IS NOT NULL
+           )
+           SELECT EXISTS (SELECT 1 FROM up WHERE id = ?2) AS "within!: bool""#,
+        node,
+        ancestor
+    )
+    .fetch_one(conn)
+    .await?)
+}
+
+/// The permission rules set on a node, as stored.
+pub async fn permissions(conn: &mut SqliteConnection, id: NodeId) -> Result<Vec<VfsPermission>> {
+    Ok(sqlx::query_as!(
+        VfsPermission,
+        r#"SELECT id AS "id!", node_id, permission AS "permission: Permission",
+                  who AS "who: JsonText<WhoCan>"
+           FROM vfs_node_permissions WHERE node_id = ? ORDER BY permission"#,
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+pub async fn set_permission(
+    conn: &mut SqliteConnection,
+    id: NodeId,
+    perm: Permission,
+    who: &WhoCan,
+) -> Result<()> {
+    let who = serde_json::to_string(who).map_err(|e| Error::InvalidNode(e.to_string()))?;
+    sqlx::query!(
+        "INSERT INTO vfs_node_permissions (node_id, permission, who) VALUES (?, ?, ?)
+         ON CONFLICT (node_id, permission) DO UPDATE SET who = excluded.who",
+        id,
+        perm,
+        who
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Drop a node's rule, so the permission is inherited again.
+pub async fn remove_permission(
+    conn: &mut SqliteConnection,
+    id: NodeId,
+    perm: Permission,
+) -> Result<bool> {
+    let done = sqlx::query!(
+        "DELETE FROM vfs_node_permissions WHERE node_id = ? AND permission = ?",
+        id,
+        perm
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+pub async fn masks(conn: &mut SqliteConnection, id: NodeId) -> Result<Vec<VfsMask>> {
+    Ok(sqlx::query_as!(
+        VfsMask,
+        r#"SELECT id AS "id!", node_id, mask, properties AS "properties: JsonText<MaskProps>",
+                  ord
+           FROM vfs_node_masks WHERE node_id = ? ORDER BY ord DESC, id"#,
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Add a mask to a node, or replace the one with the same pattern; returns
+/// its id.
+pub async fn set_mask(
+    conn: &mut SqliteConnection,
+    id: NodeId,
+    pattern: &str,
+    props: &MaskProps,
+    ord: Option<i64>,
+) -> Result<i64> {
+    mask::check(pattern, props).map_err(Error::InvalidNode)?;
+    let properties = serde_json::to_string(props).map_err(|e| Error::InvalidNode(e.to_string()))?;
+    Ok(sqlx::query_scalar!(
+        r#"INSERT INTO vfs_node_masks (node_id, mask, properties, ord) VALUES (?, ?, ?, ?)
+           ON CONFLICT (node_id, mask) DO UPDATE SET properties = excluded.properties,
+                ord = excluded.ord
+           RETURNING id AS "id!""#,
+        id,
+        pattern,
+        properties,
+        ord
+    )
+    .fetch_one(conn)
+    .await?)
+}
+
+pub async fn remove_mask(conn: &mut SqliteConnection, id: NodeId, mask_id: i64) -> Result<bool> {
+    let done = sqlx::query!(
+        "DELETE FROM vfs_node_masks WHERE id = ? AND node_id = ?",
+        mask_id,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+pub async fn renames(conn: &mut SqliteConnection, id: NodeId) -> Result<Vec<VfsRename>> {
+    Ok(sqlx::query_as!(
+        VfsRename,
+        r#"SELECT id AS "id!", node_id, original_name, new_name FROM vfs_node_renames
+           WHERE node_id = ? ORDER BY original_name"#,
+        id
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Show the disk entry `original` under a node as `new_name`.
+pub async fn set_rename(
+    conn: &mut SqliteConnection,
+    node: &VfsNode,
+    original: &str,
+    new_name: &str,
+) -> Result<()> {
+    if node.source_path.is_none() {
+        return Err(Error::InvalidNode(
+            "renames apply to nodes with a source_path".to_string(),
+        ));
+    }
+    for name in [original, new_name] {
+        if !is_valid_segment(name) {
+            return Err(Error::InvalidName(name.to_string()));
+        }
+    }
+    sqlx::query!(
+        "INSERT INTO vfs_node_renames (node_id, original_name, new_name) VALUES (?, ?, ?)
+         ON CONFLICT (node_id, original_name) DO UPDATE SET new_name = excluded.new_name",
+        node.id,
+        original,
+        new_name
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_rename(
+    conn: &mut SqliteConnection,
+    id: NodeId,
+    original: &str,
+) -> Result<bool> {
+    let done = sqlx::query!(
+        "DELETE FROM vfs_node_renames WHERE node_id = ? AND original_name = ?",
+        id,
+        original
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
@@ -0,0 +1,183 @@
+use std::cmp::Reverse;
+
+use super::{NodeId, Vfs};
+
+impl Vfs {
+    /// Pick the root node serving `host`.
+    ///
+    /// An exact `host_mask` wins, then the most specific wildcard mask (the one
+    /// sorting first among equally specific ones), then the default root.
+    pub fn root_for_host(&self, host: Option<&str>) -> Option<NodeId> {
+        if let Some(host) = host.map(strip_port).filter(|h| !h.is_empty()) {
+            if let Some(r) = self
+                .roots
+                .iter()
+                .find(|r| r.host_mask.eq_ignore_ascii_case(host))
+            {
+                return Some(r.node_id);
+            }
+            if let Some(r) = self
+                .roots
+                .iter()
+                .filter(|r| r.host_mask != "*" && host_matches(&r.host_mask, host))
+                .max_by_key(|r| (specificity(&r.host_mask), Reverse(r.host_mask.as_str())))
+            {
+                return Some(r.node_id);
+            }
+        }
+        self.default_root()
+    }
+
+    /// Root used when no `host_mask` matches: a `*` root, else the first top-level node.
+    pub fn default_root(&self) -> Option<NodeId> {
+        if let Some(r) = self.roots.iter().find(|r| r.host_mask == "*") {
+            return Some(r.node_id);
+        }
+        self.child_nodes(None).map(|n| n.id).min()
+    }
+}
+
+/// Match `host` against a mask where `*` matches any run of characters and `?`
+/// a single one, ignoring ASCII case. `*.example.com` does not match `example.com`.
+pub fn host_matches(mask: &str, host: &str) -> bool {
+    let (m, h) = (mask.as_bytes(), host.as_bytes());
+    let (mut mi, mut hi) = (0, 0);
+    // Position of the last `*` in the mask and the host position it is trying to cover.
+    let mut star: Option<(usize, usize)> = None;
+    while hi < h.len() {
+        if mi < m.len() && (m[mi] == b'?' || m[mi].eq_ignore_ascii_case(&h[hi])) {
+            mi += 1;
+            hi += 1;
+        } else if mi < m.len() && m[mi] == b'*' {
+            star = Some((mi, hi));
+            mi += 1;
+        } else if let Some((smi, shi)) = star {
+            mi = smi + 1;
+            hi = shi + 1;
+            star = Some((smi, shi + 1));
+        } else {
+            return false;
+        }
+    }
+    m[mi..].iter().all(|&c| c == b'*')
+}
+
+/// Number of literal characters; more literal masks are more specific.
+fn specificity(mask: &str) -> usize {
+    mask.chars().filter(|c| !matches!(c, '*' | '?')).count()
+}
+
+/// Drop a trailing `:port` from a `Host` value.
+pub fn strip_port(host: &str) -> &str {
+    // "[::1]:8080" -> "[::1]", "example.com:80" -> "example.com"
+    if host.starts_with('[') {
+        return host.split_inclusive(']').next().unwrap_or(host);
+    }
+    match host.rsplit_once(':') {
+        Some((h, port)) if port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Timestamp;
+    use crate::vfs::{VfsNode, VfsRoot, VfsRows};
+
+    fn vfs(roots: &[(&str, NodeId)]) -> Vfs {
+        let nodes = (1..=5)
+            .map(|id| VfsNode {
+                id,
+                parent_id: None,
+                name: Some(format!("n{id}")),
+                source_path: None,
+                url: None,
+                mime: None,
+                ord: None,
+                target: None,
+                accept: None,
+                default_child_id: None,
+                default_child_path: None,
+                allow_net: None,
+                deny_net: None,
+                created_at: Timestamp::now(),
+                updated_at: Timestamp::now(),
+            })
+            .collect();
+        let roots = roots
+            .iter()
+            .map(|&(host_mask, node_id)| VfsRoot {
+                host_mask: host_mask.to_string(),
+                node_id,
+            })
+            .collect();
+        Vfs::from_rows(VfsRows {
+            nodes,
+            roots,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn strips_ports() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("10.0.0.1:80"), "10.0.0.1");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+        assert_eq!(strip_port("[fe80::1]:443"), "[fe80::1]");
+        // Not a port: left alone.
+        assert_eq!(strip_port("example.com:http"), "example.com:http");
+    }
+
+    #[test]
+    fn wildcard_masks() {
+        assert!(host_matches("example.com", "EXAMPLE.com"));
+        assert!(host_matches("*.example.com", "a.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "a.example.org"));
+        assert!(host_matches("?.example.com", "a.example.com"));
+        assert!(!host_matches("?.example.com", "ab.example.com"));
+        assert!(host_matches("*", "anything"));
+        assert!(host_matches("a*b*c", "axxbyyc"));
+        assert!(!host_matches("a*b*c", "axxbyy"));
+        assert!(host_matches("[::1]", "[::1]"));
+    }
+
+    #[test]
+    fn exact_mask_wins() {
+        let vfs = vfs(&[("example.com", 1), ("*.example.com", 2), ("*", 4)]);
+        assert_eq!(vfs.root_for_host(Some("example.com")), Some(1));
+        assert_eq!(vfs.root_for_host(Some("Example.COM:8080")), Some(1));
+        assert_eq!(vfs.root_for_host(Some("www.example.com")), Some(2));
+    }
+
+    #[test]
+    fn most_specific_wildcard_wins() {
+        let vfs = vfs(&[
+            ("*.example.com", 2),
+            ("*.dev.example.com", 3),
+            ("www.example.*", 5),
+            ("*", 4),
+        ]);
+        assert_eq!(vfs.root_for_host(Some("a.example.com")), Some(2));
+        assert_eq!(vfs.root_for_host(Some("a.dev.example.com")), Some(3));
+        // Both masks have twelve literal characters; the first by name wins.
+        assert_eq!(vfs.root_for_host(Some("www.example.com")), Some(2));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_root() {
+        let starred = vfs(&[("*.example.com", 2), ("*", 4)]);
+        assert_eq!(starred.root_for_host(Some("example.org")), Some(4));
+        assert_eq!(starred.root_for_host(Some("")), Some(4));
+        assert_eq!(starred.root_for_host(None), Some(4));
+
+        // Without a `*` root, the first top-level node.
+        let unstarred = vfs(&[("*.example.com", 2)]);
+        assert_eq!(unstarred.root_for_host(Some("example.org")), Some(1));
+        assert_eq!(unstarred.root_for_host(Some("[::1]:8080")), Some(1));
+    }
+}
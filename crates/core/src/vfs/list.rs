@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+use super::perm::Permission;
+use super::{Entry, EntryKind, Vfs, join_url, stat};
+use crate::account::Principal;
+use crate::error::Result;
+use crate::upload::is_temp_name;
+use crate::walkdir::{CbResult, WalkDecision, WalkOptions, walk_dir_stream};
+
+/// Options for [`Vfs::list_stream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListOptions {
+    /// 1 = direct children only.
+    pub depth: usize,
+    /// Entries the caller lacks this permission on are left out.
+    pub require: Permission,
+    /// Folders are only descended into when the caller has this permission.
+    pub descend_if: Permission,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            require: Permission::CanSee,
+            descend_if: Permission::CanList,
+        }
+    }
+}
+
+type Tx = mpsc::Sender<Result<Entry>>;
+
+impl Vfs {
+    /// Stream the entries below `folder`: virtual child nodes first, then whatever
+    /// is on disk under its `source_path`.
+    ///
+    /// Disk entries shadowed by a child node of the same name are skipped, and
+    /// renames apply to the first disk level below a node.
+    pub fn list_stream(
+        self: Arc<Self>,
+        who: Principal,
+        folder: Entry,
+        opts: ListOptions,
+    ) -> ReceiverStream<Result<Entry>> {
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let who = Arc::new(who);
+            if let Err(e) = list_into(self, who, folder, opts.depth, opts, tx.clone()).await {
+                let _ = tx.send(Err(e)).await;
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+}
+
+fn list_into(
+    vfs: Arc<Vfs>,
+    who: Arc<Principal>,
+    folder: Entry,
+    depth: usize,
+    opts: ListOptions,
+    tx: Tx,
+) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+    Box::pin(async move {
+        if depth == 0 || !folder.is_folder() {
+            return Ok(());
+        }
+
+        let mut shadowed = HashSet::new();
+        if folder.is_node() {
+            let ids: Vec<_> = vfs
+                .child_nodes(Some(folder.node_id))
+                .map(|n| (n.id, n.display_name()))
+                .collect();
+            for (id, name) in ids {
+                let url_path = join_url(&folder.url_path, &name);
+                shadowed.insert(name);
+                let Some(entry) = vfs.node_entry(id, url_path).await? else {
+                    continue;
+                };
+                if !vfs.can(&who, opts.require, &entry) {
+                    continue;
+                }
+                let descend = depth > 1 && vfs.can(&who, opts.descend_if, &entry);
+                if tx.send(Ok(entry.clone())).await.is_err() {
+                    return Ok(());
+                }
+                if descend {
+                    list_into(vfs.clone(), who.clone(), entry, depth - 1, opts, tx.clone()).await?;
+                }
+            }
+        }
+
+        if folder.source.is_some() {
+            list_disk(vfs, who, folder, depth, opts, shadowed, tx).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Walk the disk part of `folder` with [`walk_dir_stream`] and forward visible entries.
+async fn list_disk(
+    vfs: Arc<Vfs>,
+    who: Arc<Principal>,
+    folder: Entry,
+    depth: usize,
+    opts: ListOptions,
+    shadowed: HashSet<String>,
+    tx: Tx,
+) -> Result<()> {
+    let Some(root) = folder.source.clone() else {
+        return Ok(());
+    };
+    // Masks and permissions decide what is listed, and nothing outside
+    // `source_path` is.
+    let walk_opts = WalkOptions {
+        depth,
+        follow_links: false,
+        ignore_files: false,
+        ..WalkOptions::default()
+    };
+    let folder = Arc::new(folder);
+    let shadowed = Arc::new(shadowed);
+
+    let mut stream = walk_dir_stream(root, walk_opts, move |w| {
+        let (vfs, who, folder, shadowed) =
+            (vfs.clone(), who.clone(), folder.clone(), shadowed.clone());
+        async move {
+            let Ok(Some(meta)) = stat(&w.abs_path).await else {
+                return CbResult::skip();
+            };
+
+            // Client-visible names: the first level below a node honours renames.
+            let mut names = Vec::new();
+            for (i, c) in w.rel_path.components().enumerate() {
+                let disk = c.as_os_str().to_string_lossy();
+                let name = if i == 0 && folder.is_node() {
+                    vfs.renamed(folder.node_id, &disk).to_string()
+                } else {
+                    disk.into_owned()
+                };
+                names.push(name);
+            }
+            let Some(name) = names.last().cloned() else {
+                return CbResult::cont();
+            };
+            if names.len() == 1 && shadowed.contains(&name) || is_temp_name(&name) {
+                return CbResult::skip();
+            }
+
+            let kind = if meta.is_dir() {
+                EntryKind::Folder
+            } else {
+                EntryKind::File
+            };
+            let mut url_path = folder.url_path.clone();
+            for n in &names {
+                url_path = join_url(&url_path, n);
+            }
+            if kind == EntryKind::Folder {
+                url_path.push('/');
+            }
+            let entry = Entry {
+                node_id: folder.node_id,
+                rel_path: folder.rel_path.join(&w.rel_path),
+                name,
+                url_path,
+                kind,
+                source: Some(w.abs_path),
+                url: None,
+                metadata: Some(meta),
+            };
+
+            if !vfs.can(&who, opts.require, &entry) {
+                return CbResult::skip();
+            }
+            if entry.is_folder() && !vfs.can(&who, opts.descend_if, &entry) {
+                return CbResult {
+                    decision: WalkDecision::SkipDescend,
+                    output: Some(entry),
+                };
+            }
+            CbResult::emit(entry)
+        }
+    })?;
+
+    while let Some(item) = stream.next().await {
+        if tx.send(item).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,524 @@
+use std::fmt;
+
+use globset::{GlobBuilder, GlobMatcher};
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::FromRow;
+use tracing::warn;
+
+use super::perm::{Permission, WhoCan};
+use super::{Entry, NodeId, Vfs, VfsNode};
+use crate::db::JsonText;
+
+/// A row of `vfs_node_masks`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Deserialize, Serialize)]
+pub struct VfsMask {
+    pub id: i64,
+    pub node_id: NodeId,
+    pub mask: String,
+    pub properties: JsonText<MaskProps>,
+    pub ord: Option<i64>,
+}
+
+/// Properties a mask can set on the entries it matches.
+///
+/// Entries are hidden from listings by denying `can_see`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaskProps {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_read: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_see: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_upload: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_list: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_archive: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub can_delete: Option<WhoCan>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Masks applied below entries matched by this one, in priority order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "ordered_masks")]
+    pub masks: Vec<(String, MaskProps)>,
+}
+
+impl MaskProps {
+    pub fn permission(&self, perm: Permission) -> Option<&WhoCan> {
+        match perm {
+            Permission::CanRead => self.can_read.as_ref(),
+            Permission::CanSee => self.can_see.as_ref(),
+            Permission::CanUpload => self.can_upload.as_ref(),
+            Permission::CanList => self.can_list.as_ref(),
+            Permission::CanArchive => self.can_archive.as_ref(),
+            Permission::CanDelete => self.can_delete.as_ref(),
+        }
+    }
+}
+
+/// Nested `masks` is a JSON object whose key order is its priority order, which a
+/// plain map would lose.
+mod ordered_masks {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        masks: &[(String, MaskProps)],
+        ser: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = ser.serialize_map(Some(masks.len()))?;
+        for (k, v) in masks {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        de: D,
+    ) -> std::result::Result<Vec<(String, MaskProps)>, D::Error> {
+        struct OrderedVisitor;
+
+        impl<'de> Visitor<'de> for OrderedVisitor {
+            type Value = Vec<(String, MaskProps)>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an object of mask -> properties")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut access: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut out = Vec::new();
+                while let Some(entry) = access.next_entry()? {
+                    out.push(entry);
+                }
+                Ok(out)
+            }
+        }
+
+        de.deserialize_map(OrderedVisitor)
+    }
+}
+
+/// A mask with its glob compiled once per snapshot.
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledMask {
+    pattern: String,
+    matcher: GlobMatcher,
+    props: MaskProps,
+    nested: Vec<CompiledMask>,
+}
+
+impl CompiledMask {
+    /// Compile a mask and its nested masks; invalid globs are logged and dropped.
+    fn compile(node_id: NodeId, pattern: String, mut props: MaskProps) -> Option<Self> {
+        let matcher = match glob(&pattern) {
+            Ok(g) => g,
+            Err(e) => {
+                warn!(node_id, mask = %pattern, "skipping invalid mask: {e}");
+                return None;
+            }
+        };
+        let nested = std::mem::take(&mut props.masks)
+            .into_iter()
+            .filter_map(|(p, props)| Self::compile(node_id, p, props))
+            .collect();
+        Some(Self {
+            pattern,
+            matcher,
+            props,
+            nested,
+        })
+    }
+}
+
+fn glob(pattern: &str) -> std::result::Result<GlobMatcher, globset::Error> {
+    Ok(GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .case_insensitive(true)
+        .build()?
+        .compile_matcher())
+}
+
+/// Check that a mask and its nested masks compile; loading skips bad ones,
+/// edits should refuse them instead.
+pub(crate) fn check(pattern: &str, props: &MaskProps) -> std::result::Result<(), String> {
+    glob(pattern).map_err(|e| format!("{pattern}: {e}"))?;
+    props
+        .masks
+        .iter()
+        .try_for_each(|(p, props)| check(p, props))
+}
+
+/// Compile the mask rows of every node, ordered by priority (highest `ord` first).
+pub(crate) fn compile_masks(
+    mut rows: Vec<VfsMask>,
+) -> std::collections::HashMap<NodeId, Vec<CompiledMask>> {
+    rows.sort_by(|a, b| {
+        a.node_id
+            .cmp(&b.node_id)
+            .then_with(|| b.ord.unwrap_or(0).cmp(&a.ord.unwrap_or(0)))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let mut out: std::collections::HashMap<NodeId, Vec<CompiledMask>> = Default::default();
+    for row in rows {
+        let props = match row.properties.into_result() {
+            Ok(p) => p,
+            Err(e) => {
+                warn!(
+                    node_id = row.node_id,
+                    mask = %row.mask,
+                    "skipping invalid mask properties: {e}"
+                );
+                continue;
+            }
+        };
+        if let Some(m) = CompiledMask::compile(row.node_id, row.mask, props) {
+            out.entry(row.node_id).or_default().push(m);
+        }
+    }
+    out
+}
+
+/// Properties contributed by one matching mask.
+#[derive(Debug, Clone, Copy)]
+pub struct MaskHit<'a> {
+    /// Node the mask is defined on.
+    pub node_id: NodeId,
+    /// Outermost pattern that matched (nested masks report their parent's pattern).
+    pub pattern: &'a str,
+    pub props: &'a MaskProps,
+}
+
+/// Collect masks matching `path` (relative to the masks' node), in priority order.
+///
+/// A mask matching a leading part of `path` hands the remainder to its nested masks.
+fn collect<'a>(
+    node_id: NodeId,
+    masks: &'a [CompiledMask],
+    path: &[&str],
+    outer: Option<&'a str>,
+    out: &mut Vec<MaskHit<'a>>,
+) {
+    for m in masks {
+        let pattern = outer.unwrap_or(&m.pattern);
+        for k in 1..=path.len() {
+            if !m.matcher.is_match(path[..k].join("/")) {
+                continue;
+            }
+            if k == path.len() {
+                out.push(MaskHit {
+                    node_id,
+                    pattern,
+                    props: &m.props,
+                });
+            } else {
+                collect(node_id, &m.nested, &path[k..], Some(pattern), out);
+            }
+        }
+    }
+}
+
+/// One step on the way from an entry up to the top of the tree.
+#[derive(Debug)]
+pub(crate) struct Level<'a> {
+    /// Set when this level is a `vfs_nodes` row.
+    pub node: Option<&'a VfsNode>,
+    pub masks: Vec<MaskHit<'a>>,
+}
+
+/// Effective properties of a resolved entry after node columns and masks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryProps {
+    pub mime: Option<String>,
+    /// Upload accept pattern; inherited from the nearest folder that sets one.
+    pub accept: Option<String>,
+    pub target: Option<String>,
+}
+
+impl Vfs {
+    /// Masks matching the entry at `rel` below `node`, nearest node first.
+    pub fn matching_masks(&self, node: NodeId, rel: &[String]) -> Vec<MaskHit<'_>> {
+        let mut out = Vec::new();
+        let mut path: Vec<&str> = rel.iter().map(String::as_str).collect();
+        let names: Vec<String> = self
+            .ancestors(node)
+            .iter()
+            .map(|n| n.display_name())
+            .collect();
+        for (n, name) in self.ancestors(node).into_iter().zip(&names) {
+            if !path.is_empty()
+                && let Some(masks) = self.masks.get(&n.id)
+            {
+                collect(n.id, masks, &path, None, &mut out);
+            }
+            path.insert(0, name);
+        }
+        out
+    }
+
+    /// The entry itself, each disk folder above it, then each node up to the top.
+    pub(crate) fn levels(&self, entry: &Entry) -> Vec<Level<'_>> {
+        let mut rel: Vec<String> = entry
+            .rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        let mut out = Vec::new();
+        while !rel.is_empty() {
+            out.push(Level {
+                node: None,
+                masks: self.matching_masks(entry.node_id, &rel),
+            });
+            rel.pop();
+        }
+        for node in self.ancestors(entry.node_id) {
+            out.push(Level {
+                node: Some(node),
+                masks: self.matching_masks(node.id, &[]),
+            });
+        }
+        out
+    }
+
+    /// Compute `mime`, `accept` and `target` for an entry.
+    ///
+    /// A node's own columns win over masks; among masks the nearest node and the
+    /// highest `ord` win.
+    pub fn props(&self, entry: &Entry) -> EntryProps {
+        let levels = self.levels(entry);
+        let pick = |level: &Level<'_>, f: &dyn Fn(&MaskProps) -> Option<String>| {
+            level.masks.iter().find_map(|hit| f(hit.props))
+        };
+
+        let mut props = EntryProps::default();
+        if let Some(first) = levels.first() {
+            let node = first.node;
+            props.mime = node
+                .and_then(|n| n.mime.clone())
+                .or_else(|| pick(first, &|p| p.mime.clone()));
+            props.target = node
+                .and_then(|n| n.target.clone())
+                .or_else(|| pick(first, &|p| p.target.clone()));
+        }
+        props.accept = levels.iter().find_map(|level| {
+            level
+                .node
+                .and_then(|n| n.accept.clone())
+                .or_else(|| pick(level, &|p| p.accept.clone()))
+        });
+        props
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::account::Principal;
+    use crate::db::Timestamp;
+    use crate::vfs::{EntryKind, VfsRows};
+
+    /// Nodes `/top/docs` (ids 1 and 2) with `masks` as (node, mask, ord, JSON).
+    fn rows(masks: &[(NodeId, &str, Option<i64>, &str)]) -> VfsRows {
+        let nodes = ["top", "docs"]
+            .into_iter()
+            .zip(1..)
+            .map(|(name, id)| VfsNode {
+                id,
+                parent_id: (id > 1).then(|| id - 1),
+                name: Some(name.to_string()),
+                source_path: None,
+                url: None,
+                mime: None,
+                ord: None,
+                target: None,
+                accept: None,
+                default_child_id: None,
+                default_child_path: None,
+                allow_net: None,
+                deny_net: None,
+                created_at: Timestamp::now(),
+                updated_at: Timestamp::now(),
+            })
+            .collect();
+        let masks = masks
+            .iter()
+            .zip(1..)
+            .map(|(&(node_id, mask, ord, props), id)| VfsMask {
+                id,
+                node_id,
+                mask: mask.to_string(),
+                properties: JsonText::parse(props),
+                ord,
+            })
+            .collect();
+        VfsRows {
+            nodes,
+            masks,
+            ..Default::default()
+        }
+    }
+
+    fn vfs(masks: &[(NodeId, &str, Option<i64>, &str)]) -> Vfs {
+        Vfs::from_rows(rows(masks))
+    }
+
+    /// The disk file `rel` below `node_id`.
+    fn file(node_id: NodeId, rel: &str) -> Entry {
+        Entry {
+            node_id,
+            rel_path: PathBuf::from(rel),
+            name: String::new(),
+            url_path: "/".to_string(),
+            kind: EntryKind::File,
+            source: None,
+            url: None,
+            metadata: None,
+        }
+    }
+
+    fn patterns(vfs: &Vfs, node: NodeId, rel: &str) -> Vec<String> {
+        let rel: Vec<String> = rel.split('/').map(str::to_string).collect();
+        vfs.matching_masks(node, &rel)
+            .iter()
+            .map(|hit| hit.pattern.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn higher_ord_wins() {
+        let vfs = vfs(&[
+            (2, "*.txt", Some(1), r#"{"mime":"text/one"}"#),
+            (2, "notes.*", None, r#"{"mime":"text/none"}"#),
+            (2, "*", Some(5), r#"{"mime":"text/five"}"#),
+            (2, "n*", Some(1), r#"{"mime":"text/n"}"#),
+            (1, "docs/*", Some(9), r#"{"mime":"text/top"}"#),
+        ]);
+        // Ties go to the older row; `top`'s masks come after `docs`' whatever their `ord`.
+        assert_eq!(
+            patterns(&vfs, 2, "notes.txt"),
+            ["*", "*.txt", "n*", "notes.*", "docs/*"]
+        );
+        assert_eq!(
+            vfs.props(&file(2, "notes.txt")).mime.as_deref(),
+            Some("text/five")
+        );
+    }
+
+    #[test]
+    fn nested_masks_take_the_rest_of_the_path() {
+        let vfs = vfs(&[
+            (
+                2,
+                "src",
+                None,
+                r#"{"mime":"inode/src","masks":{"*.rs":{"mime":"text/rust"},"lib.*":{"mime":"text/lib"}}}"#,
+            ),
+            (1, "docs", None, r#"{"masks":{"*.txt":{"can_see":false}}}"#),
+        ]);
+        // Nested masks keep their key order and report the outer pattern.
+        assert_eq!(patterns(&vfs, 2, "src/lib.rs"), ["src", "src"]);
+        assert_eq!(
+            vfs.props(&file(2, "src/lib.rs")).mime.as_deref(),
+            Some("text/rust")
+        );
+        assert_eq!(
+            vfs.props(&file(2, "src")).mime.as_deref(),
+            Some("inode/src")
+        );
+        assert_eq!(vfs.props(&file(2, "lib.rs")).mime, None);
+        assert!(patterns(&vfs, 2, "src/sub/lib.rs").is_empty());
+
+        // A mask on `top` names the `docs` node, then hands over the file name.
+        let denial = vfs
+            .check(
+                &Principal::anonymous(),
+                Permission::CanSee,
+                &file(2, "a.txt"),
+            )
+            .unwrap_err();
+        assert_eq!(denial.node_id, Some(1));
+        assert_eq!(denial.mask.as_deref(), Some("docs"));
+        assert!(vfs.can(
+            &Principal::anonymous(),
+            Permission::CanSee,
+            &file(2, "a.md")
+        ));
+    }
+
+    #[test]
+    fn globs_ignore_case_and_stop_at_slashes() {
+        let vfs = vfs(&[
+            (2, "*.TXT", None, r#"{"mime":"text/plain"}"#),
+            (2, "**/*.log", None, r#"{"mime":"text/log"}"#),
+        ]);
+        assert_eq!(patterns(&vfs, 2, "a.txt"), ["*.TXT"]);
+        assert_eq!(patterns(&vfs, 2, "A.Txt"), ["*.TXT"]);
+        assert!(patterns(&vfs, 2, "sub/a.txt").is_empty());
+        assert_eq!(patterns(&vfs, 2, "sub/deeper/a.LOG"), ["**/*.log"]);
+    }
+
+    #[test]
+    fn invalid_masks_are_skipped() {
+        let vfs = vfs(&[
+            (2, "a[", Some(9), r#"{"mime":"bad/glob"}"#),
+            (2, "*", Some(8), r#"{"bogus":true}"#),
+            (
+                2,
+                "sub",
+                None,
+                r#"{"masks":{"b[":{"mime":"bad/nested"},"*.txt":{"mime":"text/plain"}}}"#,
+            ),
+            (2, "*.txt", None, r#"{"mime":"text/top"}"#),
+        ]);
+        assert_eq!(patterns(&vfs, 2, "a[.txt"), ["*.txt"]);
+        assert_eq!(
+            vfs.props(&file(2, "sub/b.txt")).mime.as_deref(),
+            Some("text/plain")
+        );
+
+        let props = |json| serde_json::from_str::<MaskProps>(json).unwrap();
+        assert!(check("a[", &MaskProps::default()).is_err());
+        assert!(check("sub", &props(r#"{"masks":{"b[":{}}}"#)).is_err());
+        assert!(check("sub", &props(r#"{"masks":{"*.txt":{}}}"#)).is_ok());
+    }
+
+    #[test]
+    fn accept_is_inherited() {
+        let mut rows = rows(&[
+            (2, "sub", None, r#"{"accept":"text/*","mime":"inode/sub"}"#),
+            (1, "docs", None, r#"{"accept":"audio/*"}"#),
+        ]);
+        rows.nodes[0].accept = Some("image/*".to_string());
+        let vfs = Vfs::from_rows(rows);
+
+        assert_eq!(
+            vfs.props(&file(2, "sub/a.txt")),
+            EntryProps {
+                mime: None,
+                accept: Some("text/*".to_string()),
+                target: None,
+            }
+        );
+        // `docs` sets nothing itself, so the mask on `top` naming it applies.
+        assert_eq!(
+            vfs.props(&file(2, "a.txt")).accept.as_deref(),
+            Some("audio/*")
+        );
+        assert_eq!(
+            vfs.props(&file(1, "a.txt")).accept.as_deref(),
+            Some("image/*")
+        );
+    }
+}
@@ -0,0 +1,566 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+use tracing::warn;
+
+use crate::db::{JsonText, Timestamp};
+use crate::error::{Error, Result};
+use crate::net::{AccessList, NetMask};
+use crate::upload::is_temp_name;
+
+pub mod edit;
+pub mod host;
+pub mod list;
+pub mod mask;
+pub mod ops;
+pub mod perm;
+
+use mask::{CompiledMask, MaskProps, VfsMask};
+use perm::{Permission, VfsPermission, WhoCan};
+
+pub type NodeId = i64;
+
+/// A row of `vfs_nodes`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Deserialize, Serialize)]
+pub struct VfsNode {
+    pub id: NodeId,
+    pub parent_id: Option<NodeId>,
+    pub name: Option<String>,
+    pub source_path: Option<String>,
+    pub url: Option<String>,
+    pub mime: Option<String>,
+    pub ord: Option<i64>,
+    pub target: Option<String>,
+    pub accept: Option<String>,
+    pub default_child_id: Option<NodeId>,
+    pub default_child_path: Option<String>,
+    /// Network mask clients must match to reach this node and everything below it.
+    pub allow_net: Option<String>,
+    /// Network mask refused for this node and everything below it.
+    pub deny_net: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl VfsNode {
+    /// Name shown in listings and matched against URL segments.
+    ///
+    /// Falls back to the last component of `source_path`, then to the `url`.
+    pub fn display_name(&self) -> String {
+        if let Some(name) = self.name.as_deref().filter(|n| !n.is_empty()) {
+            return name.to_string();
+        }
+        if let Some(src) = &self.source_path
+            && let Some(base) = Path::new(src).file_name()
+        {
+            return base.to_string_lossy().into_owned();
+        }
+        self.url.clone().unwrap_or_default()
+    }
+
+    /// Link nodes point at an external `url` and have no disk source.
+    pub fn is_link(&self) -> bool {
+        self.url.is_some() && self.source_path.is_none()
+    }
+}
+
+/// A row of `vfs_node_renames`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Deserialize, Serialize)]
+pub struct VfsRename {
+    pub id: i64,
+    pub node_id: NodeId,
+    pub original_name: String,
+    pub new_name: String,
+}
+
+/// A row of `vfs_roots`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Deserialize, Serialize)]
+pub struct VfsRoot {
+    pub host_mask: String,
+    pub node_id: NodeId,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Folder,
+    File,
+    Link,
+}
+
+/// Result of resolving a URL path: either a node itself or a disk entry below one.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The node this entry is, or the nearest node it was read from.
+    pub node_id: NodeId,
+    /// Path below the node's `source_path` on disk; empty for the node itself.
+    pub rel_path: PathBuf,
+    /// Name as seen by clients (after renames).
+    pub name: String,
+    /// Canonical percent-encoded URL path; folders end with `/`.
+    pub url_path: String,
+    pub kind: EntryKind,
+    /// Absolute path on disk, if the entry is backed by one.
+    pub source: Option<PathBuf>,
+    /// External URL for link nodes.
+    pub url: Option<String>,
+    pub metadata: Option<std::fs::Metadata>,
+}
+
+impl Entry {
+    /// True when the entry is a `vfs_nodes` row rather than a disk child of one.
+    pub fn is_node(&self) -> bool {
+        self.rel_path.as_os_str().is_empty()
+    }
+
+    pub fn is_folder(&self) -> bool {
+        self.kind == EntryKind::Folder
+    }
+}
+
+/// Rows of the VFS tables, as loaded by [`Vfs::load`].
+#[derive(Debug, Clone, Default)]
+pub struct VfsRows {
+    pub nodes: Vec<VfsNode>,
+    pub renames: Vec<VfsRename>,
+    pub roots: Vec<VfsRoot>,
+    pub permissions: Vec<VfsPermission>,
+    pub masks: Vec<VfsMask>,
+}
+
+/// In-memory snapshot of the VFS tables.
+///
+/// The tree is small and read on every request, so we load it once and swap the
+/// whole snapshot after edits instead of querying per lookup.
+#[derive(Debug, Clone, Default)]
+pub struct Vfs {
+    nodes: HashMap<NodeId, VfsNode>,
+    /// Child ids per parent (`None` = top-level), sorted for display.
+    children: HashMap<Option<NodeId>, Vec<NodeId>>,
+    /// original name -> new name, per node.
+    renames: HashMap<NodeId, HashMap<String, String>>,
+    roots: Vec<VfsRoot>,
+    permissions: HashMap<NodeId, HashMap<Permission, WhoCan>>,
+    /// Compiled masks per node, highest priority first.
+    masks: HashMap<NodeId, Vec<CompiledMask>>,
+    /// Parsed `allow_net`/`deny_net` of the nodes that set them.
+    nets: HashMap<NodeId, AccessList>,
+}
+
+impl Vfs {
+    /// Load the whole VFS from the database.
+    pub async fn load(pool: &Pool<Sqlite>) -> Result<Self> {
+        let nodes = sqlx::query_as!(
+            VfsNode,
+            r#"SELECT id AS "id!", parent_id, name, source_path, url, mime, ord, target, accept,
+                      default_child_id, default_child_path, allow_net, deny_net,
+                      created_at AS "created_at: Timestamp", updated_at AS "updated_at: Timestamp"
+               FROM vfs_nodes"#
+        )
+        .fetch_all(pool)
+        .await?;
+        let renames = sqlx::query_as!(
+            VfsRename,
+            r#"SELECT id AS "id!", node_id, original_name, new_name FROM vfs_node_renames"#
+        )
+        .fetch_all(pool)
+        .await?;
+        let roots = sqlx::query_as!(
+            VfsRoot,
+            r#"SELECT host_mask AS "host_mask!", node_id FROM vfs_roots"#
+        )
+        .fetch_all(pool)
+        .await?;
+        let permissions = sqlx::query_as!(
+            VfsPermission,
+            r#"SELECT id AS "id!", node_id, permission AS "permission: Permission",
+                      who AS "who: JsonText<WhoCan>"
+               FROM vfs_node_permissions"#
+        )
+        .fetch_all(pool)
+        .await?;
+        let masks = sqlx::query_as!(
+            VfsMask,
+            r#"SELECT id AS "id!", node_id, mask, properties AS "properties: JsonText<MaskProps>",
+                      ord
+               FROM vfs_node_masks"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Self::from_rows(VfsRows {
+            nodes,
+            renames,
+            roots,
+            permissions,
+            masks,
+        }))
+    }
+
+    /// Build a snapshot from already-fetched rows.
+    ///
+    /// Rows that fail to parse are logged and skipped so one bad rule cannot
+    /// take the whole tree offline.
+    pub fn from_rows(rows: VfsRows) -> Self {
+        let VfsRows {
+            nodes,
+            renames,
+            roots,
+            permissions,
+            masks,
+        } = rows;
+
+        let mut children: HashMap<Option<NodeId>, Vec<NodeId>> = HashMap::new();
+        for n in &nodes {
+            children.entry(n.parent_id).or_default().push(n.id);
+        }
+        let nodes: HashMap<NodeId, VfsNode> = nodes.into_iter().map(|n| (n.id, n)).collect();
+        for ids in children.values_mut() {
+            ids.sort_by(|a, b| {
+                let (a, b) = (&nodes[a], &nodes[b]);
+                display_order(a, b)
+            });
+        }
+
+        let mut by_node: HashMap<NodeId, HashMap<String, String>> = HashMap::new();
+        for r in renames {
+            by_node
+                .entry(r.node_id)
+                .or_default()
+                .insert(r.original_name, r.new_name);
+        }
+
+        let mut rules: HashMap<NodeId, HashMap<Permission, WhoCan>> = HashMap::new();
+        for row in permissions {
+            let perm = row.permission;
+            match row.who.into_result() {
+                Ok(who) => {
+                    rules.entry(row.node_id).or_default().insert(perm, who);
+                }
+                Err(e) => warn!(node_id = row.node_id, %perm, "skipping invalid who: {e}"),
+            }
+        }
+
+        let nets = nodes
+            .values()
+            .filter(|n| n.allow_net.is_some() || n.deny_net.is_some())
+            .map(|n| (n.id, node_access_list(n)))
+            .collect();
+
+        Self {
+            nets,
+            nodes,
+            children,
+            renames: by_node,
+            roots,
+            permissions: rules,
+            masks: mask::compile_masks(masks),
+        }
+    }
+
+    /// Network restrictions set on a node itself.
+    pub fn node_nets(&self, id: NodeId) -> Option<&AccessList> {
+        self.nets.get(&id)
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&VfsNode> {
+        self.nodes.get(&id)
+    }
+
+    pub fn roots(&self) -> &[VfsRoot] {
+        &self.roots
+    }
+
+    /// Child nodes of `parent` (`None` = top-level) in display order.
+    pub fn child_nodes(&self, parent: Option<NodeId>) -> impl Iterator<Item = &VfsNode> {
+        self.children
+            .get(&parent)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.nodes.get(id))
+    }
+
+    /// The node followed by its ancestors up to the top of the tree.
+    ///
+    /// Stops early on a `parent_id` cycle instead of looping forever.
+    pub fn ancestors(&self, id: NodeId) -> Vec<&VfsNode> {
+        let mut out = Vec::new();
+        let mut seen = HashSet::new();
+        let mut cur = self.nodes.get(&id);
+        while let Some(n) = cur {
+            if !seen.insert(n.id) {
+                break;
+            }
+            out.push(n);
+            cur = n.parent_id.and_then(|p| self.nodes.get(&p));
+        }
+        out
+    }
+
+    /// Renames configured for the disk listing of `node`, as original -> new.
+    pub fn renames(&self, node: NodeId) -> Option<&HashMap<String, String>> {
+        self.renames.get(&node)
+    }
+
+    /// Name a disk entry under `node` is shown as.
+    pub fn renamed<'a>(&'a self, node: NodeId, original: &'a str) -> &'a str {
+        self.renames
+            .get(&node)
+            .and_then(|m| m.get(original))
+            .map(String::as_str)
+            .unwrap_or(original)
+    }
+
+    /// Map a client-visible name back to the disk name under `node`.
+    ///
+    /// Returns `None` when `name` is an original name that has been renamed away,
+    /// so the old name stops resolving.
+    fn original_name<'a>(&'a self, node: NodeId, name: &'a str) -> Option<&'a str> {
+        let Some(map) = self.renames.get(&node) else {
+            return Some(name);
+        };
+        if let Some((orig, _)) = map.iter().find(|(_, new)| new.as_str() == name) {
+            return Some(orig);
+        }
+        if map.contains_key(name) {
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Resolve `path` for a request addressed to `host`.
+    pub async fn resolve(&self, host: Option<&str>, path: &str) -> Result<Option<Entry>> {
+        let Some(root) = self.root_for_host(host) else {
+            return Ok(None);
+        };
+        self.resolve_from(root, path).await
+    }
+
+    /// Resolve a percent-encoded URL `path` starting at node `root`.
+    pub async fn resolve_from(&self, root: NodeId, path: &str) -> Result<Option<Entry>> {
+        let segments = split_path(path)?;
+        let Some(mut entry) = self.node_entry(root, "/".to_string()).await? else {
+            return Ok(None);
+        };
+        for seg in &segments {
+            match self.child(&entry, seg).await? {
+                Some(e) => entry = e,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(entry))
+    }
+
+    /// Look up the child `name` of a folder entry.
+    ///
+    /// Virtual child nodes shadow disk entries of the same name; anything not
+    /// listed as a node falls through to the parent's `source_path` on disk.
+    pub async fn child(&self, parent: &Entry, name: &str) -> Result<Option<Entry>> {
+        if !parent.is_folder() || !is_valid_segment(name) || is_temp_name(name) {
+            return Ok(None);
+        }
+
+        if parent.is_node()
+            && let Some(node) = self
+                .child_nodes(Some(parent.node_id))
+                .find(|n| n.display_name() == name)
+        {
+            let url_path = join_url(&parent.url_path, name);
+            return self.node_entry(node.id, url_path).await;
+        }
+
+        let Some(dir) = &parent.source else {
+            return Ok(None);
+        };
+        // Renames only apply to the direct listing of a node's source.
+        let disk_name = if parent.is_node() {
+            match self.original_name(parent.node_id, name) {
+                Some(orig) => orig,
+                None => return Ok(None),
+            }
+        } else {
+            name
+        };
+
+        let abs = dir.join(disk_name);
+        let Some(meta) = stat(&abs).await? else {
+            return Ok(None);
+        };
+        let kind = if meta.is_dir() {
+            EntryKind::Folder
+        } else {
+            EntryKind::File
+        };
+        let mut url_path = join_url(&parent.url_path, name);
+        if kind == EntryKind::Folder {
+            url_path.push('/');
+        }
+
+        Ok(Some(Entry {
+            node_id: parent.node_id,
+            rel_path: parent.rel_path.join(disk_name),
+            name: name.to_string(),
+            url_path,
+            kind,
+            source: Some(abs),
+            url: None,
+            metadata: Some(meta),
+        }))
+    }
+
+    /// Entry to serve in place of a folder, from `default_child_id` or `default_child_path`.
+    pub async fn default_child(&self, folder: &Entry) -> Result<Option<Entry>> {
+        if !folder.is_node() || !folder.is_folder() {
+            return Ok(None);
+        }
+        let Some(node) = self.nodes.get(&folder.node_id) else {
+            return Ok(None);
+        };
+
+        if let Some(id) = node.default_child_id
+            && let Some(child) = self.nodes.get(&id)
+        {
+            let url_path = join_url(&folder.url_path, &child.display_name());
+            return self.node_entry(id, url_path).await;
+        }
+        if let Some(rel) = &node.default_child_path {
+            let mut entry = folder.clone();
+            for seg in split_path(rel)? {
+                match self.child(&entry, &seg).await? {
+                    Some(e) => entry = e,
+                    None => return Ok(None),
+                }
+            }
+            return Ok(Some(entry));
+        }
+        Ok(None)
+    }
+
+    /// Build the entry for a node row, checking its `source_path` on disk.
+    ///
+    /// Returns `None` when the source no longer exists.
+    pub async fn node_entry(&self, id: NodeId, mut url_path: String) -> Result<Option<Entry>> {
+        let Some(node) = self.nodes.get(&id) else {
+            return Ok(None);
+        };
+        let name = node.display_name();
+
+        if node.is_link() {
+            return Ok(Some(Entry {
+                node_id: id,
+                rel_path: PathBuf::new(),
+                name,
+                url_path,
+                kind: EntryKind::Link,
+                source: None,
+                url: node.url.clone(),
+                metadata: None,
+            }));
+        }
+
+        let (kind, source, metadata) = match &node.source_path {
+            Some(src) => {
+                let abs = PathBuf::from(src);
+                let Some(meta) = stat(&abs).await? else {
+                    return Ok(None);
+                };
+                let kind = if meta.is_dir() {
+                    EntryKind::Folder
+                } else {
+                    EntryKind::File
+                };
+                (kind, Some(abs), Some(meta))
+            }
+            None => (EntryKind::Folder, None, None),
+        };
+        if kind == EntryKind::Folder && !url_path.ends_with('/') {
+            url_path.push('/');
+        }
+
+        Ok(Some(Entry {
+            node_id: id,
+            rel_path: PathBuf::new(),
+            name,
+            url_path,
+            kind,
+            source,
+            url: None,
+            metadata,
+        }))
+    }
+}
+
+/// A node's network lists; if either does not parse, everyone is shut out
+/// rather than silently let in.
+fn node_access_list(node: &VfsNode) -> AccessList {
+    let parse = |mask: &Option<String>| {
+        mask.as_deref()
+            .filter(|m| !m.trim().is_empty())
+            .map(str::parse::<NetMask>)
+            .transpose()
+    };
+    match (parse(&node.allow_net), parse(&node.deny_net)) {
+        (Ok(allow), Ok(deny)) => AccessList { allow, deny },
+        (Err(e), _) | (_, Err(e)) => {
+            warn!(node_id = node.id, "refusing all clients: {e}");
+            AccessList {
+                allow: Some(NetMask::default()),
+                deny: None,
+            }
+        }
+    }
+}
+
+/// Positive `ord` first (highest on top), then unordered, then negative; ties by name.
+fn display_order(a: &VfsNode, b: &VfsNode) -> std::cmp::Ordering {
+    let (oa, ob) = (a.ord.unwrap_or(0), b.ord.unwrap_or(0));
+    ob.cmp(&oa).then_with(|| {
+        a.display_name()
+            .to_lowercase()
+            .cmp(&b.display_name().to_lowercase())
+    })
+}
+
+/// Split a percent-encoded URL path into decoded segments.
+///
+/// Rejects `.`/`..` segments and anything that could smuggle a separator.
+pub fn split_path(path: &str) -> Result<Vec<String>> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut out = Vec::new();
+    for raw in path.split('/').filter(|s| !s.is_empty()) {
+        let seg = urlencoding::decode(raw)
+            .map_err(|_| Error::InvalidPath(path.to_string()))?
+            .into_owned();
+        if !is_valid_segment(&seg) {
+            return Err(Error::InvalidPath(path.to_string()));
+        }
+        out.push(seg);
+    }
+    Ok(out)
+}
+
+pub(crate) fn is_valid_segment(seg: &str) -> bool {
+    !seg.is_empty() && seg != "." && seg != ".." && !seg.contains(['/', '\\', '\0'])
+}
+
+/// Append a percent-encoded `name` to a URL path.
+pub fn join_url(base: &str, name: &str) -> String {
+    let mut out = String::with_capacity(base.len() + name.len() + 1);
+    out.push_str(base);
+    if !out.ends_with('/') {
+        out.push('/');
+    }
+    out.push_str(&urlencoding::encode(name));
+    out
+}
+
+pub(crate) async fn stat(path: &Path) -> Result<Option<std::fs::Metadata>> {
+    match tokio::fs::metadata(path).await {
+        Ok(m) => Ok(Some(m)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
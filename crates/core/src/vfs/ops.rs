@@ -0,0 +1,308 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use super::perm::{Denial, Permission};
+use super::{Entry, EntryKind, Vfs, join_url};
+use crate::account::Principal;
+use crate::error::{Error, Result};
+use crate::upload::is_temp_name;
+
+impl Vfs {
+    /// The `source_path` of the node an entry lives under.
+    pub fn source_root(&self, entry: &Entry) -> Option<&Path> {
+        self.node(entry.node_id)?
+            .source_path
+            .as_deref()
+            .map(Path::new)
+    }
+
+    /// Fail unless `path` stays inside the `source_path` of `entry`'s node once
+    /// `..` and symlinks in its parent folders are resolved.
+    ///
+    /// The last component is not followed, so a symlink itself can be renamed
+    /// or deleted without touching what it points to.
+    pub async fn confine(&self, entry: &Entry, path: &Path) -> Result<()> {
+        let root = self
+            .source_root(entry)
+            .ok_or_else(|| Error::InvalidPath(entry.url_path.clone()))?;
+        let root = tokio::fs::canonicalize(root).await?;
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(Error::OutsideRoot(path.to_path_buf()));
+        };
+        let resolved = tokio::fs::canonicalize(parent).await?.join(name);
+        if resolved == root || !resolved.starts_with(&root) {
+            return Err(Error::OutsideRoot(path.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    /// Like [`Vfs::check`], but also for everything on disk below a folder.
+    ///
+    /// Returns the first refusal found.
+    pub async fn check_tree(
+        &self,
+        who: &Principal,
+        perm: Permission,
+        entry: &Entry,
+    ) -> Result<std::result::Result<(), Denial>> {
+        if let Err(d) = self.check(who, perm, entry) {
+            return Ok(Err(d));
+        }
+        let (EntryKind::Folder, Some(source)) = (entry.kind, &entry.source) else {
+            return Ok(Ok(()));
+        };
+        if tokio::fs::symlink_metadata(source).await?.is_symlink() {
+            return Ok(Ok(()));
+        }
+
+        // Everything `remove`, `move_path` and `copy_path` act on: no ignore
+        // files, and symlinks are checked themselves but not followed.
+        let mut pending = vec![(
+            source.clone(),
+            entry.rel_path.clone(),
+            entry.url_path.clone(),
+        )];
+        while let Some((dir, rel_path, url_path)) = pending.pop() {
+            let mut children = tokio::fs::read_dir(&dir).await?;
+            while let Some(child) = children.next_entry().await? {
+                let path = child.path();
+                let meta = tokio::fs::symlink_metadata(&path).await?;
+                let disk = child.file_name().to_string_lossy().into_owned();
+                // Renames apply to the first level below a node.
+                let name = if rel_path.as_os_str().is_empty() {
+                    self.renamed(entry.node_id, &disk).to_string()
+                } else {
+                    disk
+                };
+                let kind = if meta.is_dir() {
+                    EntryKind::Folder
+                } else {
+                    EntryKind::File
+                };
+                let mut child_url = join_url(&url_path, &name);
+                if kind == EntryKind::Folder {
+                    child_url.push('/');
+                }
+                let child = Entry {
+                    node_id: entry.node_id,
+                    rel_path: rel_path.join(child.file_name()),
+                    name,
+                    url_path: child_url,
+                    kind,
+                    source: Some(path.clone()),
+                    url: None,
+                    metadata: Some(meta),
+                };
+                if let Err(d) = self.check(who, perm, &child) {
+                    return Ok(Err(d));
+                }
+                if child.is_folder() {
+                    pending.push((path, child.rel_path, child.url_path));
+                }
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// Reject names that are not a single plain path segment.
+pub fn validate_name(name: &str) -> Result<&str> {
+    let trimmed = name.trim();
+    if trimmed.is_empty()
+        || trimmed == "."
+        || trimmed == ".."
+        || trimmed.contains(['/', '\\', '\0'])
+        || is_temp_name(trimmed)
+    {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    Ok(trimmed)
+}
+
+/// Delete a file, a symlink (not its target) or a folder.
+///
+/// Non-empty folders need `recursive`.
+pub async fn remove(path: &Path, recursive: bool) -> Result<()> {
+    let meta = tokio::fs::symlink_metadata(path).await?;
+    if !meta.is_dir() {
+        tokio::fs::remove_file(path).await?;
+    } else if recursive {
+        tokio::fs::remove_dir_all(path).await?;
+    } else {
+        match tokio::fs::remove_dir(path).await {
+            Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                return Err(Error::NotEmpty(path.to_path_buf()));
+            }
+            other => other?,
+        }
+    }
+    Ok(())
+}
+
+/// Create a folder; fails if anything already has that name.
+pub async fn make_dir(path: &Path) -> Result<()> {
+    match tokio::fs::create_dir(path).await {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            Err(Error::AlreadyExists(path.to_path_buf()))
+        }
+        other => Ok(other?),
+    }
+}
+
+/// Move `from` to `to`, copying then deleting when they are on different devices.
+pub async fn move_path(from: &Path, to: &Path) -> Result<()> {
+    ensure_free(to).await?;
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_path(from, to).await?;
+            remove(from, true).await
+        }
+        other => Ok(other?),
+    }
+}
+
+/// Copy a file or a folder tree.
+///
+/// Symlinks are copied as symlinks, not followed, so the copy holds what
+/// [`Vfs::check_tree`] checked. Nothing is left at `to` when the copy fails.
+pub async fn copy_path(from: &Path, to: &Path) -> Result<()> {
+    ensure_free(to).await?;
+    let copied = copy_tree(from, to).await;
+    if copied.is_err()
+        && let Err(e) = remove(to, true).await
+        && !matches!(&e, Error::Io(e) if e.kind() == io::ErrorKind::NotFound)
+    {
+        warn!(path = %to.display(), "cannot remove a partial copy: {e}");
+    }
+    copied
+}
+
+async fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((src, dst)) = pending.pop() {
+        let meta = tokio::fs::symlink_metadata(&src).await?;
+        if meta.is_symlink() {
+            copy_link(&src, &dst).await?;
+            continue;
+        }
+        if !meta.is_dir() {
+            tokio::fs::copy(&src, &dst).await?;
+            continue;
+        }
+        tokio::fs::create_dir(&dst).await?;
+        let mut dir = tokio::fs::read_dir(&src).await?;
+        while let Some(child) = dir.next_entry().await? {
+            let name = child.file_name();
+            if is_temp_name(&name.to_string_lossy()) {
+                continue;
+            }
+            pending.push((child.path(), dst.join(name)));
+        }
+    }
+    Ok(())
+}
+
+/// Make `to` a symlink with the same target as the symlink `from`.
+async fn copy_link(from: &Path, to: &Path) -> Result<()> {
+    let target = tokio::fs::read_link(from).await?;
+    #[cfg(unix)]
+    tokio::fs::symlink(&target, to).await?;
+    #[cfg(windows)]
+    if tokio::fs::metadata(from).await.is_ok_and(|m| m.is_dir()) {
+        tokio::fs::symlink_dir(&target, to).await?;
+    } else {
+        tokio::fs::symlink_file(&target, to).await?;
+    }
+    Ok(())
+}
+
+async fn ensure_free(path: &Path) -> Result<()> {
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(_) => Err(Error::AlreadyExists(PathBuf::from(path))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::os::unix::net::UnixListener;
+
+    use super::*;
+
+    fn is_link(path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink())
+    }
+
+    #[tokio::test]
+    async fn symlinks_are_copied_not_followed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::create_dir_all(root.join("private")).unwrap();
+        fs::write(root.join("private/secret"), "s").unwrap();
+        fs::create_dir(root.join("public")).unwrap();
+        fs::write(root.join("public/a"), "a").unwrap();
+        symlink("../private", root.join("public/link")).unwrap();
+
+        copy_path(&root.join("public"), &root.join("copy"))
+            .await
+            .unwrap();
+        assert_eq!(fs::read_to_string(root.join("copy/a")).unwrap(), "a");
+        assert!(is_link(&root.join("copy/link")));
+        assert_eq!(
+            fs::read_link(root.join("copy/link")).unwrap(),
+            Path::new("../private")
+        );
+
+        // A symlink given as the source is copied as one too.
+        copy_path(&root.join("public/link"), &root.join("link"))
+            .await
+            .unwrap();
+        assert!(is_link(&root.join("link")));
+    }
+
+    #[tokio::test]
+    async fn symlink_loops_are_copied_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir(&src).unwrap();
+        symlink(".", src.join("self")).unwrap();
+        symlink("..", src.join("up")).unwrap();
+
+        let dst = tmp.path().join("dst");
+        copy_path(&src, &dst).await.unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dst)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["self", "up"]);
+        assert!(is_link(&dst.join("self")) && is_link(&dst.join("up")));
+    }
+
+    #[tokio::test]
+    async fn failed_copy_leaves_nothing_behind() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/a"), "a").unwrap();
+        // Sockets cannot be opened for reading.
+        let _sock = UnixListener::bind(src.join("sock")).unwrap();
+
+        let dst = tmp.path().join("dst");
+        assert!(copy_path(&src, &dst).await.is_err());
+        assert!(fs::symlink_metadata(&dst).is_err());
+
+        // What was there before is left alone.
+        fs::write(&dst, "kept").unwrap();
+        assert!(matches!(
+            copy_path(&src, &dst).await,
+            Err(Error::AlreadyExists(_))
+        ));
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "kept");
+    }
+}
@@ -0,0 +1,654 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, FromRow, Sqlite, Type};
+
+use super::mask::Level;
+use super::{Entry, NodeId, Vfs};
+use crate::account::Principal;
+use crate::db::JsonText;
+
+/// The permissions stored in `vfs_node_permissions.permission`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    CanRead,
+    CanSee,
+    CanUpload,
+    CanList,
+    CanArchive,
+    CanDelete,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::CanRead,
+        Permission::CanSee,
+        Permission::CanUpload,
+        Permission::CanList,
+        Permission::CanArchive,
+        Permission::CanDelete,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::CanRead => "can_read",
+            Permission::CanSee => "can_see",
+            Permission::CanUpload => "can_upload",
+            Permission::CanList => "can_list",
+            Permission::CanArchive => "can_archive",
+            Permission::CanDelete => "can_delete",
+        }
+    }
+
+    /// Rule applied when neither the node nor any ancestor sets one.
+    pub fn default_rule(self) -> WhoCan {
+        match self {
+            Permission::CanRead => WhoCan::Bool(true),
+            Permission::CanSee | Permission::CanList | Permission::CanArchive => {
+                WhoCan::Ref(Permission::CanRead)
+            }
+            Permission::CanUpload | Permission::CanDelete => WhoCan::Bool(false),
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Stored as its name; the schema only admits known names.
+impl Type<Sqlite> for Permission {
+    fn type_info() -> SqliteTypeInfo {
+        <str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <str as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Sqlite> for Permission {
+    fn encode_by_ref(
+        &self,
+        buf: &mut Vec<SqliteArgumentValue<'_>>,
+    ) -> std::result::Result<IsNull, BoxDynError> {
+        Encode::<Sqlite>::encode(self.as_str(), buf)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Permission {
+    fn decode(value: SqliteValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        Ok(<&str as Decode<Sqlite>>::decode(value)?.parse()?)
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| format!("unknown permission {s:?}"))
+    }
+}
+
+/// Who is granted a permission, as stored in `vfs_node_permissions.who`.
+///
+/// JSON forms: `true`, `false`, `"*"`, `["user", "group"]`, `"can_read"`
+/// or `{"this": WhoCan, "children": WhoCan}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "RawWhoCan", into = "RawWhoCan")]
+pub enum WhoCan {
+    /// Everyone (`true`) or no one (`false`).
+    Bool(bool),
+    /// Any logged-in account.
+    Any,
+    /// These usernames or group names.
+    Accounts(Vec<String>),
+    /// Same as another permission on the same entry.
+    Ref(Permission),
+    /// Different rules for the node itself and for everything below it.
+    /// A missing side is inherited from the parent.
+    Split {
+        this: Option<Box<WhoCan>>,
+        children: Option<Box<WhoCan>>,
+    },
+}
+
+impl WhoCan {
+    /// The part of the rule that applies to the node carrying it.
+    pub fn for_this(&self) -> Option<&WhoCan> {
+        match self {
+            WhoCan::Split { this, .. } => this.as_deref().and_then(WhoCan::for_this),
+            other => Some(other),
+        }
+    }
+
+    /// The part of the rule that applies to descendants of the node carrying it.
+    pub fn for_children(&self) -> Option<&WhoCan> {
+        match self {
+            WhoCan::Split { children, .. } => children.as_deref().and_then(WhoCan::for_children),
+            other => Some(other),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawWhoCan {
+    Bool(bool),
+    Str(String),
+    List(Vec<String>),
+    Split {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        this: Option<Box<WhoCan>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        children: Option<Box<WhoCan>>,
+    },
+}
+
+impl TryFrom<RawWhoCan> for WhoCan {
+    type Error = String;
+
+    fn try_from(raw: RawWhoCan) -> std::result::Result<Self, Self::Error> {
+        Ok(match raw {
+            RawWhoCan::Bool(b) => WhoCan::Bool(b),
+            RawWhoCan::Str(s) if s == "*" => WhoCan::Any,
+            RawWhoCan::Str(s) => WhoCan::Ref(s.parse()?),
+            RawWhoCan::List(names) => WhoCan::Accounts(names),
+            RawWhoCan::Split { this, children } => WhoCan::Split { this, children },
+        })
+    }
+}
+
+impl From<WhoCan> for RawWhoCan {
+    fn from(who: WhoCan) -> Self {
+        match who {
+            WhoCan::Bool(b) => RawWhoCan::Bool(b),
+            WhoCan::Any => RawWhoCan::Str("*".to_string()),
+            WhoCan::Accounts(names) => RawWhoCan::List(names),
+            WhoCan::Ref(p) => RawWhoCan::Str(p.as_str().to_string()),
+            WhoCan::Split { this, children } => RawWhoCan::Split { this, children },
+        }
+    }
+}
+
+/// A row of `vfs_node_permissions`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Deserialize, Serialize)]
+pub struct VfsPermission {
+    pub id: i64,
+    pub node_id: NodeId,
+    pub permission: Permission,
+    pub who: JsonText<WhoCan>,
+}
+
+/// Why a permission check failed, in a form admins can display.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Denial {
+    pub permission: Permission,
+    /// Node whose rule decided; `None` when the built-in default applied.
+    pub node_id: Option<NodeId>,
+    pub node_name: Option<String>,
+    /// Mask pattern that carried the rule, if it did not come from the node itself.
+    pub mask: Option<String>,
+    /// Permissions followed through `"can_…"` references to reach the deciding rule.
+    pub via: Vec<Permission>,
+    pub reason: DenyReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DenyReason {
+    /// The rule is `false`.
+    Nobody,
+    /// The rule needs a logged-in account.
+    LoginRequired,
+    /// The account is not listed, directly or through a group.
+    NotListed { allowed: Vec<String> },
+    /// `"can_…"` references loop back on themselves.
+    ReferenceLoop,
+    /// The client's address is outside the node's `allow_net` or inside its `deny_net`.
+    Network,
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "denied because ")?;
+        let decided = self.via.last().copied().unwrap_or(self.permission);
+        match &self.reason {
+            DenyReason::Nobody => write!(f, "{decided} is disabled")?,
+            DenyReason::LoginRequired => write!(f, "{decided} requires login")?,
+            DenyReason::NotListed { allowed } => {
+                write!(f, "{decided} is limited to {}", allowed.join(", "))?
+            }
+            DenyReason::ReferenceLoop => {
+                write!(f, "{} is defined in terms of itself", self.permission)?
+            }
+            DenyReason::Network => write!(f, "access from this address is not allowed")?,
+        }
+        match (&self.node_name, self.node_id) {
+            (Some(name), Some(id)) => write!(f, " on '{name}' (node {id})")?,
+            (None, Some(id)) => write!(f, " on node {id}")?,
+            _ => write!(f, " by default")?,
+        }
+        if let Some(mask) = &self.mask {
+            write!(f, " via mask '{mask}'")?;
+        }
+        if !self.via.is_empty() {
+            let chain: Vec<&str> = self.via.iter().map(|p| p.as_str()).collect();
+            write!(f, " ({} follows {})", self.permission, chain.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A rule and where it came from (`None` = built-in default).
+struct Rule<'a> {
+    who: Cow<'a, WhoCan>,
+    node_id: Option<NodeId>,
+    mask: Option<&'a str>,
+}
+
+impl Vfs {
+    /// Whether `who` has `perm` on `entry`.
+    pub fn can(&self, who: &Principal, perm: Permission, entry: &Entry) -> bool {
+        self.check(who, perm, entry).is_ok()
+    }
+
+    /// Every permission `who` holds on `entry`.
+    pub fn granted(&self, who: &Principal, entry: &Entry) -> Vec<Permission> {
+        Permission::ALL
+            .into_iter()
+            .filter(|p| self.can(who, *p, entry))
+            .collect()
+    }
+
+    /// Like [`Vfs::can`], but explains a refusal.
+    pub fn check(
+        &self,
+        who: &Principal,
+        perm: Permission,
+        entry: &Entry,
+    ) -> std::result::Result<(), Denial> {
+        if let Some(node) = self
+            .ancestors(entry.node_id)
+            .into_iter()
+            .find(|n| self.node_nets(n.id).is_some_and(|l| !l.permits(who.ip)))
+        {
+            return Err(Denial {
+                permission: perm,
+                node_id: Some(node.id),
+                node_name: Some(node.display_name()),
+                mask: None,
+                via: Vec::new(),
+                reason: DenyReason::Network,
+            });
+        }
+
+        let levels = self.levels(entry);
+        let mut via = Vec::new();
+        let mut current = perm;
+        loop {
+            let rule = self.rule_for(current, &levels);
+            let deny = |reason| Denial {
+                permission: perm,
+                node_id: rule.node_id,
+                node_name: rule
+                    .node_id
+                    .and_then(|id| self.node(id))
+                    .map(|n| n.display_name()),
+                mask: rule.mask.map(str::to_string),
+                via: via.clone(),
+                reason,
+            };
+            match rule.who.as_ref() {
+                WhoCan::Bool(true) => return Ok(()),
+                WhoCan::Bool(false) => return Err(deny(DenyReason::Nobody)),
+                WhoCan::Any if who.is_anonymous() => return Err(deny(DenyReason::LoginRequired)),
+                WhoCan::Any => return Ok(()),
+                WhoCan::Accounts(names) if names.iter().any(|n| who.is(n)) => return Ok(()),
+                WhoCan::Accounts(_) if who.is_anonymous() => {
+                    return Err(deny(DenyReason::LoginRequired));
+                }
+                WhoCan::Accounts(names) => {
+                    return Err(deny(DenyReason::NotListed {
+                        allowed: names.clone(),
+                    }));
+                }
+                WhoCan::Ref(next) => {
+                    if *next == perm || via.contains(next) {
+                        return Err(deny(DenyReason::ReferenceLoop));
+                    }
+                    via.push(*next);
+                    current = *next;
+                }
+                // `rule_for` only returns resolved sides of a split.
+                WhoCan::Split { .. } => return Err(deny(DenyReason::Nobody)),
+            }
+        }
+    }
+
+    /// Explicit rules of a node, as parsed from `vfs_node_permissions`.
+    pub fn node_rule(&self, node: NodeId, perm: Permission) -> Option<&WhoCan> {
+        self.permissions.get(&node).and_then(|m| m.get(&perm))
+    }
+
+    /// Find the rule in force for `perm` given the entry's [`Vfs::levels`].
+    ///
+    /// The entry itself contributes the `this` side of its rules; every level
+    /// above it contributes the `children` side. At each level a node's own rule
+    /// wins over masks.
+    fn rule_for<'a>(&'a self, perm: Permission, levels: &[Level<'a>]) -> Rule<'a> {
+        for (i, level) in levels.iter().enumerate() {
+            let side = |who: &'a WhoCan| {
+                if i == 0 {
+                    who.for_this()
+                } else {
+                    who.for_children()
+                }
+            };
+            if let Some(node) = level.node
+                && let Some(who) = self.node_rule(node.id, perm).and_then(side)
+            {
+                return Rule {
+                    who: Cow::Borrowed(who),
+                    node_id: Some(node.id),
+                    mask: None,
+                };
+            }
+            for hit in &level.masks {
+                if let Some(who) = hit.props.permission(perm).and_then(side) {
+                    return Rule {
+                        who: Cow::Borrowed(who),
+                        node_id: Some(hit.node_id),
+                        mask: Some(hit.pattern),
+                    };
+                }
+            }
+        }
+        Rule {
+            who: Cow::Owned(perm.default_rule()),
+            node_id: None,
+            mask: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::db::Timestamp;
+    use crate::vfs::{EntryKind, VfsNode, VfsRows};
+
+    /// Nodes `/top/mid/leaf` (ids 1 to 3) with `rules` as (node, permission, JSON).
+    fn vfs(rules: &[(NodeId, Permission, &str)]) -> Vfs {
+        let nodes = ["top", "mid", "leaf"]
+            .into_iter()
+            .zip(1..)
+            .map(|(name, id)| VfsNode {
+                id,
+                parent_id: (id > 1).then(|| id - 1),
+                name: Some(name.to_string()),
+                source_path: None,
+                url: None,
+                mime: None,
+                ord: None,
+                target: None,
+                accept: None,
+                default_child_id: None,
+                default_child_path: None,
+                allow_net: None,
+                deny_net: None,
+                created_at: Timestamp::now(),
+                updated_at: Timestamp::now(),
+            })
+            .collect();
+        let permissions = rules
+            .iter()
+            .zip(1..)
+            .map(|(&(node_id, permission, who), id)| VfsPermission {
+                id,
+                node_id,
+                permission,
+                who: JsonText::parse(who),
+            })
+            .collect();
+        Vfs::from_rows(VfsRows {
+            nodes,
+            permissions,
+            ..Default::default()
+        })
+    }
+
+    /// The node itself, or the disk file `rel` below it.
+    fn entry(node_id: NodeId, rel: &str) -> Entry {
+        Entry {
+            node_id,
+            rel_path: PathBuf::from(rel),
+            name: String::new(),
+            url_path: "/".to_string(),
+            kind: if rel.is_empty() {
+                EntryKind::Folder
+            } else {
+                EntryKind::File
+            },
+            source: None,
+            url: None,
+            metadata: None,
+        }
+    }
+
+    fn account(name: &str, groups: &[&str]) -> Principal {
+        Principal {
+            id: Some(1),
+            username: Some(name.to_string()),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn who_can_json_forms() {
+        let split = |this: Option<WhoCan>, children: Option<WhoCan>| WhoCan::Split {
+            this: this.map(Box::new),
+            children: children.map(Box::new),
+        };
+        let forms = [
+            ("true", WhoCan::Bool(true)),
+            ("false", WhoCan::Bool(false)),
+            (r#""*""#, WhoCan::Any),
+            (
+                r#"["alice","staff"]"#,
+                WhoCan::Accounts(vec!["alice".into(), "staff".into()]),
+            ),
+            (r#""can_read""#, WhoCan::Ref(Permission::CanRead)),
+            (
+                r#"{"this":false,"children":"*"}"#,
+                split(Some(WhoCan::Bool(false)), Some(WhoCan::Any)),
+            ),
+            (
+                r#"{"children":["bob"]}"#,
+                split(None, Some(WhoCan::Accounts(vec!["bob".into()]))),
+            ),
+        ];
+        for (json, who) in forms {
+            assert_eq!(serde_json::from_str::<WhoCan>(json).unwrap(), who, "{json}");
+            assert_eq!(serde_json::to_string(&who).unwrap(), json);
+        }
+        assert!(serde_json::from_str::<WhoCan>(r#""can_fly""#).is_err());
+        assert!(serde_json::from_str::<WhoCan>("1").is_err());
+    }
+
+    #[test]
+    fn split_rules_apply_by_side() {
+        let vfs = vfs(&[
+            (1, Permission::CanUpload, r#"{"children":["alice"]}"#),
+            (2, Permission::CanUpload, r#"{"this":true}"#),
+        ]);
+        let (alice, bob) = (account("alice", &[]), account("bob", &[]));
+
+        // No `this` side on the top node and nothing above it: the default.
+        let err = vfs
+            .check(&alice, Permission::CanUpload, &entry(1, ""))
+            .unwrap_err();
+        assert_eq!((err.node_id, err.reason), (None, DenyReason::Nobody));
+
+        assert!(vfs.can(
+            &Principal::anonymous(),
+            Permission::CanUpload,
+            &entry(2, "")
+        ));
+
+        // `mid` has no `children` side, so `top`'s applies below it.
+        for e in [entry(3, ""), entry(3, "docs/a.txt")] {
+            assert!(vfs.can(&alice, Permission::CanUpload, &e));
+            let err = vfs.check(&bob, Permission::CanUpload, &e).unwrap_err();
+            assert_eq!(err.node_id, Some(1));
+            assert_eq!(
+                err.reason,
+                DenyReason::NotListed {
+                    allowed: vec!["alice".into()]
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn references_are_followed() {
+        let vfs = vfs(&[
+            (1, Permission::CanRead, r#"["alice"]"#),
+            (1, Permission::CanList, r#""can_read""#),
+            (2, Permission::CanRead, "true"),
+        ]);
+        let bob = account("bob", &[]);
+
+        let err = vfs
+            .check(&bob, Permission::CanList, &entry(1, ""))
+            .unwrap_err();
+        assert_eq!(err.via, vec![Permission::CanRead]);
+        assert_eq!(err.node_id, Some(1));
+
+        // `can_list` is still set on `top`, but `can_read` is looked up afresh
+        // and `mid` opens it.
+        assert!(vfs.can(&bob, Permission::CanList, &entry(3, "")));
+        // `can_see` follows `can_read` by default.
+        assert!(vfs.can(&bob, Permission::CanSee, &entry(2, "")));
+    }
+
+    #[test]
+    fn reference_loops_are_denied() {
+        let vfs = vfs(&[
+            (1, Permission::CanSee, r#""can_list""#),
+            (1, Permission::CanList, r#""can_see""#),
+            (1, Permission::CanArchive, r#""can_see""#),
+        ]);
+        let alice = account("alice", &[]);
+
+        let err = vfs
+            .check(&alice, Permission::CanSee, &entry(3, ""))
+            .unwrap_err();
+        assert_eq!(err.reason, DenyReason::ReferenceLoop);
+        assert_eq!(err.via, vec![Permission::CanList]);
+
+        // A loop further down the chain than the permission asked for.
+        let err = vfs
+            .check(&alice, Permission::CanArchive, &entry(3, ""))
+            .unwrap_err();
+        assert_eq!(err.reason, DenyReason::ReferenceLoop);
+        assert_eq!(err.via, vec![Permission::CanSee, Permission::CanList]);
+    }
+
+    #[test]
+    fn accounts_match_through_groups() {
+        let vfs = vfs(&[
+            (1, Permission::CanRead, r#"["staff"]"#),
+            (1, Permission::CanDelete, r#""*""#),
+        ]);
+        let top = entry(1, "");
+
+        assert!(vfs.can(&account("alice", &["staff"]), Permission::CanRead, &top));
+        assert!(vfs.can(&account("staff", &[]), Permission::CanRead, &top));
+        assert_eq!(
+            vfs.check(&account("bob", &["guests"]), Permission::CanRead, &top)
+                .unwrap_err()
+                .reason,
+            DenyReason::NotListed {
+                allowed: vec!["staff".into()]
+            }
+        );
+        assert_eq!(
+            vfs.check(&Principal::anonymous(), Permission::CanRead, &top)
+                .unwrap_err()
+                .reason,
+            DenyReason::LoginRequired
+        );
+
+        assert!(vfs.can(&account("bob", &[]), Permission::CanDelete, &top));
+        assert_eq!(
+            vfs.check(&Principal::anonymous(), Permission::CanDelete, &top)
+                .unwrap_err()
+                .reason,
+            DenyReason::LoginRequired
+        );
+    }
+
+    #[test]
+    fn denials_explain_themselves() {
+        let vfs = vfs(&[
+            (1, Permission::CanRead, r#"["alice","staff"]"#),
+            (2, Permission::CanSee, r#""can_list""#),
+            (2, Permission::CanList, r#""can_see""#),
+        ]);
+        let bob = account("bob", &[]);
+        let denial = |perm, node| vfs.check(&bob, perm, &entry(node, "")).unwrap_err();
+
+        assert_eq!(
+            denial(Permission::CanUpload, 1).to_string(),
+            "denied because can_upload is disabled by default"
+        );
+        assert_eq!(
+            denial(Permission::CanRead, 1).to_string(),
+            "denied because can_read is limited to alice, staff on 'top' (node 1)"
+        );
+        assert_eq!(
+            denial(Permission::CanArchive, 1).to_string(),
+            "denied because can_read is limited to alice, staff on 'top' (node 1) \
+             (can_archive follows can_read)"
+        );
+        assert_eq!(
+            denial(Permission::CanSee, 2).to_string(),
+            "denied because can_see is defined in terms of itself on 'mid' (node 2) \
+             (can_see follows can_list)"
+        );
+        assert_eq!(
+            vfs.check(&Principal::anonymous(), Permission::CanRead, &entry(1, ""))
+                .unwrap_err()
+                .to_string(),
+            "denied because can_read requires login on 'top' (node 1)"
+        );
+
+        let network = Denial {
+            permission: Permission::CanRead,
+            node_id: Some(2),
+            node_name: None,
+            mask: Some("*.txt".to_string()),
+            via: Vec::new(),
+            reason: DenyReason::Network,
+        };
+        assert_eq!(
+            network.to_string(),
+            "denied because access from this address is not allowed on node 2 via mask '*.txt'"
+        );
+    }
+}
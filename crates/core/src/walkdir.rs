@@ -69,6 +69,11 @@ pub struct WalkOptions {
     pub parallelize_recursion: bool,
     /// Number of threads to use when parallelizing.
     pub max_concurrency: usize,
+    /// Descend into directories that symlinks point to, wherever they are.
+    pub follow_links: bool,
+    /// Skip entries matched by .gitignore, .ignore and similar files, in the
+    /// tree and in its parents.
+    pub ignore_files: bool,
 }
 
 impl Default for WalkOptions {
@@ -78,6 +83,8 @@ impl Default for WalkOptions {
             include_hidden: true,
             parallelize_recursion: true,
             max_concurrency: 8,
+            follow_links: true,
+            ignore_files: true,
         }
     }
 }
@@ -167,14 +174,11 @@ where
         let in_tx_p = in_tx.clone();
         tokio::task::spawn_blocking(move || {
             let mut b = WalkBuilder::new(&root_p);
-            // Respect .gitignore and .ignore files by default.
-            b.git_ignore(true)
-                .git_global(true)
-                .git_exclude(true)
-                .parents(true)
-                .follow_links(true)
+            // Turns every ignore-file filter on or off, hidden files included;
+            // `hidden` must come after it.
+            b.standard_filters(opts.ignore_files)
                 .hidden(!opts.include_hidden)
-                .standard_filters(true)
+                .follow_links(opts.follow_links)
                 .max_depth(max_depth_opt)
                 .threads(threads);
 
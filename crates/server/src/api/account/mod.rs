@@ -0,0 +1,123 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use ferri_core::account::{self, Principal};
+use ferri_core::error::Error as CoreError;
+use ferri_core::session::{self, SessionRow};
+use ferri_core::srp::StoredVerifier;
+use serde::{Deserialize, Serialize};
+
+use crate::api::auth::srp::{self, FinishRequest};
+use crate::error::{ApiError, ApiResult};
+use crate::host::ClientIp;
+use crate::session::{CurrentSession, forget};
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/account/sessions", get(list_sessions))
+        .route("/account/sessions/revoke", post(revoke_sessions))
+        .route("/account/password", post(change_password))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub row: SessionRow,
+    /// The session this request was made with.
+    pub current: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    /// `id_hash` values from the session list.
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// Also revoke every session but the current one.
+    #[serde(default)]
+    pub others: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevokeResult {
+    pub revoked: u64,
+}
+
+/// `GET /api/account/sessions`; the caller's sessions, most recent first.
+async fn list_sessions(
+    State(state): State<AppState>,
+    current: Option<Extension<CurrentSession>>,
+    who: Option<Extension<Principal>>,
+) -> ApiResult<Json<Vec<SessionInfo>>> {
+    let (current, account_id) = logged_in(current, who)?;
+    let rows = session::list(&state.db, account_id).await?;
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| SessionInfo {
+                current: row.id_hash == current.id_hash,
+                row,
+            })
+            .collect(),
+    ))
+}
+
+/// `POST /api/account/sessions/revoke`; log out other devices.
+async fn revoke_sessions(
+    State(state): State<AppState>,
+    current: Option<Extension<CurrentSession>>,
+    who: Option<Extension<Principal>>,
+    Json(req): Json<RevokeRequest>,
+) -> ApiResult<Json<RevokeResult>> {
+    let (current, account_id) = logged_in(current, who)?;
+    let mut revoked = session::revoke(&state.db, account_id, &req.ids).await?;
+    if req.others {
+        revoked += session::revoke_others(&state.db, account_id, &current.id_hash).await?;
+    }
+    req.ids.iter().for_each(|id| forget(id));
+    Ok(Json(RevokeResult { revoked }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordRequest {
+    /// Proof of the current password: an exchange begun with
+    /// `POST /api/auth/srp/start` for the caller's username.
+    pub current: FinishRequest,
+    /// New SRP salt, hex.
+    pub salt: String,
+    /// New SRP verifier, hex; computed by the client, the password never leaves it.
+    pub verifier: String,
+}
+
+/// `POST /api/account/password`; set a new password and end the caller's
+/// other sessions.
+///
+/// A session alone is not enough: the current password must be proven too,
+/// so a stolen cookie cannot lock the owner out.
+async fn change_password(
+    State(state): State<AppState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    current: Option<Extension<CurrentSession>>,
+    who: Option<Extension<Principal>>,
+    Json(req): Json<PasswordRequest>,
+) -> ApiResult<StatusCode> {
+    let (current, account_id) = logged_in(current, who)?;
+    let verifier = StoredVerifier::from_hex(&req.salt, &req.verifier)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    if srp::prove(&req.current, ip)?.account_id != account_id {
+        return Err(CoreError::AuthFailed.into());
+    }
+    account::change_password(&state.db, account_id, &verifier).await?;
+    session::revoke_others(&state.db, account_id, &current.id_hash).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn logged_in(
+    current: Option<Extension<CurrentSession>>,
+    who: Option<Extension<Principal>>,
+) -> ApiResult<(CurrentSession, i64)> {
+    match (current, who.and_then(|Extension(w)| w.id)) {
+        (Some(Extension(current)), Some(id)) => Ok((current, id)),
+        _ => Err(ApiError::LoginRequired),
+    }
+}
@@ -0,0 +1,344 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use ferri_core::account::{
+    self, AccountChanges, AccountFilter, AccountRecord, AccountRef, Principal, set_verifier,
+};
+use ferri_core::audit::{self, Target};
+use ferri_core::error::Error as CoreError;
+use ferri_core::session;
+use ferri_core::srp::StoredVerifier;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use sqlx::SqliteConnection;
+
+use super::{Admin, Page, PageQuery};
+use crate::api::account::PasswordRequest;
+use crate::error::{ApiError, ApiResult};
+use crate::state::AppState;
+
+/// An account with its direct memberships.
+#[derive(Debug, Serialize)]
+pub struct AccountDetail {
+    #[serde(flatten)]
+    pub record: AccountRecord,
+    /// Groups it belongs to directly.
+    pub groups: Vec<AccountRef>,
+    /// For a group, its direct members.
+    pub members: Vec<AccountRef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAccount {
+    #[serde(default)]
+    pub is_group: bool,
+    #[serde(flatten)]
+    pub changes: AccountChanges,
+    /// Names of the groups to put it in.
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Not for groups.
+    pub password: Option<PasswordRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAccount {
+    #[serde(flatten)]
+    pub changes: AccountChanges,
+    /// Replaces the direct memberships when present.
+    pub groups: Option<Vec<String>>,
+    /// Required when renaming an account that has a password, which is bound
+    /// to the name.
+    pub password: Option<PasswordRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPassword {
+    #[serde(flatten)]
+    pub password: PasswordRequest,
+    /// Make the user pick a new one at their next login.
+    #[serde(default)]
+    pub require_change: bool,
+}
+
+/// `GET /api/admin/accounts`; accounts and groups, by name.
+pub async fn list(
+    State(state): State<AppState>,
+    _: Admin,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<AccountFilter>,
+) -> ApiResult<Json<Page<AccountRecord>>> {
+    let (limit, offset) = page.limit_offset();
+    let (items, total) = account::list_accounts(&state.db, &filter, limit, offset).await?;
+    Ok(Json(page.wrap(items, total)))
+}
+
+/// `GET /api/admin/accounts/{id}`
+pub async fn get(
+    State(state): State<AppState>,
+    _: Admin,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<AccountDetail>> {
+    let mut conn = state.db.acquire().await.map_err(CoreError::from)?;
+    Ok(Json(detail(&mut conn, id).await?))
+}
+
+/// `POST /api/admin/accounts`
+pub async fn create(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Json(req): Json<CreateAccount>,
+) -> ApiResult<(StatusCode, Json<AccountDetail>)> {
+    let Some(username) = req.changes.username.clone() else {
+        return Err(ApiError::BadRequest("username is required".to_string()));
+    };
+    if req.is_group && req.password.is_some() {
+        return Err(ApiError::BadRequest("groups have no password".to_string()));
+    }
+    let verifier = req.password.as_ref().map(verifier).transpose()?;
+
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let id = account::create_account(&mut tx, &username, req.is_group, &req.changes).await?;
+    if let Some(v) = &verifier {
+        set_verifier(&mut tx, id, v).await?;
+    }
+    for group in account::group_ids(&mut tx, &req.groups).await? {
+        account::add_membership(&mut tx, id, group).await?;
+    }
+    let created = detail(&mut tx, id).await?;
+    audit::record(
+        &mut tx,
+        &admin,
+        "account.create",
+        Target::account(id, &username),
+        json!({ "account": created.record, "groups": names(&created.groups) }),
+    )
+    .await?;
+    tx.commit().await.map_err(CoreError::from)?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// `PATCH /api/admin/accounts/{id}`
+pub async fn update(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateAccount>,
+) -> ApiResult<Json<AccountDetail>> {
+    if admin.id == Some(id)
+        && (req.changes.admin == Some(false) || req.changes.disabled == Some(true))
+    {
+        return Err(ApiError::BadRequest(
+            "you cannot remove your own admin rights or disable yourself".to_string(),
+        ));
+    }
+    let verifier = req.password.as_ref().map(verifier).transpose()?;
+
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let before = detail(&mut tx, id).await?;
+    let renamed = req
+        .changes
+        .username
+        .as_ref()
+        .is_some_and(|n| *n != before.record.username);
+    if renamed && before.record.has_password() && verifier.is_none() {
+        return Err(ApiError::BadRequest(
+            "renaming an account with a password needs a new password".to_string(),
+        ));
+    }
+    if before.record.is_group && verifier.is_some() {
+        return Err(ApiError::BadRequest("groups have no password".to_string()));
+    }
+
+    account::update_account(&mut tx, id, &req.changes).await?;
+    if let Some(v) = &verifier {
+        set_verifier(&mut tx, id, v).await?;
+    }
+    if let Some(groups) = &req.groups {
+        let wanted = account::group_ids(&mut tx, groups).await?;
+        for old in &before.groups {
+            if !wanted.contains(&old.id) {
+                account::remove_membership(&mut tx, id, old.id).await?;
+            }
+        }
+        for group in wanted {
+            account::add_membership(&mut tx, id, group).await?;
+        }
+    }
+
+    let after = detail(&mut tx, id).await?;
+    let mut changes = diff(&before.record, &after.record);
+    if before.groups != after.groups {
+        changes.insert(
+            "groups".to_string(),
+            json!([names(&before.groups), names(&after.groups)]),
+        );
+    }
+    if verifier.is_some() {
+        changes.insert("password".to_string(), json!("changed"));
+    }
+    if !changes.is_empty() {
+        audit::record(
+            &mut tx,
+            &admin,
+            "account.update",
+            Target::account(id, &after.record.username),
+            Value::Object(changes),
+        )
+        .await?;
+    }
+    tx.commit().await.map_err(CoreError::from)?;
+    Ok(Json(after))
+}
+
+/// `DELETE /api/admin/accounts/{id}`
+pub async fn delete(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<i64>,
+) -> ApiResult<StatusCode> {
+    if admin.id == Some(id) {
+        return Err(ApiError::BadRequest(
+            "you cannot delete your own account".to_string(),
+        ));
+    }
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let gone = detail(&mut tx, id).await?;
+    account::delete_account(&mut tx, id).await?;
+    audit::record(
+        &mut tx,
+        &admin,
+        "account.delete",
+        Target::account(id, &gone.record.username),
+        json!({
+            "account": gone.record,
+            "groups": names(&gone.groups),
+            "members": names(&gone.members),
+        }),
+    )
+    .await?;
+    tx.commit().await.map_err(CoreError::from)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /api/admin/accounts/{id}/password`; also ends the account's sessions.
+pub async fn set_password(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<i64>,
+    Json(req): Json<SetPassword>,
+) -> ApiResult<StatusCode> {
+    let verifier = verifier(&req.password)?;
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let target = detail(&mut tx, id).await?;
+    if target.record.is_group {
+        return Err(ApiError::BadRequest("groups have no password".to_string()));
+    }
+    set_verifier(&mut tx, id, &verifier).await?;
+    let changes = AccountChanges {
+        require_password_change: Some(req.require_change),
+        ..Default::default()
+    };
+    account::update_account(&mut tx, id, &changes).await?;
+    audit::record(
+        &mut tx,
+        &admin,
+        "account.password",
+        Target::account(id, &target.record.username),
+        json!({ "require_password_change": req.require_change }),
+    )
+    .await?;
+    tx.commit().await.map_err(CoreError::from)?;
+    session::revoke_all(&state.db, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /api/admin/accounts/{id}/groups/{group_id}`
+pub async fn add_to_group(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, group_id)): Path<(i64, i64)>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    if !account::add_membership(&mut tx, id, group_id).await? {
+        return Err(ApiError::NotFound);
+    }
+    record_membership(&mut tx, &admin, "membership.add", id, group_id).await?;
+    tx.commit().await.map_err(CoreError::from)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DELETE /api/admin/accounts/{id}/groups/{group_id}`
+pub async fn remove_from_group(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, group_id)): Path<(i64, i64)>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    if !account::remove_membership(&mut tx, id, group_id).await? {
+        return Err(ApiError::NotFound);
+    }
+    record_membership(&mut tx, &admin, "membership.remove", id, group_id).await?;
+    tx.commit().await.map_err(CoreError::from)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn record_membership(
+    conn: &mut SqliteConnection,
+    admin: &Principal,
+    action: &str,
+    id: i64,
+    group_id: i64,
+) -> ApiResult<()> {
+    let member = detail(conn, id).await?;
+    let group = detail(conn, group_id).await?;
+    audit::record(
+        conn,
+        admin,
+        action,
+        Target::account(id, &member.record.username),
+        json!({ "group": group.record.username, "group_id": group_id }),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn detail(conn: &mut SqliteConnection, id: i64) -> ApiResult<AccountDetail> {
+    let record = account::find_record(conn, id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let groups = account::groups_of(conn, id).await?;
+    let members = if record.is_group {
+        account::members_of(conn, id).await?
+    } else {
+        Vec::new()
+    };
+    Ok(AccountDetail {
+        record,
+        groups,
+        members,
+    })
+}
+
+fn verifier(req: &PasswordRequest) -> ApiResult<StoredVerifier> {
+    StoredVerifier::from_hex(&req.salt, &req.verifier)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+fn names(refs: &[AccountRef]) -> Vec<&str> {
+    refs.iter().map(|r| r.username.as_str()).collect()
+}
+
+/// `{field: [old, new]}` for every column that changed.
+fn diff(before: &AccountRecord, after: &AccountRecord) -> Map<String, Value> {
+    let (Value::Object(old), Value::Object(new)) = (json!(before), json!(after)) else {
+        return Map::new();
+    };
+    new.into_iter()
+        .filter(|(k, v)| k != "updated_at" && old.get(k) != Some(v))
+        .map(|(k, v)| {
+            let was = old.get(&k).cloned().unwrap_or_default();
+            (k, json!([was, v]))
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+use axum::Json;
+use axum::extract::State;
+use ferri_core::audit::{self, Target};
+use ferri_core::error::Error as CoreError;
+use serde_json::json;
+
+use super::Admin;
+use crate::error::ApiResult;
+use crate::reload::{self, ReloadReport, Trigger};
+use crate::state::AppState;
+
+/// `GET /api/admin/config/reload`: the last reload, `null` before the first.
+pub async fn last_reload(State(state): State<AppState>, _: Admin) -> Json<Option<ReloadReport>> {
+    Json(state.reloader.last())
+}
+
+/// `POST /api/admin/config/reload`: read the config files again now.
+///
+/// Answers 200 either way; `applied` tells whether the new config was taken.
+pub async fn reload(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+) -> ApiResult<Json<ReloadReport>> {
+    let report = reload::reload(&state, Trigger::Admin).await;
+    if report.applied {
+        let mut conn = state.db.acquire().await.map_err(CoreError::from)?;
+        audit::record(
+            &mut conn,
+            &admin,
+            "config.reload",
+            Target::config(),
+            json!({
+                "changed": report.changed,
+                "restart_required": report.restart_required,
+            }),
+        )
+        .await?;
+    }
+    Ok(Json(report))
+}
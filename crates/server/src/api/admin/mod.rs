@@ -0,0 +1,135 @@
+use axum::extract::{FromRequestParts, Query, State};
+use axum::http::request::Parts;
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use ferri_core::account::Principal;
+use ferri_core::audit::{self, AuditEntry, AuditFilter};
+use serde::{Deserialize, Serialize};
+
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::state::AppState;
+
+mod accounts;
+mod config;
+mod vfs;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/admin/accounts",
+            get(accounts::list).post(accounts::create),
+        )
+        .route(
+            "/admin/accounts/{id}",
+            get(accounts::get)
+                .patch(accounts::update)
+                .delete(accounts::delete),
+        )
+        .route("/admin/accounts/{id}/password", put(accounts::set_password))
+        .route(
+            "/admin/accounts/{id}/groups/{group_id}",
+            put(accounts::add_to_group).delete(accounts::remove_from_group),
+        )
+        .route("/admin/vfs/nodes", get(vfs::list).post(vfs::create))
+        .route(
+            "/admin/vfs/nodes/{id}",
+            get(vfs::get).patch(vfs::update).delete(vfs::delete),
+        )
+        .route(
+            "/admin/vfs/nodes/{id}/permissions/{permission}",
+            put(vfs::set_permission).delete(vfs::remove_permission),
+        )
+        .route("/admin/vfs/nodes/{id}/masks", post(vfs::set_mask))
+        .route(
+            "/admin/vfs/nodes/{id}/masks/{mask_id}",
+            delete(vfs::remove_mask),
+        )
+        .route(
+            "/admin/vfs/nodes/{id}/renames/{original}",
+            put(vfs::set_rename).delete(vfs::remove_rename),
+        )
+        .route(
+            "/admin/config/reload",
+            get(config::last_reload).post(config::reload),
+        )
+        .route("/admin/audit", get(list_audit))
+}
+
+/// An administrator making the request; rejects everyone else.
+#[derive(Debug, Clone)]
+pub struct Admin(pub Principal);
+
+impl<S: Send + Sync> FromRequestParts<S> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(Who(who)) = Who::from_request_parts(parts, state).await;
+        if who.is_anonymous() {
+            return Err(ApiError::LoginRequired);
+        }
+        if !who.admin {
+            return Err(ApiError::AdminRequired);
+        }
+        Ok(Admin(who))
+    }
+}
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 500;
+
+/// `?page=&per_page=`, 1-based.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct PageQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl PageQuery {
+    fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    /// `(limit, offset)` for the query.
+    pub fn limit_offset(&self) -> (i64, i64) {
+        let per_page = self.per_page();
+        (per_page, (self.page() - 1).saturating_mul(per_page))
+    }
+
+    pub fn wrap<T>(&self, items: Vec<T>, total: i64) -> Page<T> {
+        Page {
+            items,
+            total,
+            page: self.page(),
+            per_page: self.per_page(),
+        }
+    }
+}
+
+/// One page of a listing.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Matches across all pages.
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+/// `GET /api/admin/audit`; recorded changes, newest first.
+async fn list_audit(
+    State(state): State<AppState>,
+    _: Admin,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<AuditFilter>,
+) -> ApiResult<Json<Page<AuditEntry>>> {
+    let (limit, offset) = page.limit_offset();
+    let (items, total) = audit::list(&state.db, &filter, limit, offset).await?;
+    Ok(Json(page.wrap(items, total)))
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use ferri_core::account::Principal;
+use ferri_core::audit::{self, Target};
+use ferri_core::db::JsonText;
+use ferri_core::error::Error as CoreError;
+use ferri_core::vfs::edit::{self, NodeChanges};
+use ferri_core::vfs::mask::MaskProps;
+use ferri_core::vfs::perm::{Permission, WhoCan};
+use ferri_core::vfs::{NodeId, VfsNode, VfsRename};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sqlx::SqliteConnection;
+
+use super::Admin;
+use crate::error::{ApiError, ApiResult};
+use crate::state::AppState;
+
+/// A node with the rules set on it.
+#[derive(Debug, Serialize)]
+pub struct NodeDetail {
+    #[serde(flatten)]
+    pub node: VfsNode,
+    pub permissions: BTreeMap<&'static str, WhoCan>,
+    /// Highest priority first.
+    pub masks: Vec<MaskInfo>,
+    pub renames: Vec<VfsRename>,
+    /// Child node ids.
+    pub children: Vec<NodeId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaskInfo {
+    pub id: i64,
+    pub mask: String,
+    pub properties: JsonText<MaskProps>,
+    pub ord: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaskRequest {
+    pub mask: String,
+    pub properties: MaskProps,
+    pub ord: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameRequest {
+    pub new_name: String,
+}
+
+/// `GET /api/admin/vfs/nodes`; every node, flat.
+pub async fn list(State(state): State<AppState>, _: Admin) -> ApiResult<Json<Vec<VfsNode>>> {
+    let mut conn = state.db.acquire().await.map_err(CoreError::from)?;
+    Ok(Json(edit::list_nodes(&mut conn).await?))
+}
+
+/// `GET /api/admin/vfs/nodes/{id}`
+pub async fn get(
+    State(state): State<AppState>,
+    _: Admin,
+    Path(id): Path<NodeId>,
+) -> ApiResult<Json<NodeDetail>> {
+    let mut conn = state.db.acquire().await.map_err(CoreError::from)?;
+    Ok(Json(detail(&mut conn, id).await?))
+}
+
+/// `POST /api/admin/vfs/nodes`; a disk folder or file (`source_path`), a link
+/// (`url`) or a virtual folder (just a `name`).
+pub async fn create(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Json(changes): Json<NodeChanges>,
+) -> ApiResult<(StatusCode, Json<NodeDetail>)> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let id = edit::create_node(&mut tx, &changes).await?;
+    let created = detail(&mut tx, id).await?;
+    audit::record(
+        &mut tx,
+        &admin,
+        "vfs_node.create",
+        Target::vfs_node(id, &created.node.display_name()),
+        json!({ "node": created.node }),
+    )
+    .await?;
+    commit(&state, tx).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// `PATCH /api/admin/vfs/nodes/{id}`; setting `parent_id` moves the subtree.
+pub async fn update(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<NodeId>,
+    Json(changes): Json<NodeChanges>,
+) -> ApiResult<Json<NodeDetail>> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let before = edit::find_node(&mut tx, id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let after = edit::update_node(&mut tx, id, &changes)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let diff = diff(&before, &after);
+    if !diff.is_empty() {
+        audit::record(
+            &mut tx,
+            &admin,
+            if before.parent_id != after.parent_id {
+                "vfs_node.move"
+            } else {
+                "vfs_node.update"
+            },
+            Target::vfs_node(id, &after.display_name()),
+            Value::Object(diff),
+        )
+        .await?;
+    }
+    let updated = detail(&mut tx, id).await?;
+    commit(&state, tx).await?;
+    Ok(Json(updated))
+}
+
+/// `DELETE /api/admin/vfs/nodes/{id}`; removes the node and everything below
+/// it from the VFS. Files on disk are left alone.
+pub async fn delete(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<NodeId>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let gone = detail(&mut tx, id).await?;
+    if !edit::delete_node(&mut tx, id).await? {
+        return Err(ApiError::NotFound);
+    }
+    audit::record(
+        &mut tx,
+        &admin,
+        "vfs_node.delete",
+        Target::vfs_node(id, &gone.node.display_name()),
+        json!({ "node": gone.node, "children": gone.children }),
+    )
+    .await?;
+    commit(&state, tx).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /api/admin/vfs/nodes/{id}/permissions/{permission}`; the body is the
+/// `who` rule (`true`, `"*"`, `["alice"]`, `"can_read"`, `{"this": …, "children": …}`).
+pub async fn set_permission(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, perm)): Path<(NodeId, String)>,
+    Json(who): Json<WhoCan>,
+) -> ApiResult<Json<NodeDetail>> {
+    let perm = permission(&perm)?;
+    with_node(&state, &admin, id, "vfs_node.permission", async |tx, _| {
+        edit::set_permission(tx, id, perm, &who).await?;
+        Ok(json!({ "permission": perm.as_str(), "who": who }))
+    })
+    .await
+}
+
+/// `DELETE /api/admin/vfs/nodes/{id}/permissions/{permission}`; inherit it again.
+pub async fn remove_permission(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, perm)): Path<(NodeId, String)>,
+) -> ApiResult<Json<NodeDetail>> {
+    let perm = permission(&perm)?;
+    with_node(&state, &admin, id, "vfs_node.permission", async |tx, _| {
+        if !edit::remove_permission(tx, id, perm).await? {
+            return Err(ApiError::NotFound);
+        }
+        Ok(json!({ "permission": perm.as_str(), "who": null }))
+    })
+    .await
+}
+
+/// `POST /api/admin/vfs/nodes/{id}/masks`; adds a mask, or replaces the one
+/// with the same pattern.
+pub async fn set_mask(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(id): Path<NodeId>,
+    Json(req): Json<MaskRequest>,
+) -> ApiResult<Json<NodeDetail>> {
+    with_node(&state, &admin, id, "vfs_node.mask", async |tx, _| {
+        let mask_id = edit::set_mask(tx, id, &req.mask, &req.properties, req.ord).await?;
+        Ok(json!({
+            "mask_id": mask_id,
+            "mask": req.mask,
+            "properties": req.properties,
+            "ord": req.ord,
+        }))
+    })
+    .await
+}
+
+/// `DELETE /api/admin/vfs/nodes/{id}/masks/{mask_id}`
+pub async fn remove_mask(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, mask_id)): Path<(NodeId, i64)>,
+) -> ApiResult<Json<NodeDetail>> {
+    with_node(&state, &admin, id, "vfs_node.mask", async |tx, _| {
+        if !edit::remove_mask(tx, id, mask_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        Ok(json!({ "mask_id": mask_id, "removed": true }))
+    })
+    .await
+}
+
+/// `PUT /api/admin/vfs/nodes/{id}/renames/{original}`; show a disk entry of
+/// the node under another name.
+pub async fn set_rename(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, original)): Path<(NodeId, String)>,
+    Json(req): Json<RenameRequest>,
+) -> ApiResult<Json<NodeDetail>> {
+    with_node(&state, &admin, id, "vfs_node.rename", async |tx, node| {
+        edit::set_rename(tx, node, &original, &req.new_name).await?;
+        Ok(json!({ "original_name": original, "new_name": req.new_name }))
+    })
+    .await
+}
+
+/// `DELETE /api/admin/vfs/nodes/{id}/renames/{original}`
+pub async fn remove_rename(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path((id, original)): Path<(NodeId, String)>,
+) -> ApiResult<Json<NodeDetail>> {
+    with_node(&state, &admin, id, "vfs_node.rename", async |tx, _| {
+        if !edit::remove_rename(tx, id, &original).await? {
+            return Err(ApiError::NotFound);
+        }
+        Ok(json!({ "original_name": original, "new_name": null }))
+    })
+    .await
+}
+
+/// Run an edit of node `id`'s rules in a transaction, audit it with the detail
+/// it returns, and reload the VFS.
+async fn with_node(
+    state: &AppState,
+    admin: &Principal,
+    id: NodeId,
+    action: &str,
+    change: impl AsyncFnOnce(&mut SqliteConnection, &VfsNode) -> ApiResult<Value>,
+) -> ApiResult<Json<NodeDetail>> {
+    let mut tx = state.db.begin().await.map_err(CoreError::from)?;
+    let node = edit::find_node(&mut tx, id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let detail_json = change(&mut tx, &node).await?;
+    audit::record(
+        &mut tx,
+        admin,
+        action,
+        Target::vfs_node(id, &node.display_name()),
+        detail_json,
+    )
+    .await?;
+    let updated = detail(&mut tx, id).await?;
+    commit(state, tx).await?;
+    Ok(Json(updated))
+}
+
+async fn commit(state: &AppState, tx: sqlx::Transaction<'_, sqlx::Sqlite>) -> ApiResult<()> {
+    tx.commit().await.map_err(CoreError::from)?;
+    state.reload_vfs().await?;
+    Ok(())
+}
+
+async fn detail(conn: &mut SqliteConnection, id: NodeId) -> ApiResult<NodeDetail> {
+    let node = edit::find_node(conn, id).await?.ok_or(ApiError::NotFound)?;
+    let permissions = edit::permissions(conn, id)
+        .await?
+        .into_iter()
+        .filter_map(|p| Some((p.permission.as_str(), p.who.into_result().ok()?)))
+        .collect();
+    let masks = edit::masks(conn, id)
+        .await?
+        .into_iter()
+        .map(|m| MaskInfo {
+            id: m.id,
+            properties: m.properties,
+            mask: m.mask,
+            ord: m.ord,
+        })
+        .collect();
+    let renames = edit::renames(conn, id).await?;
+    let children = edit::list_nodes(conn)
+        .await?
+        .into_iter()
+        .filter(|n| n.parent_id == Some(id))
+        .map(|n| n.id)
+        .collect();
+    Ok(NodeDetail {
+        node,
+        permissions,
+        masks,
+        renames,
+        children,
+    })
+}
+
+fn permission(name: &str) -> ApiResult<Permission> {
+    name.parse().map_err(ApiError::BadRequest)
+}
+
+/// `{field: [old, new]}` for every column that changed.
+fn diff(before: &VfsNode, after: &VfsNode) -> serde_json::Map<String, Value> {
+    let (Value::Object(old), Value::Object(new)) = (json!(before), json!(after)) else {
+        return Default::default();
+    };
+    new.into_iter()
+        .filter(|(k, v)| old.get(k) != Some(v))
+        .map(|(k, v)| {
+            let was = old.get(&k).cloned().unwrap_or_default();
+            (k, json!([was, v]))
+        })
+        .collect()
+}
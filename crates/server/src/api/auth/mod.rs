@@ -0,0 +1,115 @@
+use std::net::IpAddr;
+
+use axum::extract::{FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Router};
+use ferri_core::account::{self, Principal};
+use ferri_core::error::Error as CoreError;
+use ferri_core::session;
+
+use crate::error::ApiResult;
+use crate::host::{ClientIp, RequestHost};
+use crate::session::{CurrentSession, forget};
+use crate::state::AppState;
+
+mod oidc;
+pub(crate) mod srp;
+
+/// Name of the cookie carrying the session token.
+pub const SESSION_COOKIE: &str = "ferri_session";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/auth/srp/start", post(srp::start))
+        .route("/auth/srp/finish", post(srp::finish))
+        .route("/auth/logout", post(logout))
+        .route("/auth/oidc", get(oidc::providers))
+        .route("/auth/oidc/{provider}/login", get(oidc::login))
+        .route("/auth/oidc/{provider}/callback", get(oidc::callback))
+}
+
+/// `Set-Cookie` value for a new session token.
+///
+/// `Lax` rather than `Strict`, so following a link to a shared file from
+/// elsewhere still carries the session.
+pub fn session_cookie(token: &str, max_age: u64, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!("{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={max_age}{secure}")
+}
+
+/// `Set-Cookie` value that removes the session cookie.
+pub fn clear_cookie(secure: bool) -> String {
+    session_cookie("", 0, secure)
+}
+
+/// Whether cookies for this request get the `Secure` attribute: as configured,
+/// otherwise when the client reached us over HTTPS.
+pub fn cookie_secure(state: &AppState, host: &RequestHost) -> bool {
+    state.cfg().cookie_secure.unwrap_or(host.secure)
+}
+
+/// Checks made once the credentials are good: `ip` is within the account's
+/// `allow_net`, and the account is enabled and current.
+///
+/// The address comes first, so a login refused for it does not start the
+/// clock on `days_to_live`.
+async fn check_login(
+    state: &AppState,
+    account_id: i64,
+    ip: Option<IpAddr>,
+) -> ApiResult<Principal> {
+    let Some(who) = Principal::load(&state.db, account_id).await? else {
+        // Disabled or expired; `begin_login` says which, without writing anything.
+        account::begin_login(&state.db, account_id).await?;
+        return Err(CoreError::AuthFailed.into());
+    };
+    if !who.net_allowed(ip) {
+        return Err(CoreError::NetDenied.into());
+    }
+    account::begin_login(&state.db, account_id).await?;
+    Ok(who)
+}
+
+/// `POST /api/auth/logout`; ends the current session, if any.
+async fn logout(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    current: Option<Extension<CurrentSession>>,
+) -> ApiResult<Response> {
+    if let Some(Extension(current)) = current {
+        session::delete(&state.db, &current.id_hash).await?;
+        forget(&current.id_hash);
+    }
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(
+            header::SET_COOKIE,
+            clear_cookie(cookie_secure(&state, &host)),
+        )],
+    )
+        .into_response())
+}
+
+/// The account making the request; anonymous unless a session put one in
+/// the request extensions. Carries the client address either way.
+#[derive(Debug, Clone)]
+pub struct Who(pub Principal);
+
+impl<S: Send + Sync> FromRequestParts<S> for Who {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let mut who = parts
+            .extensions
+            .get::<Principal>()
+            .cloned()
+            .unwrap_or_default();
+        if let Some(ClientIp(ip)) = parts.extensions.get::<ClientIp>() {
+            who.ip = *ip;
+        }
+        Ok(Who(who))
+    }
+}
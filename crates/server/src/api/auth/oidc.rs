@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, header};
+use axum::response::{AppendHeaders, IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use ferri_core::error::Error as CoreError;
+use ferri_core::oidc::{self, OidcProvider, Provider};
+use ferri_core::session::{self, ClientInfo};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use super::{Who, check_login, cookie_secure, session_cookie};
+use crate::error::{ApiError, ApiResult};
+use crate::host::{ClientIp, RequestHost};
+use crate::session::cookie;
+use crate::state::AppState;
+
+/// Name of the cookie tying a sign-in to the browser that started it.
+const STATE_COOKIE: &str = "ferri_oidc";
+
+/// `Set-Cookie` value for the state binding; only sent back to the callback.
+fn state_cookie(binding: &str, max_age: i64, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!(
+        "{STATE_COOKIE}={binding}; Path=/api/auth/oidc/; HttpOnly; SameSite=Lax; Max-Age={max_age}{secure}"
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub display_name: String,
+    /// Where to send the browser to sign in.
+    pub login_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// `GET /api/auth/oidc`: the configured providers.
+pub async fn providers(State(state): State<AppState>) -> Json<Vec<ProviderInfo>> {
+    Json(
+        state
+            .oidc
+            .providers()
+            .iter()
+            .map(|p| ProviderInfo {
+                name: p.name.clone(),
+                display_name: p.label().to_string(),
+                login_url: format!("/api/auth/oidc/{}/login", p.name),
+            })
+            .collect(),
+    )
+}
+
+/// `GET /api/auth/oidc/{provider}/login`: redirect to the provider.
+///
+/// Signing in while already logged in links the identity to that account.
+pub async fn login(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Path(name): Path<String>,
+    Who(who): Who,
+) -> ApiResult<Response> {
+    let provider = provider(&state, &name).await?;
+    let redirect_uri = redirect_uri(&state, &host, &provider.cfg)?;
+    let started = oidc::begin(&state.db, &provider, &redirect_uri, who.id).await?;
+    let cookie = state_cookie(
+        &started.binding,
+        oidc::STATE_TTL,
+        cookie_secure(&state, &host),
+    );
+    Ok((
+        [(header::SET_COOKIE, cookie)],
+        Redirect::to(started.url.as_str()),
+    )
+        .into_response())
+}
+
+/// `GET /api/auth/oidc/{provider}/callback`: finish signing in and open a session.
+pub async fn callback(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Path(name): Path<String>,
+    Query(q): Query<CallbackQuery>,
+    Who(who): Who,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    if let Some(error) = q.error {
+        let detail = q.error_description.unwrap_or_default();
+        info!(provider = %name, %error, %detail, "sign-in refused by provider");
+        return Err(ApiError::BadRequest(format!(
+            "sign-in failed: {error} {detail}"
+        )));
+    }
+    let (Some(code), Some(st)) = (q.code, q.state) else {
+        return Err(ApiError::BadRequest("missing code or state".to_string()));
+    };
+
+    let provider = provider(&state, &name).await?;
+    let binding = cookie(&headers, STATE_COOKIE);
+    let pending = oidc::take_state(&state.db, &st, binding.as_deref())
+        .await?
+        .ok_or(CoreError::OidcState)?;
+    // The state must have been issued for this provider's callback.
+    if pending.redirect_uri != redirect_uri(&state, &host, &provider.cfg)? {
+        return Err(CoreError::OidcState.into());
+    }
+
+    let tokens = provider.exchange(&code, &pending).await.inspect_err(|e| {
+        warn!(provider = %name, ip = ?ip, "sign-in failed: {e}");
+    })?;
+    let signed_in = oidc::sign_in(
+        &state.db,
+        &provider.cfg,
+        &tokens.claims,
+        pending.account_id,
+        who.id,
+    )
+    .await?;
+    let account_id = signed_in.account_id;
+    check_login(&state, account_id, ip).await.inspect_err(|e| {
+        info!(provider = %name, account_id, ip = ?ip, "login refused: {e}");
+    })?;
+    if !oidc::store_tokens(&state.db, &state.keys, signed_in.identity_id, &tokens).await? {
+        debug!(provider = %name, "no master key configured; provider tokens not kept");
+    }
+
+    let client = ClientInfo {
+        ip: ip.map(|ip| ip.to_string()),
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+    };
+    let max_age = state.cfg().session_max_age;
+    let token = session::create(&state.db, account_id, max_age, &client).await?;
+    info!(provider = %name, account_id, ip = ?ip, "logged in");
+
+    let secure = cookie_secure(&state, &host);
+    let cookies = AppendHeaders([
+        (header::SET_COOKIE, session_cookie(&token, max_age, secure)),
+        (header::SET_COOKIE, state_cookie("", 0, secure)),
+    ]);
+    Ok((cookies, Redirect::to("/")).into_response())
+}
+
+async fn provider(state: &AppState, name: &str) -> ApiResult<Arc<Provider>> {
+    state.oidc.provider(name).await?.ok_or(ApiError::NotFound)
+}
+
+/// The configured callback URL, else one on the host the request came to.
+fn redirect_uri(state: &AppState, host: &RequestHost, cfg: &OidcProvider) -> ApiResult<String> {
+    if let Some(url) = &cfg.redirect_url {
+        return Ok(url.clone());
+    }
+    let authority = host
+        .authority
+        .as_deref()
+        .ok_or_else(|| ApiError::BadRequest("missing Host header".to_string()))?;
+    let scheme = if cookie_secure(state, host) {
+        "https"
+    } else {
+        "http"
+    };
+    Ok(format!(
+        "{scheme}://{authority}/api/auth/oidc/{}/callback",
+        cfg.name
+    ))
+}
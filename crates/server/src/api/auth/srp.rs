@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use ferri_core::account::{self, AccountRecord};
+use ferri_core::error::Error as CoreError;
+use ferri_core::session::{self, ClientInfo};
+use ferri_core::srp::{ServerLogin, StoredVerifier, parse_int};
+use parking_lot::Mutex;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::{check_login, cookie_secure, session_cookie};
+use crate::error::{ApiError, ApiResult};
+use crate::host::{ClientIp, RequestHost};
+use crate::state::AppState;
+
+/// How long a client has between the two login steps.
+const EXCHANGE_TTL: Duration = Duration::from_secs(120);
+/// Cap on half-finished logins kept in memory.
+const MAX_PENDING: usize = 10_000;
+
+/// Logins waiting for their second step, by exchange id.
+static PENDING: LazyLock<Mutex<HashMap<String, Pending>>> = LazyLock::new(Default::default);
+
+/// Per-process key for the fake salts handed out for unknown usernames.
+static DECOY_KEY: LazyLock<[u8; 32]> = LazyLock::new(|| {
+    let mut key = [0u8; 32];
+    rand::rng().fill_bytes(&mut key);
+    key
+});
+
+struct Pending {
+    /// `None` for a decoy exchange that can never succeed.
+    account_id: Option<i64>,
+    username: String,
+    login: ServerLogin,
+    started: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartResponse {
+    /// Identifies this exchange in the second step.
+    pub id: String,
+    /// Hex.
+    pub salt: String,
+    /// Server public value `B`, hex.
+    pub b: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FinishRequest {
+    pub id: String,
+    /// Client public value `A`, hex.
+    pub a: String,
+    /// Client proof `M1`, hex.
+    pub m1: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinishResponse {
+    pub username: String,
+    /// Server proof `M2`, hex; the client should check it before trusting the session.
+    pub m2: String,
+    /// The session is only good for `POST /api/account/password` until a new
+    /// password is set.
+    pub password_change_required: bool,
+}
+
+/// `POST /api/auth/srp/start`: first SRP-6a step.
+///
+/// Unknown usernames get a plausible salt and `B` so they cannot be told apart
+/// from real ones; their exchange simply never verifies.
+pub async fn start(
+    State(state): State<AppState>,
+    Json(req): Json<StartRequest>,
+) -> ApiResult<Json<StartResponse>> {
+    let mut conn = state.db.acquire().await.map_err(CoreError::from)?;
+    let found = account::find_by_name(&mut conn, &req.username).await?;
+    drop(conn);
+
+    let (account_id, stored) = match found.filter(|a| !a.is_group) {
+        Some(AccountRecord {
+            id, srp: Some(srp), ..
+        }) => match StoredVerifier::decode(&srp) {
+            Ok(v) => (Some(id), v),
+            Err(e) => {
+                warn!(account = %req.username, "unusable password verifier: {e}");
+                (None, decoy(&req.username))
+            }
+        },
+        _ => (None, decoy(&req.username)),
+    };
+    let login = ServerLogin::new(&req.username, &stored);
+    let res = StartResponse {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        salt: hex::encode(login.salt()),
+        b: login.public().to_str_radix(16),
+    };
+
+    let mut pending = PENDING.lock();
+    pending.retain(|_, p| p.started.elapsed() < EXCHANGE_TTL);
+    if pending.len() >= MAX_PENDING {
+        return Err(ApiError::BadRequest(
+            "too many logins in progress, try again shortly".to_string(),
+        ));
+    }
+    pending.insert(
+        res.id.clone(),
+        Pending {
+            account_id,
+            username: req.username,
+            login,
+            started: Instant::now(),
+        },
+    );
+    Ok(Json(res))
+}
+
+/// An exchange whose proof checked out.
+pub(crate) struct Proven {
+    pub account_id: i64,
+    pub username: String,
+    /// Server proof `M2`.
+    pub m2: Vec<u8>,
+}
+
+/// Check the client's proof for an exchange begun with [`start`].
+///
+/// One attempt per exchange, whatever the outcome.
+pub(crate) fn prove(req: &FinishRequest, ip: Option<IpAddr>) -> ApiResult<Proven> {
+    let pending = PENDING
+        .lock()
+        .remove(&req.id)
+        .filter(|p| p.started.elapsed() < EXCHANGE_TTL)
+        .ok_or(CoreError::AuthFailed)?;
+
+    let a_pub = parse_int(&req.a).ok_or(CoreError::AuthFailed)?;
+    let m1 = hex::decode(req.m1.trim()).map_err(|_| CoreError::AuthFailed)?;
+    let verified = pending.login.verify(&a_pub, &m1);
+    let (Some(account_id), Ok(m2)) = (pending.account_id, verified) else {
+        info!(account = %pending.username, ip = ?ip, "wrong password");
+        return Err(CoreError::AuthFailed.into());
+    };
+    Ok(Proven {
+        account_id,
+        username: pending.username,
+        m2,
+    })
+}
+
+/// `POST /api/auth/srp/finish`: check the client's proof and open a session.
+pub async fn finish(
+    State(state): State<AppState>,
+    Extension(ClientIp(ip)): Extension<ClientIp>,
+    Extension(host): Extension<RequestHost>,
+    headers: HeaderMap,
+    Json(req): Json<FinishRequest>,
+) -> ApiResult<Response> {
+    let Proven {
+        account_id,
+        username,
+        m2,
+    } = prove(&req, ip)?;
+    let who = check_login(&state, account_id, ip).await.inspect_err(|e| {
+        info!(account = %username, ip = ?ip, "login refused: {e}");
+    })?;
+
+    let client = ClientInfo {
+        ip: ip.map(|ip| ip.to_string()),
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+    };
+    let max_age = state.cfg().session_max_age;
+    let token = session::create(&state.db, account_id, max_age, &client).await?;
+    info!(account = %username, ip = ?ip, "logged in");
+
+    let body = Json(FinishResponse {
+        username,
+        m2: hex::encode(m2),
+        password_change_required: who.must_change_password,
+    });
+    Ok((
+        [(
+            header::SET_COOKIE,
+            session_cookie(&token, max_age, cookie_secure(&state, &host)),
+        )],
+        body,
+    )
+        .into_response())
+}
+
+/// Stable fake verifier for a username that cannot log in.
+fn decoy(username: &str) -> StoredVerifier {
+    let digest = Sha256::new()
+        .chain_update(*DECOY_KEY)
+        .chain_update(username.as_bytes())
+        .finalize();
+    let mut verifier = [0u8; 32];
+    rand::rng().fill_bytes(&mut verifier);
+    StoredVerifier {
+        salt: digest[..16].to_vec(),
+        verifier: num_bigint::BigUint::from_bytes_be(&verifier),
+    }
+}
@@ -1,4 +1,17 @@
+use axum::Router;
+
+use crate::state::AppState;
+
 pub mod account;
 pub mod admin;
 pub mod auth;
 pub mod vfs;
+
+/// Routes mounted under `/api`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .merge(account::router())
+        .merge(admin::router())
+        .merge(auth::router())
+        .merge(vfs::router())
+}
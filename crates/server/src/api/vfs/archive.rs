@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use ferri_core::account::Principal;
+use ferri_core::archive::ArchiveFormat;
+use ferri_core::vfs::perm::Permission;
+use ferri_core::vfs::{Entry, Vfs};
+
+use super::range::content_disposition;
+use crate::error::{ApiError, ApiResult};
+
+/// Separates entries in `?list=`; `*` is as unlikely in file names as it gets
+/// without being reserved in URLs.
+const LIST_SEPARATOR: char = '*';
+
+/// `GET /folder/?get=zip|tar|tar.gz[&list=a*b/c]`: stream the folder, or the
+/// listed entries below it, as an archive.
+pub async fn archive(
+    vfs: Arc<Vfs>,
+    who: Principal,
+    folder: Entry,
+    format: ArchiveFormat,
+    list: Option<&str>,
+    method: &Method,
+) -> ApiResult<Response> {
+    vfs.check(&who, Permission::CanArchive, &folder)?;
+
+    let selection = match list {
+        Some(list) => Some(select(&vfs, &folder, list).await?),
+        None => None,
+    };
+
+    let base = if folder.name.is_empty() {
+        "archive"
+    } else {
+        folder.name.as_str()
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    if let Ok(v) = HeaderValue::from_str(&content_disposition(
+        &format!("{base}.{}", format.extension()),
+        true,
+    )) {
+        headers.insert(header::CONTENT_DISPOSITION, v);
+    }
+    if method == Method::HEAD {
+        return Ok((StatusCode::OK, headers).into_response());
+    }
+
+    let stream = vfs.archive_stream(who, folder, selection, format);
+    Ok((StatusCode::OK, headers, Body::from_stream(stream)).into_response())
+}
+
+/// Resolve `list` entries (relative paths, `/`-separated) below `folder`.
+async fn select(vfs: &Vfs, folder: &Entry, list: &str) -> ApiResult<Vec<Entry>> {
+    let mut out = Vec::new();
+    for item in list.split(LIST_SEPARATOR).filter(|s| !s.is_empty()) {
+        let mut entry = folder.clone();
+        for seg in item.split('/').filter(|s| !s.is_empty()) {
+            entry = vfs.child(&entry, seg).await?.ok_or(ApiError::NotFound)?;
+        }
+        if entry.url_path != folder.url_path {
+            out.push(entry);
+        }
+    }
+    if out.is_empty() {
+        return Err(ApiError::BadRequest("empty archive selection".to_string()));
+    }
+    Ok(out)
+}
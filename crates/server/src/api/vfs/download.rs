@@ -0,0 +1,264 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::Extension;
+use axum::body::{Body, Bytes};
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use ferri_core::archive::ArchiveFormat;
+use ferri_core::vfs::perm::Permission;
+use ferri_core::vfs::{Entry, EntryKind, Vfs};
+use futures_util::{StreamExt, TryStreamExt, stream};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use super::archive;
+use super::range::{
+    ByteRange, RangeSpec, content_disposition, etag_matches, not_modified_since, parse_range,
+};
+use super::resolve;
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FileQuery {
+    /// Present (any value) to force `Content-Disposition: attachment`.
+    pub dl: Option<String>,
+    /// Download a folder as an archive instead of its default child.
+    pub get: Option<ArchiveFormat>,
+    /// With `get`, only these entries of the folder (`*`-separated relative paths).
+    pub list: Option<String>,
+}
+
+/// `GET`/`HEAD` on any VFS path that is not an API route.
+///
+/// Files are streamed with `Range` and conditional request support; folders
+/// serve their default child, or an archive with `?get=`; link nodes redirect
+/// to their `url`.
+pub async fn download(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    Query(q): Query<FileQuery>,
+) -> ApiResult<Response> {
+    let vfs = state.vfs();
+    let mut entry = resolve(&vfs, &host, uri.path()).await?;
+
+    if entry.is_folder() {
+        if !uri.path().ends_with('/') {
+            let query = uri.query().map(|q| format!("?{q}")).unwrap_or_default();
+            return Ok(Redirect::permanent(&format!("{}/{query}", uri.path())).into_response());
+        }
+        if let Some(format) = q.get {
+            return archive::archive(vfs, who, entry, format, q.list.as_deref(), &method).await;
+        }
+        entry = vfs.default_child(&entry).await?.ok_or(ApiError::NotFound)?;
+    }
+    vfs.check(&who, Permission::CanRead, &entry)?;
+
+    match entry.kind {
+        EntryKind::Link => {
+            let url = entry.url.as_deref().ok_or(ApiError::NotFound)?;
+            Ok(Redirect::to(url).into_response())
+        }
+        EntryKind::File => serve_file(&vfs, &entry, &method, &headers, q.dl.is_some()).await,
+        // A default child that is itself a folder has nothing to send.
+        EntryKind::Folder => Err(ApiError::NotFound),
+    }
+}
+
+async fn serve_file(
+    vfs: &Vfs,
+    entry: &Entry,
+    method: &Method,
+    headers: &HeaderMap,
+    attachment: bool,
+) -> ApiResult<Response> {
+    let path = entry.source.clone().ok_or(ApiError::NotFound)?;
+    let meta = match &entry.metadata {
+        Some(m) => m.clone(),
+        None => tokio::fs::metadata(&path)
+            .await
+            .map_err(ferri_core::error::Error::from)?,
+    };
+    let size = meta.len();
+    let mtime = meta.modified().unwrap_or(UNIX_EPOCH);
+    let etag = etag_for(size, mtime);
+
+    let content_type = vfs.props(entry).mime.unwrap_or_else(|| {
+        mime_guess::from_path(&entry.name)
+            .first_or_octet_stream()
+            .to_string()
+    });
+
+    let mut res_headers = HeaderMap::new();
+    res_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    insert(&mut res_headers, header::ETAG, &etag);
+    insert(
+        &mut res_headers,
+        header::LAST_MODIFIED,
+        &httpdate::fmt_http_date(mtime),
+    );
+    insert(
+        &mut res_headers,
+        header::CONTENT_DISPOSITION,
+        &content_disposition(&entry.name, attachment),
+    );
+
+    // If-None-Match takes precedence over If-Modified-Since (RFC 9110 §13.2.2).
+    let not_modified = match header_str(headers, header::IF_NONE_MATCH) {
+        Some(inm) => etag_matches(inm, &etag, true),
+        None => header_str(headers, header::IF_MODIFIED_SINCE)
+            .is_some_and(|ims| not_modified_since(ims, mtime)),
+    };
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, res_headers).into_response());
+    }
+
+    let range_ok = match header_str(headers, header::IF_RANGE) {
+        None => true,
+        Some(v) if v.trim_start().starts_with('"') => etag_matches(v, &etag, false),
+        Some(v) => not_modified_since(v, mtime),
+    };
+    let spec = match header_str(headers, header::RANGE) {
+        Some(r) if range_ok => parse_range(r, size),
+        _ => RangeSpec::Full,
+    };
+    let head = method == Method::HEAD;
+
+    match spec {
+        RangeSpec::Full => {
+            insert(&mut res_headers, header::CONTENT_TYPE, &content_type);
+            res_headers.insert(header::CONTENT_LENGTH, size.into());
+            let body = if head {
+                Body::empty()
+            } else {
+                file_body(
+                    path,
+                    ByteRange {
+                        start: 0,
+                        end: size.saturating_sub(1),
+                    },
+                )
+                .await?
+            };
+            Ok((StatusCode::OK, res_headers, body).into_response())
+        }
+        RangeSpec::Unsatisfiable => {
+            insert(
+                &mut res_headers,
+                header::CONTENT_RANGE,
+                &format!("bytes */{size}"),
+            );
+            Ok((StatusCode::RANGE_NOT_SATISFIABLE, res_headers).into_response())
+        }
+        RangeSpec::Partial(ranges) if ranges.len() == 1 => {
+            let r = ranges[0];
+            insert(&mut res_headers, header::CONTENT_TYPE, &content_type);
+            insert(
+                &mut res_headers,
+                header::CONTENT_RANGE,
+                &r.content_range(size),
+            );
+            res_headers.insert(header::CONTENT_LENGTH, r.len().into());
+            let body = if head {
+                Body::empty()
+            } else {
+                file_body(path, r).await?
+            };
+            Ok((StatusCode::PARTIAL_CONTENT, res_headers, body).into_response())
+        }
+        RangeSpec::Partial(ranges) => {
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let parts: Vec<(String, ByteRange)> = ranges
+                .into_iter()
+                .map(|r| {
+                    let head = format!(
+                        "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+                        r.content_range(size)
+                    );
+                    (head, r)
+                })
+                .collect();
+            let closing = format!("\r\n--{boundary}--\r\n");
+            let length = parts
+                .iter()
+                .map(|(h, r)| h.len() as u64 + r.len())
+                .sum::<u64>()
+                + closing.len() as u64;
+
+            insert(
+                &mut res_headers,
+                header::CONTENT_TYPE,
+                &format!("multipart/byteranges; boundary={boundary}"),
+            );
+            res_headers.insert(header::CONTENT_LENGTH, length.into());
+            let body = if head {
+                Body::empty()
+            } else {
+                multipart_body(path, parts, closing)
+            };
+            Ok((StatusCode::PARTIAL_CONTENT, res_headers, body).into_response())
+        }
+    }
+}
+
+/// Strong validator from size and mtime, like most static file servers use.
+fn etag_for(size: u64, mtime: SystemTime) -> String {
+    let nanos = mtime
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("\"{nanos:x}-{size:x}\"")
+}
+
+async fn open_range(
+    path: PathBuf,
+    r: ByteRange,
+) -> std::io::Result<tokio::io::Take<tokio::fs::File>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(r.start)).await?;
+    Ok(file.take(r.len()))
+}
+
+async fn file_body(path: PathBuf, r: ByteRange) -> ApiResult<Body> {
+    let file = open_range(path, r)
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    Ok(Body::from_stream(ReaderStream::new(file)))
+}
+
+/// Stream each part header followed by its slice of the file, opening the file
+/// lazily per part.
+fn multipart_body(path: PathBuf, parts: Vec<(String, ByteRange)>, closing: String) -> Body {
+    let parts = stream::iter(parts)
+        .then(move |(head, r)| {
+            let path = path.clone();
+            async move {
+                let file = open_range(path, r).await?;
+                let head = stream::once(async move { Ok(Bytes::from(head)) });
+                Ok::<_, std::io::Error>(head.chain(ReaderStream::new(file)))
+            }
+        })
+        .try_flatten();
+    let closing = stream::once(async move { Ok(Bytes::from(closing)) });
+    Body::from_stream(parts.chain(closing))
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn insert(headers: &mut HeaderMap, name: header::HeaderName, value: &str) {
+    if let Ok(v) = HeaderValue::from_str(value) {
+        headers.insert(name, v);
+    }
+}
@@ -0,0 +1,161 @@
+use std::convert::Infallible;
+use std::time::UNIX_EPOCH;
+
+use axum::Extension;
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use ferri_core::account::Principal;
+use ferri_core::vfs::list::ListOptions;
+use ferri_core::vfs::perm::Permission;
+use ferri_core::vfs::{Entry, EntryKind, Vfs};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+use super::resolve;
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+/// Deepest recursion a client may ask for.
+const MAX_DEPTH: usize = 32;
+
+/// Sent in place of a walk error, whose text may hold host paths; the error
+/// itself goes to the log.
+const READ_ERROR: &str = "part of the folder could not be read";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    Ndjson,
+    Sse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    #[serde(default)]
+    pub path: String,
+    /// 1 = direct children only.
+    pub depth: Option<usize>,
+    /// Overrides content negotiation through `Accept`.
+    pub format: Option<ListFormat>,
+}
+
+/// One line of NDJSON output, or the data of one SSE `entry` event.
+#[derive(Debug, Serialize)]
+pub struct ListItem {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Unix seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    pub permissions: Vec<Permission>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl ListItem {
+    fn new(vfs: &Vfs, who: &Principal, entry: Entry) -> Self {
+        let meta = entry.metadata.as_ref();
+        let size = meta.filter(|m| m.is_file()).map(|m| m.len());
+        let mtime = meta
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64);
+        let permissions = vfs.granted(who, &entry);
+        let target = vfs.props(&entry).target;
+
+        Self {
+            name: entry.name,
+            path: entry.url_path,
+            kind: entry.kind,
+            size,
+            mtime,
+            permissions,
+            url: entry.url,
+            target,
+        }
+    }
+}
+
+/// `GET /api/list?path=…&depth=…`
+///
+/// Streams the folder's entries as NDJSON, or as Server-Sent Events when asked
+/// for `text/event-stream`, so large folders render while they are walked.
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    headers: HeaderMap,
+    Query(q): Query<ListQuery>,
+) -> ApiResult<Response> {
+    let vfs = state.vfs();
+    let folder = resolve(&vfs, &host, &q.path).await?;
+    if !folder.is_folder() {
+        return Err(ApiError::BadRequest("not a folder".to_string()));
+    }
+    vfs.check(&who, Permission::CanList, &folder)?;
+
+    let opts = ListOptions {
+        depth: q.depth.unwrap_or(1).clamp(1, MAX_DEPTH),
+        ..ListOptions::default()
+    };
+    let items = {
+        let (vfs, path) = (vfs.clone(), folder.url_path.clone());
+        vfs.clone()
+            .list_stream(who.clone(), folder, opts)
+            .map(move |r| match r {
+                Ok(e) => Ok(ListItem::new(&vfs, &who, e)),
+                Err(e) => {
+                    warn!(%path, "listing failed: {e}");
+                    Err(READ_ERROR)
+                }
+            })
+    };
+
+    let wants_sse = match q.format {
+        Some(f) => f == ListFormat::Sse,
+        None => headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/event-stream")),
+    };
+
+    if wants_sse {
+        let events = items
+            .map(|r| match r {
+                Ok(item) => Event::default().event("entry").json_data(item),
+                Err(e) => Ok(Event::default().event("error").data(e)),
+            })
+            // Tell clients the listing is complete so they don't auto-reconnect.
+            .chain(tokio_stream::once(Ok(Event::default()
+                .event("end")
+                .data(""))));
+        return Ok(Sse::new(events).into_response());
+    }
+
+    let lines = items.map(|r| {
+        let mut line = match r {
+            Ok(item) => serde_json::to_string(&item).unwrap_or_default(),
+            Err(e) => json!({ "error": e }).to_string(),
+        };
+        line.push('\n');
+        Ok::<_, Infallible>(line)
+    });
+    let mut res = Body::from_stream(lines).into_response();
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(res)
+}
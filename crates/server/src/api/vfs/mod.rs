@@ -0,0 +1,66 @@
+use axum::Router;
+use axum::extract::DefaultBodyLimit;
+use axum::routing::{MethodRouter, get, post};
+use ferri_core::vfs::{Entry, Vfs, split_path};
+
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+mod archive;
+mod download;
+mod list;
+mod ops;
+mod range;
+pub mod tus;
+mod upload;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/list", get(list::list))
+        .route("/delete", post(ops::delete))
+        .route("/rename", post(ops::rename))
+        .route("/move", post(ops::move_to))
+        .route("/copy", post(ops::copy_to))
+        .route("/mkdir", post(ops::mkdir))
+        .merge(tus::router().layer(DefaultBodyLimit::disable()))
+}
+
+/// Handlers for VFS paths themselves, mounted as the app's fallback.
+pub fn files() -> MethodRouter<AppState> {
+    get(download::download)
+        .put(upload::put)
+        .post(upload::post)
+        // Uploads enforce `max_upload_size` themselves while streaming.
+        .layer(DefaultBodyLimit::disable())
+}
+
+/// Resolve `path` under the root picked for the request's host.
+pub(crate) async fn resolve(vfs: &Vfs, host: &RequestHost, path: &str) -> ApiResult<Entry> {
+    let root = host.root.ok_or(ApiError::NotFound)?;
+    vfs.resolve_from(root, path)
+        .await?
+        .ok_or(ApiError::NotFound)
+}
+
+/// Resolve the folder holding the last segment of `path`, and that segment decoded.
+pub(crate) async fn resolve_parent(
+    vfs: &Vfs,
+    host: &RequestHost,
+    path: &str,
+) -> ApiResult<(Entry, String)> {
+    let root = host.root.ok_or(ApiError::NotFound)?;
+    let mut segments = split_path(path)?;
+    let name = segments
+        .pop()
+        .ok_or_else(|| ApiError::BadRequest("missing file name".to_string()))?;
+
+    let mut folder = vfs
+        .node_entry(root, "/".to_string())
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    for seg in &segments {
+        folder = vfs.child(&folder, seg).await?.ok_or(ApiError::NotFound)?;
+    }
+    Ok((folder, name))
+}
@@ -0,0 +1,300 @@
+use std::path::PathBuf;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use ferri_core::account::Principal;
+use ferri_core::vfs::ops::{copy_path, make_dir, move_path, remove, validate_name};
+use ferri_core::vfs::perm::Permission;
+use ferri_core::vfs::{Entry, Vfs, join_url};
+use serde::{Deserialize, Serialize};
+
+use super::upload::{check_target, source_dir};
+use super::{resolve, resolve_parent};
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteRequest {
+    pub paths: Vec<String>,
+    /// Delete non-empty folders with everything in them.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameRequest {
+    pub path: String,
+    /// New name within the same folder.
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferRequest {
+    pub paths: Vec<String>,
+    /// Destination folder.
+    pub to: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MkdirRequest {
+    /// Path of the folder to create.
+    pub path: String,
+}
+
+/// Outcome of one item of a batch request.
+#[derive(Debug, Serialize)]
+pub struct ItemResult {
+    pub path: String,
+    pub status: u16,
+    /// Where the item ended up, for moves and copies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ItemResult {
+    fn new(path: String, res: ApiResult<Option<String>>) -> Self {
+        match res {
+            Ok(to) => Self {
+                path,
+                status: StatusCode::OK.as_u16(),
+                to,
+                error: None,
+            },
+            Err(e) => Self {
+                path,
+                status: e.status().as_u16(),
+                to: None,
+                error: Some(e.client_message()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub results: Vec<ItemResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathResult {
+    pub path: String,
+}
+
+/// `POST /api/delete`; needs `can_delete` on each item, and on everything inside
+/// folders deleted recursively.
+pub async fn delete(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    Json(req): Json<DeleteRequest>,
+) -> Json<BatchResult> {
+    let vfs = state.vfs();
+    let mut results = Vec::with_capacity(req.paths.len());
+    for path in req.paths {
+        let res = delete_one(&vfs, &who, &host, &path, req.recursive).await;
+        results.push(ItemResult::new(path, res.map(|()| None)));
+    }
+    Json(BatchResult { results })
+}
+
+async fn delete_one(
+    vfs: &Vfs,
+    who: &Principal,
+    host: &RequestHost,
+    path: &str,
+    recursive: bool,
+) -> ApiResult<()> {
+    let (entry, src) = disk_entry(vfs, host, path).await?;
+    vfs.confine(&entry, &src).await?;
+    if recursive {
+        vfs.check_tree(who, Permission::CanDelete, &entry).await??;
+    } else {
+        vfs.check(who, Permission::CanDelete, &entry)?;
+    }
+    remove(&src, recursive).await?;
+    Ok(())
+}
+
+/// `POST /api/rename`; needs `can_delete` on the item and `can_upload` on its folder.
+pub async fn rename(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    Json(req): Json<RenameRequest>,
+) -> ApiResult<Json<PathResult>> {
+    let vfs = state.vfs();
+    let (entry, src) = disk_entry(&vfs, &host, &req.path).await?;
+    let (folder, _) = resolve_parent(&vfs, &host, &req.path).await?;
+    let name = validate_name(&req.name)?.to_string();
+
+    vfs.confine(&entry, &src).await?;
+    vfs.check(&who, Permission::CanDelete, &entry)?;
+    check_destination(&vfs, &who, &folder, &entry, &name).await?;
+    let dst = src.with_file_name(&name);
+    vfs.confine(&entry, &dst).await?;
+
+    move_path(&src, &dst).await?;
+    Ok(Json(PathResult {
+        path: target_url(&folder, &entry, &name),
+    }))
+}
+
+/// `POST /api/move`; needs `can_delete` on each item (and its contents) and
+/// `can_upload` on the destination.
+pub async fn move_to(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    Json(req): Json<TransferRequest>,
+) -> ApiResult<Json<BatchResult>> {
+    transfer(state, host, who, req, false).await
+}
+
+/// `POST /api/copy`; needs `can_read` on each item (and its contents) and
+/// `can_upload` on the destination.
+pub async fn copy_to(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    Json(req): Json<TransferRequest>,
+) -> ApiResult<Json<BatchResult>> {
+    transfer(state, host, who, req, true).await
+}
+
+async fn transfer(
+    state: AppState,
+    host: RequestHost,
+    who: Principal,
+    req: TransferRequest,
+    copy: bool,
+) -> ApiResult<Json<BatchResult>> {
+    let vfs = state.vfs();
+    let dest = resolve(&vfs, &host, &req.to).await?;
+    if !dest.is_folder() {
+        return Err(ApiError::BadRequest(
+            "destination is not a folder".to_string(),
+        ));
+    }
+
+    let mut results = Vec::with_capacity(req.paths.len());
+    for path in req.paths {
+        let res = transfer_one(&vfs, &who, &host, &path, &dest, copy).await;
+        results.push(ItemResult::new(path, res.map(Some)));
+    }
+    Ok(Json(BatchResult { results }))
+}
+
+async fn transfer_one(
+    vfs: &Vfs,
+    who: &Principal,
+    host: &RequestHost,
+    path: &str,
+    dest: &Entry,
+    copy: bool,
+) -> ApiResult<String> {
+    let (entry, src) = disk_entry(vfs, host, path).await?;
+    vfs.confine(&entry, &src).await?;
+    let perm = if copy {
+        Permission::CanRead
+    } else {
+        Permission::CanDelete
+    };
+    vfs.check_tree(who, perm, &entry).await??;
+    check_destination(vfs, who, dest, &entry, &entry.name).await?;
+
+    let dst = source_dir(dest)?.join(&entry.name);
+    vfs.confine(dest, &dst).await?;
+    let canonical_src = tokio::fs::canonicalize(&src)
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    let canonical_dst = tokio::fs::canonicalize(source_dir(dest)?)
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    if entry.is_folder() && canonical_dst.starts_with(&canonical_src) {
+        return Err(ApiError::BadRequest(
+            "cannot put a folder inside itself".to_string(),
+        ));
+    }
+
+    if copy {
+        copy_path(&src, &dst).await?;
+    } else {
+        move_path(&src, &dst).await?;
+    }
+    Ok(target_url(dest, &entry, &entry.name))
+}
+
+/// `POST /api/mkdir`; needs `can_upload` on the parent folder.
+pub async fn mkdir(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    Json(req): Json<MkdirRequest>,
+) -> ApiResult<Response> {
+    let vfs = state.vfs();
+    let (folder, name) = resolve_parent(&vfs, &host, &req.path).await?;
+    let name = validate_name(&name)?.to_string();
+    if !folder.is_folder() {
+        return Err(ApiError::BadRequest("not a folder".to_string()));
+    }
+    vfs.check(&who, Permission::CanUpload, &folder)?;
+    if vfs.child(&folder, &name).await?.is_some() {
+        return Err(ferri_core::error::Error::AlreadyExists(name.into()).into());
+    }
+
+    let dst = source_dir(&folder)?.join(&name);
+    vfs.confine(&folder, &dst).await?;
+    make_dir(&dst).await?;
+    let path = format!("{}/", join_url(&folder.url_path, &name));
+    Ok((StatusCode::CREATED, Json(PathResult { path })).into_response())
+}
+
+/// Resolve a path to something on disk below a node; nodes themselves are
+/// managed through the admin API.
+async fn disk_entry(vfs: &Vfs, host: &RequestHost, path: &str) -> ApiResult<(Entry, PathBuf)> {
+    let entry = resolve(vfs, host, path).await?;
+    if entry.is_node() {
+        return Err(ApiError::BadRequest(format!(
+            "{} is a VFS node; change it through the admin API",
+            entry.name
+        )));
+    }
+    let src = entry.source.clone().ok_or(ApiError::NotFound)?;
+    Ok((entry, src))
+}
+
+/// Check `can_upload` (and `accept`, for files) on `folder`, and that `name` is free.
+async fn check_destination(
+    vfs: &Vfs,
+    who: &Principal,
+    folder: &Entry,
+    entry: &Entry,
+    name: &str,
+) -> ApiResult<()> {
+    if entry.is_folder() {
+        if !folder.is_folder() {
+            return Err(ApiError::BadRequest("not a folder".to_string()));
+        }
+        vfs.check(who, Permission::CanUpload, folder)?;
+    } else {
+        check_target(vfs, who, folder, name)?;
+    }
+    if vfs.child(folder, name).await?.is_some() {
+        return Err(ferri_core::error::Error::AlreadyExists(name.into()).into());
+    }
+    Ok(())
+}
+
+fn target_url(folder: &Entry, entry: &Entry, name: &str) -> String {
+    let mut url = join_url(&folder.url_path, name);
+    if entry.is_folder() {
+        url.push('/');
+    }
+    url
+}
@@ -0,0 +1,279 @@
+use std::time::SystemTime;
+
+/// An inclusive byte range within a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// `Content-Range` value for this range of a `size`-byte file.
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// What a `Range` header asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// No usable `Range`: send the whole file.
+    Full,
+    /// One or more satisfiable ranges, sorted and coalesced.
+    Partial(Vec<ByteRange>),
+    /// Syntactically valid, but nothing overlaps the file.
+    Unsatisfiable,
+}
+
+/// More ranges than this is more likely abuse than a real client.
+const MAX_RANGES: usize = 16;
+
+/// Parse a `Range` header against a file of `size` bytes (RFC 9110 §14.2).
+///
+/// Malformed headers are ignored rather than rejected, as the RFC asks.
+pub fn parse_range(header: &str, size: u64) -> RangeSpec {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return RangeSpec::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeSpec::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+        let range = if first.is_empty() {
+            // "-n": the last n bytes.
+            let Ok(n) = last.parse::<u64>() else {
+                return RangeSpec::Full;
+            };
+            if n == 0 || size == 0 {
+                continue;
+            }
+            ByteRange {
+                start: size.saturating_sub(n),
+                end: size - 1,
+            }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeSpec::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeSpec::Full,
+                }
+            };
+            if start >= size {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(size - 1),
+            }
+        };
+        ranges.push(range);
+        if ranges.len() > MAX_RANGES {
+            return RangeSpec::Full;
+        }
+    }
+
+    if ranges.is_empty() {
+        return RangeSpec::Unsatisfiable;
+    }
+    RangeSpec::Partial(coalesce(ranges))
+}
+
+/// Sort ranges and merge any that overlap or touch.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut out: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match out.last_mut() {
+            Some(prev) if r.start <= prev.end.saturating_add(1) => prev.end = prev.end.max(r.end),
+            _ => out.push(r),
+        }
+    }
+    out
+}
+
+/// Does any tag in an `If-None-Match` / `If-Range` list match `etag`?
+///
+/// `weak` selects weak comparison (`W/` prefixes ignored), as `If-None-Match` uses.
+pub fn etag_matches(header: &str, etag: &str, weak: bool) -> bool {
+    let header = header.trim();
+    if header == "*" {
+        return true;
+    }
+    let strip = |t: &str| -> Option<String> {
+        let t = t.trim();
+        match t.strip_prefix("W/") {
+            Some(rest) if weak => Some(rest.to_string()),
+            Some(_) => None,
+            None => Some(t.to_string()),
+        }
+    };
+    let Some(ours) = strip(etag) else {
+        return false;
+    };
+    header
+        .split(',')
+        .filter_map(strip)
+        .any(|theirs| theirs == ours)
+}
+
+/// True if `mtime` is no later than the HTTP date in `header` (second precision).
+pub fn not_modified_since(header: &str, mtime: SystemTime) -> bool {
+    let Ok(since) = httpdate::parse_http_date(header.trim()) else {
+        return false;
+    };
+    let mtime = httpdate::HttpDate::from(mtime);
+    SystemTime::from(mtime) <= since
+}
+
+/// `Content-Disposition` with an ASCII fallback and an RFC 5987 `filename*`.
+pub fn content_disposition(name: &str, attachment: bool) -> String {
+    let kind = if attachment { "attachment" } else { "inline" };
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut encoded = String::with_capacity(name.len());
+    for b in name.bytes() {
+        // attr-char from RFC 5987 §3.2.1
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    format!("{kind}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn ranges(pairs: &[(u64, u64)]) -> RangeSpec {
+        RangeSpec::Partial(
+            pairs
+                .iter()
+                .map(|&(start, end)| ByteRange { start, end })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ranges(&[(0, 99)]));
+        assert_eq!(parse_range("bytes=900-", 1000), ranges(&[(900, 999)]));
+        // An end past the file is cut to its last byte.
+        assert_eq!(parse_range("bytes=500-5000", 1000), ranges(&[(500, 999)]));
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(parse_range("bytes=-100", 1000), ranges(&[(900, 999)]));
+        assert_eq!(parse_range("bytes=-5000", 1000), ranges(&[(0, 999)]));
+        assert_eq!(parse_range("bytes=-0", 1000), RangeSpec::Unsatisfiable);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=1000-", 1000), RangeSpec::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=2000-3000", 1000),
+            RangeSpec::Unsatisfiable
+        );
+        // One satisfiable range is enough.
+        assert_eq!(parse_range("bytes=2000-, 0-0", 1000), ranges(&[(0, 0)]));
+    }
+
+    #[test]
+    fn multiple_ranges_are_sorted_and_merged() {
+        assert_eq!(
+            parse_range("bytes=20-29, 0-9", 1000),
+            ranges(&[(0, 9), (20, 29)])
+        );
+        assert_eq!(
+            parse_range("bytes=0-9,5-15,16-20,-10", 1000),
+            ranges(&[(0, 20), (990, 999)])
+        );
+        let many = (0..=MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 10, i * 10))
+            .collect::<Vec<_>>()
+            .join(",");
+        assert_eq!(parse_range(&format!("bytes={many}"), 1000), RangeSpec::Full);
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(parse_range("bytes=0-", 0), RangeSpec::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-10", 0), RangeSpec::Unsatisfiable);
+    }
+
+    #[test]
+    fn malformed_headers_mean_the_whole_file() {
+        for header in [
+            "items=0-9",
+            "bytes=9-0",
+            "bytes=abc",
+            "bytes=1",
+            "bytes=-x",
+            "0-9",
+        ] {
+            assert_eq!(parse_range(header, 1000), RangeSpec::Full, "{header}");
+        }
+    }
+
+    #[test]
+    fn strong_etags() {
+        assert!(etag_matches("\"a\"", "\"a\"", false));
+        assert!(etag_matches("\"x\", \"a\"", "\"a\"", false));
+        assert!(!etag_matches("\"b\"", "\"a\"", false));
+        // Strong comparison never matches weak tags, on either side.
+        assert!(!etag_matches("W/\"a\"", "\"a\"", false));
+        assert!(!etag_matches("\"a\"", "W/\"a\"", false));
+    }
+
+    #[test]
+    fn weak_etags() {
+        assert!(etag_matches("W/\"a\"", "\"a\"", true));
+        assert!(etag_matches("\"a\"", "W/\"a\"", true));
+        assert!(etag_matches("\"x\", W/\"a\"", "\"a\"", true));
+        assert!(!etag_matches("W/\"b\"", "\"a\"", true));
+    }
+
+    #[test]
+    fn any_etag() {
+        assert!(etag_matches("*", "\"a\"", false));
+        assert!(etag_matches(" * ", "W/\"a\"", true));
+    }
+
+    #[test]
+    fn modified_since() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let at = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert!(not_modified_since(date, at));
+        // Sub-second parts are below what the header can say.
+        assert!(not_modified_since(date, at + Duration::from_millis(500)));
+        assert!(not_modified_since(date, at - Duration::from_secs(60)));
+        assert!(!not_modified_since(date, at + Duration::from_secs(1)));
+        assert!(!not_modified_since("yesterday", at));
+    }
+}
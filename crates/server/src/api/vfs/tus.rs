@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::{Duration, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::options;
+use axum::{Extension, Router};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ferri_core::account::Principal;
+use ferri_core::db::Timestamp;
+use ferri_core::upload::{PendingUpload, temp_path};
+use futures_util::TryStreamExt;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::resolve;
+use super::upload::{check_target, finish, target_dir};
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::model::upload::{self, Upload};
+use crate::state::AppState;
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+static TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+static UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+static UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+static UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+static UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+/// Uploads with a PATCH in flight; a second concurrent PATCH is refused.
+static ACTIVE: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(Default::default);
+
+/// How often expired uploads are swept.
+const GC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/tus", options(capabilities).post(create))
+        .route(
+            "/tus/{id}",
+            options(capabilities)
+                .head(status)
+                .patch(append)
+                .delete(terminate),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateQuery {
+    /// Target folder; defaults to the root.
+    #[serde(default)]
+    pub path: String,
+}
+
+/// `OPTIONS /api/tus`: advertise the protocol version and extensions.
+async fn capabilities(State(state): State<AppState>) -> Response {
+    let mut headers = HeaderMap::new();
+    set(&mut headers, "tus-version", TUS_VERSION);
+    set(&mut headers, "tus-extension", TUS_EXTENSIONS);
+    if let Some(max) = state.cfg().max_upload_size {
+        set(&mut headers, "tus-max-size", &max.to_string());
+    }
+    tus_response(StatusCode::NO_CONTENT, headers)
+}
+
+/// `POST /api/tus?path=/folder/` (creation extension).
+///
+/// The file name comes from the `filename` (or `name`) key of `Upload-Metadata`.
+async fn create(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    headers: HeaderMap,
+    Query(q): Query<CreateQuery>,
+) -> ApiResult<Response> {
+    if let Some(res) = version_mismatch(&headers) {
+        return Ok(res);
+    }
+    let length: u64 = header_str(&headers, &UPLOAD_LENGTH)
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| ApiError::BadRequest("missing or invalid Upload-Length".to_string()))?;
+    if let Some(limit) = state.cfg().max_upload_size
+        && length > limit
+    {
+        return Err(ferri_core::error::Error::TooLarge { limit }.into());
+    }
+    let raw_meta = header_str(&headers, &UPLOAD_METADATA).map(str::to_string);
+    let meta = parse_metadata(raw_meta.as_deref().unwrap_or_default())?;
+    let file_name = meta
+        .iter()
+        .find(|(k, _)| k == "filename" || k == "name")
+        .map(|(_, v)| v.clone())
+        .ok_or_else(|| ApiError::BadRequest("Upload-Metadata needs a filename".to_string()))?;
+
+    let vfs = state.vfs();
+    let root = host.root.ok_or(ApiError::NotFound)?;
+    let folder = resolve(&vfs, &host, &q.path).await?;
+    let name = check_target(&vfs, &who, &folder, &file_name)?;
+    let dir = target_dir(&vfs, &folder, &name).await?;
+
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let temp = temp_path(dir, &id);
+    // Create the empty partial file now so a bad target fails at creation time.
+    PendingUpload::resume(dir, &name, &temp, 0, Some(length)).await?;
+
+    let now = Timestamp::now();
+    let expires_at = now + Duration::from_secs(state.cfg().upload_expiry);
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    upload::insert(
+        &mut conn,
+        &Upload {
+            id: id.clone(),
+            account_id: who.id,
+            root_id: root,
+            folder_path: folder.url_path.clone(),
+            name,
+            temp_path: temp.to_string_lossy().into_owned(),
+            length: length as i64,
+            offset: 0,
+            metadata: raw_meta,
+            created_at: now,
+            expires_at,
+        },
+    )
+    .await?;
+
+    let mut out = HeaderMap::new();
+    set(&mut out, "location", &format!("/api/tus/{id}"));
+    set(&mut out, UPLOAD_EXPIRES.as_str(), &http_date(expires_at));
+    Ok(tus_response(StatusCode::CREATED, out))
+}
+
+/// `HEAD /api/tus/{id}`: report how much has been received.
+async fn status(
+    State(state): State<AppState>,
+    Who(who): Who,
+    headers: HeaderMap,
+    UrlPath(id): UrlPath<String>,
+) -> ApiResult<Response> {
+    if let Some(res) = version_mismatch(&headers) {
+        return Ok(res);
+    }
+    let upload = load(&state, &who, &id).await?;
+
+    let mut out = HeaderMap::new();
+    set(&mut out, UPLOAD_OFFSET.as_str(), &upload.offset.to_string());
+    set(&mut out, UPLOAD_LENGTH.as_str(), &upload.length.to_string());
+    set(
+        &mut out,
+        UPLOAD_EXPIRES.as_str(),
+        &http_date(upload.expires_at),
+    );
+    set(&mut out, "cache-control", "no-store");
+    if let Some(meta) = &upload.metadata {
+        set(&mut out, UPLOAD_METADATA.as_str(), meta);
+    }
+    Ok(tus_response(StatusCode::OK, out))
+}
+
+/// `PATCH /api/tus/{id}`: append the body at `Upload-Offset`.
+///
+/// Whatever arrives before a disconnect is kept and acknowledged, so the client
+/// can resume from there. The file is placed once the last byte is in.
+async fn append(
+    State(state): State<AppState>,
+    Who(who): Who,
+    headers: HeaderMap,
+    UrlPath(id): UrlPath<String>,
+    body: Body,
+) -> ApiResult<Response> {
+    if let Some(res) = version_mismatch(&headers) {
+        return Ok(res);
+    }
+    if header_str(&headers, &axum::http::header::CONTENT_TYPE) != Some(OFFSET_CONTENT_TYPE) {
+        return Ok(tus_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            HeaderMap::new(),
+        ));
+    }
+    let claimed: i64 = header_str(&headers, &UPLOAD_OFFSET)
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| ApiError::BadRequest("missing or invalid Upload-Offset".to_string()))?;
+
+    // Claim the upload before reading its offset, so two PATCHes cannot both
+    // pass the check against the same offset.
+    let Some(_busy) = ActiveGuard::acquire(&id) else {
+        return Ok(tus_response(StatusCode::LOCKED, HeaderMap::new()));
+    };
+    let upload = load(&state, &who, &id).await?;
+    if claimed != upload.offset {
+        return Ok(tus_response(StatusCode::CONFLICT, HeaderMap::new()));
+    }
+
+    let vfs = state.vfs();
+    let folder = vfs
+        .resolve_from(upload.root_id, &upload.folder_path)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let name = check_target(&vfs, &who, &folder, &upload.name)?;
+    let dir = target_dir(&vfs, &folder, &name).await?;
+    let mut pending = PendingUpload::resume(
+        dir,
+        &name,
+        Path::new(&upload.temp_path),
+        upload.offset as u64,
+        Some(upload.length as u64),
+    )
+    .await?;
+
+    let mut stream = body.into_data_stream();
+    let received = loop {
+        match stream.try_next().await {
+            Ok(Some(chunk)) => {
+                if let Err(e) = pending.write(&chunk).await {
+                    break Err(ApiError::from(e));
+                }
+            }
+            Ok(None) => break Ok(()),
+            // Client went away: keep what we have.
+            Err(e) => break Err(ApiError::BadRequest(e.to_string())),
+        }
+    };
+    pending.sync().await?;
+    let offset = pending.written() as i64;
+    let expires_at = Timestamp::now() + Duration::from_secs(state.cfg().upload_expiry);
+
+    if offset == upload.length {
+        let saved = finish(&vfs, &who, &folder, pending, state.cfg().upload_collision).await?;
+        let mut conn = state
+            .db
+            .acquire()
+            .await
+            .map_err(ferri_core::error::Error::from)?;
+        upload::delete(&mut conn, &id).await?;
+        info!(upload = %id, path = %saved.path, "resumable upload complete");
+    } else {
+        let mut conn = state
+            .db
+            .acquire()
+            .await
+            .map_err(ferri_core::error::Error::from)?;
+        upload::set_offset(&mut conn, &id, offset, expires_at).await?;
+        received?;
+    }
+
+    let mut out = HeaderMap::new();
+    set(&mut out, UPLOAD_OFFSET.as_str(), &offset.to_string());
+    set(&mut out, UPLOAD_EXPIRES.as_str(), &http_date(expires_at));
+    Ok(tus_response(StatusCode::NO_CONTENT, out))
+}
+
+/// `DELETE /api/tus/{id}` (termination extension).
+async fn terminate(
+    State(state): State<AppState>,
+    Who(who): Who,
+    headers: HeaderMap,
+    UrlPath(id): UrlPath<String>,
+) -> ApiResult<Response> {
+    if let Some(res) = version_mismatch(&headers) {
+        return Ok(res);
+    }
+    let Some(_busy) = ActiveGuard::acquire(&id) else {
+        return Ok(tus_response(StatusCode::LOCKED, HeaderMap::new()));
+    };
+    let upload = load(&state, &who, &id).await?;
+    discard(&state, &upload.id, Path::new(&upload.temp_path)).await?;
+    Ok(tus_response(StatusCode::NO_CONTENT, HeaderMap::new()))
+}
+
+/// Fetch an unexpired upload owned by `who`.
+///
+/// Someone else's upload reads as missing rather than forbidden.
+async fn load(state: &AppState, who: &Principal, id: &str) -> ApiResult<Upload> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    match upload::find(&mut conn, id).await? {
+        Some(u) if u.account_id == who.id => Ok(u),
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+async fn discard(state: &AppState, id: &str, temp: &Path) -> ApiResult<()> {
+    match tokio::fs::remove_file(temp).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(ferri_core::error::Error::from(e).into()),
+    }
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    upload::delete(&mut conn, id).await?;
+    Ok(())
+}
+
+/// Periodically delete expired uploads and the partial files of deleted ones.
+pub fn spawn_gc(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(GC_INTERVAL);
+        loop {
+            tick.tick().await;
+            if let Err(e) = collect_expired(&state).await {
+                warn!("upload cleanup failed: {e}");
+            }
+        }
+    });
+}
+
+async fn collect_expired(state: &AppState) -> ApiResult<()> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    let expired = upload::expired(&mut conn, Timestamp::now()).await?;
+    drop(conn);
+    for u in expired {
+        if ACTIVE.lock().contains(&u.id) {
+            continue;
+        }
+        discard(state, &u.id, &PathBuf::from(u.temp_path)).await?;
+        info!(upload = %u.id, "expired resumable upload removed");
+    }
+    empty_trash(state).await
+}
+
+/// Delete the partial files of uploads whose row is gone.
+async fn empty_trash(state: &AppState) -> ApiResult<()> {
+    let mut conn = state
+        .db
+        .acquire()
+        .await
+        .map_err(ferri_core::error::Error::from)?;
+    for temp in upload::trash(&mut conn).await? {
+        match tokio::fs::remove_file(&temp).await {
+            Ok(()) => info!(path = %temp, "partial upload removed"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(path = %temp, "cannot remove a partial upload: {e}");
+                continue;
+            }
+        }
+        upload::forget(&mut conn, &temp).await?;
+    }
+    Ok(())
+}
+
+/// Marks an upload busy for the duration of a request.
+struct ActiveGuard(String);
+
+impl ActiveGuard {
+    fn acquire(id: &str) -> Option<Self> {
+        ACTIVE
+            .lock()
+            .insert(id.to_string())
+            .then(|| Self(id.to_string()))
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.lock().remove(&self.0);
+    }
+}
+
+/// 412 with the supported version if the client speaks another tus version.
+fn version_mismatch(headers: &HeaderMap) -> Option<Response> {
+    if header_str(headers, &TUS_RESUMABLE) == Some(TUS_VERSION) {
+        return None;
+    }
+    let mut out = HeaderMap::new();
+    set(&mut out, "tus-version", TUS_VERSION);
+    Some(tus_response(StatusCode::PRECONDITION_FAILED, out))
+}
+
+/// Parse `Upload-Metadata`: comma-separated `key base64value` pairs.
+fn parse_metadata(raw: &str) -> ApiResult<Vec<(String, String)>> {
+    let mut out = Vec::new();
+    for pair in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+        let value = STANDARD
+            .decode(value.trim())
+            .ok()
+            .and_then(|v| String::from_utf8(v).ok())
+            .ok_or_else(|| {
+                ApiError::BadRequest(format!("invalid Upload-Metadata value for {key}"))
+            })?;
+        out.push((key.to_string(), value));
+    }
+    Ok(out)
+}
+
+fn tus_response(status: StatusCode, mut headers: HeaderMap) -> Response {
+    headers.insert(TUS_RESUMABLE.clone(), HeaderValue::from_static(TUS_VERSION));
+    (status, headers).into_response()
+}
+
+fn http_date(at: Timestamp) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(at.unix().max(0) as u64))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn set(headers: &mut HeaderMap, name: &str, value: &str) {
+    if let (Ok(n), Ok(v)) = (HeaderName::try_from(name), HeaderValue::from_str(value)) {
+        headers.insert(n, v);
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use axum::body::Body;
+use axum::extract::{Multipart, State};
+use axum::http::{HeaderMap, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use ferri_core::account::Principal;
+use ferri_core::upload::{CollisionPolicy, PendingUpload, accepts, sanitize_name};
+use ferri_core::vfs::perm::Permission;
+use ferri_core::vfs::{Entry, Vfs, join_url};
+use futures_util::TryStreamExt;
+use serde::Serialize;
+
+use super::{resolve, resolve_parent};
+use crate::api::auth::Who;
+use crate::error::{ApiError, ApiResult};
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+/// A file saved by an upload.
+#[derive(Debug, Serialize)]
+pub struct Uploaded {
+    /// Name the file was saved under; differs from the request on a rename collision.
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+}
+
+/// `PUT /folder/name`: store the raw request body as `name`.
+pub async fn put(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Body,
+) -> ApiResult<Response> {
+    let vfs = state.vfs();
+    let (folder, name) = resolve_parent(&vfs, &host, uri.path()).await?;
+    let name = check_target(&vfs, &who, &folder, &name)?;
+
+    let limit = state.cfg().max_upload_size;
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if let (Some(limit), Some(len)) = (limit, declared)
+        && len > limit
+    {
+        return Err(ferri_core::error::Error::TooLarge { limit }.into());
+    }
+
+    let dir = target_dir(&vfs, &folder, &name).await?;
+    let mut pending = PendingUpload::create(dir, &name, limit).await?;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream
+        .try_next()
+        .await
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?
+    {
+        pending.write(&chunk).await?;
+    }
+
+    let saved = finish(&vfs, &who, &folder, pending, state.cfg().upload_collision).await?;
+    Ok((StatusCode::CREATED, Json(saved)).into_response())
+}
+
+/// `POST /folder/` with `multipart/form-data`: store every file field.
+///
+/// The size limit covers the whole request, not each file.
+pub async fn post(
+    State(state): State<AppState>,
+    Extension(host): Extension<RequestHost>,
+    Who(who): Who,
+    uri: Uri,
+    mut multipart: Multipart,
+) -> ApiResult<Response> {
+    let vfs = state.vfs();
+    let folder = resolve(&vfs, &host, uri.path()).await?;
+    if !folder.is_folder() {
+        return Err(ApiError::BadRequest("not a folder".to_string()));
+    }
+
+    let mut remaining = state.cfg().max_upload_size;
+    let mut saved = Vec::new();
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::BadRequest(e.body_text()))?
+    {
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let name = check_target(&vfs, &who, &folder, &file_name)?;
+
+        let dir = target_dir(&vfs, &folder, &name).await?;
+        let mut pending = PendingUpload::create(dir, &name, remaining).await?;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| ApiError::BadRequest(e.body_text()))?
+        {
+            pending.write(&chunk).await?;
+        }
+        remaining = remaining.map(|r| r - pending.written());
+
+        saved.push(finish(&vfs, &who, &folder, pending, state.cfg().upload_collision).await?);
+    }
+
+    if saved.is_empty() {
+        return Err(ApiError::BadRequest("no files in request".to_string()));
+    }
+    Ok((StatusCode::CREATED, Json(saved)).into_response())
+}
+
+/// Check `can_upload` on `folder` and its `accept` pattern; returns the safe name.
+pub(crate) fn check_target(
+    vfs: &Vfs,
+    who: &Principal,
+    folder: &Entry,
+    name: &str,
+) -> ApiResult<String> {
+    if !folder.is_folder() {
+        return Err(ApiError::BadRequest("not a folder".to_string()));
+    }
+    vfs.check(who, Permission::CanUpload, folder)?;
+    let name = sanitize_name(name)?;
+    if let Some(accept) = vfs.props(folder).accept
+        && !accepts(&accept, &name)
+    {
+        return Err(ApiError::NotAccepted(format!(
+            "{name} does not match the accepted types {accept}"
+        )));
+    }
+    Ok(name)
+}
+
+/// The folder's directory on disk, once `name` in it is known to stay inside
+/// the node's `source_path` (a symlinked folder may point elsewhere).
+pub(crate) async fn target_dir<'a>(
+    vfs: &Vfs,
+    folder: &'a Entry,
+    name: &str,
+) -> ApiResult<&'a Path> {
+    let dir = source_dir(folder)?;
+    vfs.confine(folder, &dir.join(name)).await?;
+    Ok(dir)
+}
+
+pub(crate) fn source_dir(folder: &Entry) -> ApiResult<&Path> {
+    folder
+        .source
+        .as_deref()
+        .ok_or_else(|| ApiError::BadRequest("folder is not backed by disk".to_string()))
+}
+
+/// Place a completed upload. Overwriting an existing file needs `can_delete` on it.
+pub(crate) async fn finish(
+    vfs: &Vfs,
+    who: &Principal,
+    folder: &Entry,
+    pending: PendingUpload,
+    policy: CollisionPolicy,
+) -> ApiResult<Uploaded> {
+    if policy == CollisionPolicy::Overwrite
+        && let Some(existing) = vfs.child(folder, pending.name()).await?
+    {
+        vfs.check(who, Permission::CanDelete, &existing)?;
+    }
+
+    let size = pending.written();
+    let path = pending.finish(policy).await?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    Ok(Uploaded {
+        path: join_url(&folder.url_path, &name),
+        name,
+        size,
+    })
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use clap::{Args, Subcommand};
+use ferri_core::account::{self, AccountChanges, AccountFilter, set_verifier};
+use ferri_core::config::Config;
+use ferri_core::db::{Timestamp, transaction};
+use ferri_core::session;
+use ferri_core::srp::StoredVerifier;
+
+use super::{open_db, read_secret};
+
+#[derive(Debug, Subcommand)]
+pub enum AccountCommand {
+    /// Create an account, or a group with `--group`.
+    Add(AddArgs),
+    /// Set an account's password; its sessions end.
+    Passwd(PasswdArgs),
+    /// List accounts and groups.
+    List,
+    /// Delete an account or group.
+    Delete { username: String },
+}
+
+#[derive(Debug, Args)]
+pub struct AddArgs {
+    pub username: String,
+    /// Create a group rather than an account.
+    #[arg(long)]
+    pub group: bool,
+    /// Grant administrator rights (inherited by a group's members).
+    #[arg(long)]
+    pub admin: bool,
+    /// Put it in this group; repeatable.
+    #[arg(long = "member-of", value_name = "GROUP")]
+    pub groups: Vec<String>,
+    #[command(flatten)]
+    pub password: PasswordArgs,
+    /// Leave the account without a password, for logins through an
+    /// identity provider only.
+    #[arg(long, conflicts_with = "password")]
+    pub no_password: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PasswdArgs {
+    pub username: String,
+    #[command(flatten)]
+    pub password: PasswordArgs,
+    /// Make the user pick a new one at their next login.
+    #[arg(long)]
+    pub require_change: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PasswordArgs {
+    /// The password; read from standard input when omitted.
+    #[arg(long, env = "FERRI_PASSWORD", hide_env_values = true)]
+    pub password: Option<String>,
+}
+
+impl PasswordArgs {
+    fn verifier(&self, username: &str) -> anyhow::Result<StoredVerifier> {
+        let password = match &self.password {
+            Some(p) => p.clone(),
+            None => read_secret("Password", true)?,
+        };
+        Ok(StoredVerifier::generate(username, &password))
+    }
+}
+
+pub async fn run(cmd: AccountCommand, cfg: &Config) -> anyhow::Result<()> {
+    let db = open_db(cfg).await?;
+    match cmd {
+        AccountCommand::Add(args) => {
+            let verifier = (!args.group && !args.no_password)
+                .then(|| args.password.verifier(&args.username))
+                .transpose()?;
+            let changes = AccountChanges {
+                admin: Some(args.admin),
+                ..Default::default()
+            };
+            let id = transaction(&db, async |conn| {
+                let id =
+                    account::create_account(conn, &args.username, args.group, &changes).await?;
+                if let Some(v) = &verifier {
+                    set_verifier(conn, id, v).await?;
+                }
+                for group in account::group_ids(conn, &args.groups).await? {
+                    account::add_membership(conn, id, group).await?;
+                }
+                anyhow::Ok(id)
+            })
+            .await?;
+            let kind = if args.group { "group" } else { "account" };
+            println!("created {kind} {} (id {id})", args.username);
+        }
+        AccountCommand::Passwd(args) => {
+            let mut conn = db.acquire().await?;
+            let Some(found) = account::find_by_name(&mut conn, &args.username).await? else {
+                anyhow::bail!("no account named {}", args.username);
+            };
+            anyhow::ensure!(!found.is_group, "{} is a group", args.username);
+            drop(conn);
+            let verifier = args.password.verifier(&args.username)?;
+            transaction(&db, async |conn| {
+                set_verifier(conn, found.id, &verifier).await?;
+                let changes = AccountChanges {
+                    require_password_change: Some(args.require_change),
+                    ..Default::default()
+                };
+                account::update_account(conn, found.id, &changes).await?;
+                anyhow::Ok(())
+            })
+            .await?;
+            let ended = session::revoke_all(&db, found.id).await?;
+            println!(
+                "password set for {}; {ended} session(s) ended",
+                args.username
+            );
+        }
+        AccountCommand::List => {
+            let (all, _) =
+                account::list_accounts(&db, &AccountFilter::default(), i64::MAX, 0).await?;
+            let mut conn = db.acquire().await?;
+            let names: HashMap<i64, &str> =
+                all.iter().map(|a| (a.id, a.username.as_str())).collect();
+            let now = Timestamp::now();
+            println!(
+                "{:>5}  {:<24}  {:<7}  {:<28}  GROUPS",
+                "ID", "NAME", "KIND", "FLAGS"
+            );
+            for a in &all {
+                let groups: Vec<&str> = account::memberships(&mut conn, a.id)
+                    .await?
+                    .iter()
+                    .filter_map(|m| names.get(&m.group_id).copied())
+                    .collect();
+                let mut flags = Vec::new();
+                if a.admin {
+                    flags.push("admin");
+                }
+                if a.disabled {
+                    flags.push("disabled");
+                }
+                if a.expire.is_some_and(|t| t <= now) {
+                    flags.push("expired");
+                }
+                if !a.is_group && !a.has_password() {
+                    flags.push("no-password");
+                }
+                if a.require_password_change {
+                    flags.push("must-change");
+                }
+                println!(
+                    "{:>5}  {:<24}  {:<7}  {:<28}  {}",
+                    a.id,
+                    a.username,
+                    if a.is_group { "group" } else { "account" },
+                    flags.join(","),
+                    groups.join(","),
+                );
+            }
+        }
+        AccountCommand::Delete { username } => {
+            let mut conn = db.acquire().await?;
+            let Some(found) = account::find_by_name(&mut conn, &username).await? else {
+                anyhow::bail!("no account named {username}");
+            };
+            account::delete_account(&mut conn, found.id).await?;
+            let kind = if found.is_group { "group" } else { "account" };
+            println!("deleted {kind} {username} (id {})", found.id);
+        }
+    }
+    Ok(())
+}
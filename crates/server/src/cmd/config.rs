@@ -0,0 +1,87 @@
+use clap::Subcommand;
+use ferri_core::config::{Config, Severity, config_files};
+
+use super::GlobalArgs;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the config in effect, after every layer is applied.
+    Show {
+        /// Print master keys and client secrets instead of hiding them.
+        #[arg(long)]
+        show_secrets: bool,
+    },
+    /// Check that the server would start with this config, and report
+    /// anything suspicious.
+    Check,
+    /// Print where the config, database and logs are looked for.
+    Paths,
+}
+
+pub fn run(cmd: ConfigCommand, cfg: &Config, global: &GlobalArgs) -> anyhow::Result<()> {
+    match cmd {
+        ConfigCommand::Show { show_secrets } => {
+            let mut cfg = cfg.clone();
+            if !show_secrets {
+                redact(&mut cfg);
+            }
+            for path in config_files(&global.dirs()?, global.config.as_deref()) {
+                if path.exists() {
+                    println!("# read {}", path.display());
+                }
+            }
+            print!("{}", toml::to_string_pretty(&cfg)?);
+        }
+        ConfigCommand::Check => {
+            let issues = cfg.validate();
+            for issue in &issues {
+                println!("{issue}");
+            }
+            let errors = issues
+                .iter()
+                .filter(|i| i.severity == Severity::Error)
+                .count();
+            let warnings = issues.len() - errors;
+            anyhow::ensure!(
+                errors == 0,
+                "{errors} error(s) and {warnings} warning(s) in the config"
+            );
+            if warnings > 0 {
+                println!("config ok, with {warnings} warning(s)");
+            } else {
+                println!("config ok");
+            }
+        }
+        ConfigCommand::Paths => {
+            let dirs = global.dirs()?;
+            for path in config_files(&dirs, global.config.as_deref()) {
+                let state = if path.exists() { "" } else { " (missing)" };
+                println!("config    {}{state}", path.display());
+            }
+            println!("database  {}", cfg.db_path);
+            for path in [&cfg.log_path, &cfg.log_error_path].into_iter().flatten() {
+                println!("logs      {path}");
+            }
+            if dirs.portable {
+                println!("(portable mode)");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn redact(cfg: &mut Config) {
+    if cfg.master_key.is_some() {
+        cfg.master_key = Some(REDACTED.to_string());
+    }
+    for key in &mut cfg.old_master_keys {
+        *key = REDACTED.to_string();
+    }
+    for p in &mut cfg.oidc_providers {
+        if p.client_secret.is_some() {
+            p.client_secret = Some(REDACTED.to_string());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Subcommand;
+use ferri_core::config::Config;
+use ferri_core::db::{bootstrap_db, init_db};
+use sqlx::{Pool, Sqlite};
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Apply pending schema migrations.
+    Migrate,
+    /// Write a consistent copy of the database, safe while the server runs.
+    Backup {
+        /// File to write.
+        dest: PathBuf,
+        /// Replace `dest` if it exists.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Rebuild the database file to reclaim free space.
+    Vacuum,
+}
+
+pub async fn run(cmd: DbCommand, cfg: &Config) -> anyhow::Result<()> {
+    let db = init_db(cfg)?;
+    match cmd {
+        DbCommand::Migrate => {
+            let before = applied(&db).await;
+            bootstrap_db(&db).await?;
+            let after = applied(&db).await;
+            println!(
+                "{}: {} migration(s) applied, {after} in total",
+                cfg.db_path,
+                after - before
+            );
+        }
+        DbCommand::Backup { dest, force } => {
+            if dest.exists() {
+                anyhow::ensure!(
+                    force,
+                    "{} exists; pass --force to replace it",
+                    dest.display()
+                );
+                std::fs::remove_file(&dest)
+                    .with_context(|| format!("cannot replace {}", dest.display()))?;
+            }
+            let dest_str = dest.to_string_lossy();
+            sqlx::query!("VACUUM INTO ?", dest_str)
+                .execute(&db)
+                .await
+                .with_context(|| format!("cannot back up to {}", dest.display()))?;
+            println!("{} -> {} ({})", cfg.db_path, dest.display(), size(&dest));
+        }
+        DbCommand::Vacuum => {
+            let path = Path::new(&cfg.db_path);
+            let before = size(path);
+            sqlx::query!("VACUUM").execute(&db).await?;
+            // Its result columns have no type for `query!` to check.
+            sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+                .execute(&db)
+                .await?;
+            println!("{}: {before} -> {}", cfg.db_path, size(path));
+        }
+    }
+    db.close().await;
+    Ok(())
+}
+
+/// Migrations recorded as applied; none for a new database.
+async fn applied(db: &Pool<Sqlite>) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!: i64" FROM _sqlx_migrations WHERE success"#)
+        .fetch_one(db)
+        .await
+        .unwrap_or(0)
+}
+
+fn size(path: &Path) -> String {
+    match std::fs::metadata(path) {
+        Ok(m) => format!("{} KiB", m.len().div_ceil(1024)),
+        Err(_) => "?".to_string(),
+    }
+}
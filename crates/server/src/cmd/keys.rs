@@ -0,0 +1,38 @@
+use clap::Subcommand;
+use ferri_core::config::Config;
+use ferri_core::envelope::{Keyring, MasterKey};
+use ferri_core::oidc::rotate_token_keys;
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Print a new random master key (base64).
+    Generate,
+    /// Re-wrap every stored secret under the current master key.
+    ///
+    /// Set the new key as `master_key` and list the previous one in
+    /// `old_master_keys` first; the old key can be removed afterwards.
+    Rotate,
+}
+
+pub async fn run(cmd: KeysCommand, cfg: &Config) -> anyhow::Result<()> {
+    match cmd {
+        KeysCommand::Generate => {
+            let key = MasterKey::generate();
+            println!("{}", key.to_base64());
+            eprintln!("key id: {}", key.id());
+        }
+        KeysCommand::Rotate => {
+            let keys = Keyring::from_config(cfg)?;
+            let Some(current) = keys.current() else {
+                anyhow::bail!("no master key configured; set master_key or FERRI_MASTER_KEY");
+            };
+            let db = super::open_db(cfg).await?;
+            let rows = rotate_token_keys(&db, &keys).await?;
+            println!(
+                "re-wrapped {rows} provider token row(s) under key {}",
+                current.id()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::io::{self, BufRead, IsTerminal};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::builder::BoolishValueParser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use ferri_core::config::{Config, load_config};
+use ferri_core::db::{bootstrap_db, init_db};
+use ferri_core::paths::Dirs;
+use sqlx::{Pool, Sqlite};
+
+pub mod account;
+pub mod config;
+pub mod db;
+pub mod keys;
+pub mod serve;
+pub mod vfs;
+
+#[derive(Debug, Parser)]
+#[command(name = "ferri", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    /// Runs the server when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Accepted by every subcommand.
+#[derive(Debug, Clone, Args)]
+pub struct GlobalArgs {
+    /// Config file to read instead of the user one; it must exist.
+    #[arg(long, global = true, value_name = "FILE", env = "FERRI_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Set a config key, over files and `FERRI_*` variables; repeatable.
+    #[arg(long, global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    pub set: Vec<(String, String)>,
+    /// Keep the database and logs here, whatever the config says.
+    #[arg(long, global = true, value_name = "DIR", env = "FERRI_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Keep the config, database and logs beside the executable.
+    #[arg(long, global = true, env = "FERRI_PORTABLE", value_parser = BoolishValueParser::new())]
+    pub portable: bool,
+}
+
+impl GlobalArgs {
+    pub fn dirs(&self) -> anyhow::Result<Dirs> {
+        Dirs::resolve(self.portable, self.data_dir.as_deref())
+            .context("cannot locate the executable for portable mode")
+    }
+
+    /// Move files left beside the executable by earlier versions into the
+    /// standard directories. Done before the config is read by the commands
+    /// that open the database.
+    pub fn migrate_legacy(&self) -> anyhow::Result<()> {
+        if self.data_dir.is_some() {
+            return Ok(());
+        }
+        let moved = self
+            .dirs()?
+            .migrate_legacy()
+            .context("cannot move files from beside the executable")?;
+        for (from, to) in moved {
+            eprintln!("moved {} to {}", from.display(), to.display());
+        }
+        Ok(())
+    }
+
+    /// Load the config from the files and the command line.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        let dirs = self.dirs()?;
+        let mut cfg =
+            load_config(&dirs, self.config.as_deref(), &self.set).context("cannot load config")?;
+        if let Some(dir) = &self.data_dir {
+            cfg.set_data_dir(dir);
+        }
+        Ok(cfg)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the server (the default).
+    Serve,
+    /// Manage accounts and groups.
+    #[command(subcommand)]
+    Account(account::AccountCommand),
+    /// Manage the virtual file system.
+    ///
+    /// A running server sees the changes after a restart.
+    #[command(subcommand)]
+    Vfs(vfs::VfsCommand),
+    /// Maintain the database.
+    #[command(subcommand)]
+    Db(db::DbCommand),
+    /// Inspect the configuration.
+    #[command(subcommand)]
+    Config(config::ConfigCommand),
+    /// Manage the master keys that seal secrets in the database.
+    #[command(subcommand)]
+    Keys(keys::KeysCommand),
+    /// Print a completion script for a shell.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+impl Command {
+    /// Whether the command reads or writes the database; only those move an
+    /// earlier version's files into place first.
+    fn opens_db(&self) -> bool {
+        match self {
+            Command::Serve | Command::Account(_) | Command::Vfs(_) | Command::Db(_) => true,
+            Command::Keys(cmd) => matches!(cmd, keys::KeysCommand::Rotate),
+            Command::Config(_) | Command::Completions { .. } => false,
+        }
+    }
+}
+
+pub async fn run(cli: Cli) -> anyhow::Result<()> {
+    let command = cli.command.unwrap_or(Command::Serve);
+    if let Command::Completions { shell } = command {
+        clap_complete::generate(shell, &mut Cli::command(), "ferri", &mut io::stdout());
+        return Ok(());
+    }
+    if command.opens_db() {
+        cli.global.migrate_legacy()?;
+    }
+    let cfg = cli.global.load_config()?;
+    match command {
+        Command::Serve => serve::run(cfg, &cli.global).await,
+        Command::Account(cmd) => account::run(cmd, &cfg).await,
+        Command::Vfs(cmd) => vfs::run(cmd, &cfg).await,
+        Command::Db(cmd) => db::run(cmd, &cfg).await,
+        Command::Config(cmd) => config::run(cmd, &cfg, &cli.global),
+        Command::Keys(cmd) => keys::run(cmd, &cfg).await,
+        Command::Completions { .. } => unreachable!(),
+    }
+}
+
+fn parse_setting(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got {s:?}")),
+    }
+}
+
+/// Open the database and bring its schema up to date.
+async fn open_db(cfg: &Config) -> anyhow::Result<Pool<Sqlite>> {
+    let db = init_db(cfg)?;
+    bootstrap_db(&db)
+        .await
+        .with_context(|| format!("cannot open database {}", cfg.db_path))?;
+    Ok(db)
+}
+
+/// Read one line from standard input. On a terminal it is prompted for
+/// without echo, and asked for twice when `confirm` is set.
+fn read_secret(prompt: &str, confirm: bool) -> anyhow::Result<String> {
+    let what = prompt.to_lowercase();
+    let stdin = io::stdin();
+    let line = if stdin.is_terminal() {
+        let line = rpassword::prompt_password(format!("{prompt}: "))?;
+        if confirm && !line.is_empty() {
+            let again = rpassword::prompt_password(format!("Repeat {what}: "))?;
+            anyhow::ensure!(again == line, "the {what}s do not match");
+        }
+        line
+    } else {
+        let mut line = String::new();
+        stdin.lock().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_string()
+    };
+    anyhow::ensure!(!line.is_empty(), "no {what} given");
+    Ok(line)
+}
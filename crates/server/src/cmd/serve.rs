@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use axum::middleware;
+use ferri_core::config::{Config, Severity};
+use ferri_core::db::{bootstrap_db, init_db};
+use ferri_core::logger::init_logger;
+use tracing::warn;
+
+use super::GlobalArgs;
+use crate::reload::{self, Reloader};
+use crate::state::AppState;
+use crate::{api, host, session, tls};
+
+pub async fn run(cfg: Config, global: &GlobalArgs) -> anyhow::Result<()> {
+    // Reported before the logger starts, since a bad log setting stops it.
+    let issues = cfg.validate();
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        for issue in &issues {
+            eprintln!("{issue}");
+        }
+        anyhow::bail!("{errors} error(s) in the config; see `ferri config check`");
+    }
+    let (_guards, log_filter) = init_logger(&cfg)?;
+    for issue in &issues {
+        warn!(key = issue.key, hint = %issue.hint, "config: {}", issue.message);
+    }
+
+    let db = init_db(&cfg)?;
+    bootstrap_db(&db).await?;
+    let reloader = Reloader::new(global.clone(), log_filter);
+    let state = AppState::new(cfg, db, reloader).await?;
+    if let Err(e) = reload::spawn_watcher(state.clone()) {
+        warn!("config changes will not be picked up: {e:#}");
+    }
+    api::vfs::tus::spawn_gc(state.clone());
+    session::spawn_flush(state.clone());
+    session::spawn_account_sweep(state.clone());
+
+    let app = Router::new()
+        .nest("/api", api::router())
+        .fallback(api::vfs::files())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            session::authenticate,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), tls::redirect))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            host::dispatch,
+        ))
+        .with_state(state.clone());
+    let cfg = state.cfg();
+    let listener = tokio::net::TcpListener::bind((cfg.addr.as_str(), cfg.port)).await?;
+    if cfg.tls_enabled() {
+        tls::reload(&state)?;
+        let acceptor = tls::acceptor(state.certs.clone())?;
+        let tls_listener =
+            tokio::net::TcpListener::bind((cfg.addr.as_str(), cfg.https_port)).await?;
+        tokio::spawn(tls::serve(tls_listener, acceptor, app.clone()));
+    }
+
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use clap::{Args, Subcommand};
+use ferri_core::config::Config;
+use ferri_core::db::transaction;
+use ferri_core::vfs::edit::{self, NodeChanges};
+use ferri_core::vfs::{NodeId, VfsNode};
+
+use super::open_db;
+
+#[derive(Debug, Subcommand)]
+pub enum VfsCommand {
+    /// Add a disk folder or file, a link, or a virtual folder.
+    Add(AddArgs),
+    /// Show the tree.
+    Ls,
+    /// Remove a node and everything below it; files on disk are kept.
+    Rm { id: NodeId },
+}
+
+#[derive(Debug, Args)]
+#[command(arg_required_else_help = true)]
+pub struct AddArgs {
+    /// A path on disk, or an http(s) URL for a link. Leave it out, with
+    /// `--name`, for a virtual folder.
+    #[arg(required_unless_present = "name")]
+    pub source: Option<String>,
+    /// Node to add it under (see `ferri vfs ls`); the first root when omitted.
+    #[arg(long, value_name = "ID")]
+    pub parent: Option<NodeId>,
+    /// Name it is listed under; defaults to the last part of the path.
+    #[arg(long)]
+    pub name: Option<String>,
+}
+
+pub async fn run(cmd: VfsCommand, cfg: &Config) -> anyhow::Result<()> {
+    let db = open_db(cfg).await?;
+    match cmd {
+        VfsCommand::Add(args) => {
+            let (source_path, url) = match args.source {
+                Some(s) if s.starts_with("http://") || s.starts_with("https://") => (None, Some(s)),
+                Some(s) => {
+                    let path =
+                        std::fs::canonicalize(&s).with_context(|| format!("cannot resolve {s}"))?;
+                    (Some(path.to_string_lossy().into_owned()), None)
+                }
+                None => (None, None),
+            };
+            let node = transaction(&db, async |conn| {
+                let parent = match args.parent {
+                    Some(id) => Some(id),
+                    None => edit::list_nodes(conn)
+                        .await?
+                        .iter()
+                        .find(|n| n.parent_id.is_none())
+                        .map(|n| n.id),
+                };
+                let changes = NodeChanges {
+                    parent_id: Some(parent),
+                    name: args.name.map(Some),
+                    source_path: Some(source_path),
+                    url: Some(url),
+                    ..Default::default()
+                };
+                let id = edit::create_node(conn, &changes).await?;
+                anyhow::Ok(edit::find_node(conn, id).await?.context("node vanished")?)
+            })
+            .await?;
+            match node.parent_id {
+                Some(parent) => println!(
+                    "added {} (id {}) under node {parent}",
+                    node.display_name(),
+                    node.id
+                ),
+                None => println!("added {} (id {}) as a root", node.display_name(), node.id),
+            }
+        }
+        VfsCommand::Ls => {
+            let mut conn = db.acquire().await?;
+            let nodes = edit::list_nodes(&mut conn).await?;
+            let mut children: HashMap<Option<NodeId>, Vec<&VfsNode>> = HashMap::new();
+            for n in &nodes {
+                children.entry(n.parent_id).or_default().push(n);
+            }
+            println!("{:>5}  NODE", "ID");
+            print_tree(&children, None, 0);
+        }
+        VfsCommand::Rm { id } => {
+            let mut conn = db.acquire().await?;
+            let Some(node) = edit::find_node(&mut conn, id).await? else {
+                anyhow::bail!("no node {id}");
+            };
+            let below = descendants(&edit::list_nodes(&mut conn).await?, id);
+            drop(conn);
+            transaction(&db, async |conn| {
+                edit::delete_node(conn, id).await?;
+                anyhow::Ok(())
+            })
+            .await?;
+            println!(
+                "removed {} (id {id}) and {below} node(s) below it",
+                node.display_name()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_tree(
+    children: &HashMap<Option<NodeId>, Vec<&VfsNode>>,
+    parent: Option<NodeId>,
+    depth: usize,
+) {
+    for node in children.get(&parent).into_iter().flatten() {
+        let target = match (&node.source_path, &node.url) {
+            (Some(path), _) => format!("  <- {path}"),
+            (None, Some(url)) => format!("  -> {url}"),
+            (None, None) => String::new(),
+        };
+        println!(
+            "{:>5}  {}{}{target}",
+            node.id,
+            "  ".repeat(depth),
+            node.display_name()
+        );
+        print_tree(children, Some(node.id), depth + 1);
+    }
+}
+
+fn descendants(nodes: &[VfsNode], id: NodeId) -> usize {
+    nodes
+        .iter()
+        .filter(|n| n.parent_id == Some(id))
+        .map(|n| 1 + descendants(nodes, n.id))
+        .sum()
+}
@@ -0,0 +1,105 @@
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use ferri_core::error::Error as CoreError;
+use ferri_core::vfs::perm::{Denial, DenyReason};
+use serde_json::json;
+use thiserror::Error;
+use tracing::error;
+
+/// Errors returned by API handlers, rendered as `{"error": "..."}`.
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("not found")]
+    NotFound,
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("login required")]
+    LoginRequired,
+    #[error("admin rights required")]
+    AdminRequired,
+    /// The session may only be used to set a new password.
+    #[error("password change required")]
+    PasswordChangeRequired,
+    /// The client's address is refused by the global network lists.
+    #[error("access from this address is not allowed")]
+    AddressDenied,
+    #[error("{0}")]
+    Denied(Denial),
+    /// Upload rejected by the folder's `accept` pattern.
+    #[error("{0}")]
+    NotAccepted(String),
+    #[error(transparent)]
+    Core(#[from] CoreError),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::LoginRequired => StatusCode::UNAUTHORIZED,
+            ApiError::AdminRequired
+            | ApiError::AddressDenied
+            | ApiError::PasswordChangeRequired => StatusCode::FORBIDDEN,
+            ApiError::Denied(d) if d.reason == DenyReason::LoginRequired => {
+                StatusCode::UNAUTHORIZED
+            }
+            ApiError::Denied(_) => StatusCode::FORBIDDEN,
+            ApiError::NotAccepted(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Core(e) => match e {
+                CoreError::InvalidPath(_)
+                | CoreError::InvalidName(_)
+                | CoreError::InvalidUsername(_)
+                | CoreError::InvalidNetMask(_)
+                | CoreError::NotAGroup(_)
+                | CoreError::InvalidNode(_) => StatusCode::BAD_REQUEST,
+                CoreError::UsernameTaken(_) | CoreError::MembershipCycle { .. } => {
+                    StatusCode::CONFLICT
+                }
+                CoreError::AlreadyExists(_) | CoreError::NotEmpty(_) => StatusCode::CONFLICT,
+                CoreError::OutsideRoot(_) => StatusCode::FORBIDDEN,
+                CoreError::AuthFailed | CoreError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+                CoreError::OidcState => StatusCode::BAD_REQUEST,
+                CoreError::NotLinked
+                | CoreError::NetDenied
+                | CoreError::AccountDisabled
+                | CoreError::AccountExpired
+                | CoreError::PasswordChangeDisabled => StatusCode::FORBIDDEN,
+                CoreError::IdentityInUse => StatusCode::CONFLICT,
+                CoreError::Oidc(_) => StatusCode::BAD_GATEWAY,
+                CoreError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<Denial> for ApiError {
+    fn from(d: Denial) -> Self {
+        ApiError::Denied(d)
+    }
+}
+
+impl ApiError {
+    /// Message safe to show a client; server errors are logged and hidden.
+    pub fn client_message(&self) -> String {
+        if self.status().is_server_error() {
+            error!("request failed: {self:#}");
+            return "internal error".to_string();
+        }
+        self.to_string()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        (status, Json(json!({ "error": self.client_message() }))).into_response()
+    }
+}
+
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
@@ -0,0 +1,102 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::HOST;
+use axum::http::{HeaderMap, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use ferri_core::vfs::NodeId;
+use ferri_core::vfs::host::strip_port;
+
+use crate::error::ApiError;
+use crate::state::AppState;
+use crate::tls::TlsConnection;
+
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Host a request was addressed to and the VFS root serving it.
+///
+/// Inserted into request extensions by [`dispatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHost {
+    /// Host name without port, lower-cased; `None` if the client sent none.
+    pub host: Option<String>,
+    /// Host as the client sent it, port included; for building absolute URLs.
+    pub authority: Option<String>,
+    /// Root node for this host; `None` when the VFS is empty.
+    pub root: Option<NodeId>,
+    /// The client used HTTPS: the request came in on `https_port`, or a
+    /// trusted proxy says so.
+    pub secure: bool,
+}
+
+/// Address of the client, seen through trusted proxies; `None` when the
+/// connection info is unavailable.
+///
+/// Inserted into request extensions by [`dispatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+/// Middleware that applies the global network lists and picks the VFS root for
+/// each request from its host.
+pub async fn dispatch(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ci| ci.0.ip());
+    let net = state.net();
+    let trusted = peer.is_some_and(|ip| net.is_trusted_proxy(ip));
+    let client = peer.map(|ip| {
+        let forwarded = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>();
+        net.client_ip(ip, forwarded.into_iter())
+    });
+    if !net.access.permits(client) {
+        return ApiError::AddressDenied.into_response();
+    }
+
+    let authority = request_host(req.headers(), req.uri(), trusted);
+    let host = authority
+        .as_deref()
+        .map(|a| strip_port(a).to_ascii_lowercase());
+    let root = state.vfs().root_for_host(host.as_deref());
+    let secure = req.extensions().get::<TlsConnection>().is_some()
+        || trusted
+            && header_str(req.headers(), X_FORWARDED_PROTO)
+                .is_some_and(|p| p.split(',').next().unwrap_or_default().trim() == "https");
+
+    req.extensions_mut().insert(RequestHost {
+        host,
+        authority,
+        root,
+        secure,
+    });
+    req.extensions_mut().insert(ClientIp(client));
+    next.run(req).await
+}
+
+/// `X-Forwarded-Host` when the peer is a trusted proxy, else `Host`, or the
+/// `:authority` of an HTTP/2 request; port included.
+fn request_host(headers: &HeaderMap, uri: &Uri, from_proxy: bool) -> Option<String> {
+    let forwarded = from_proxy
+        .then(|| header_str(headers, X_FORWARDED_HOST))
+        .flatten()
+        // Proxies append; the first value is what the client asked for.
+        .and_then(|v| v.split(',').next());
+    let raw = forwarded
+        .or_else(|| header_str(headers, HOST.as_str()))
+        .or_else(|| uri.authority().map(|a| a.as_str()))?;
+
+    let raw = raw.trim();
+    (!strip_port(raw).is_empty()).then(|| raw.to_string())
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
@@ -1,21 +1,18 @@
-use axum::Router;
-use axum::routing::get;
-use ferri_core::config::load_config;
-use ferri_core::logger::init_logger;
+use clap::Parser;
+
+use crate::cmd::Cli;
 
 mod api;
 mod cmd;
+mod error;
+mod host;
 mod model;
+mod reload;
+mod session;
+mod state;
+mod tls;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cfg = load_config()?;
-    let _guards = init_logger(&cfg)?;
-
-    let app = Router::new().route("/", get(|| async { "Hello, World!" }));
-    let listener = tokio::net::TcpListener::bind(format!("{}:{}", cfg.addr, cfg.port)).await?;
-
-    axum::serve(listener, app).await?;
-
-    Ok(())
+    cmd::run(Cli::parse()).await
 }
@@ -0,0 +1,8 @@
+//! Typed rows for the tables only the server uses, and the queries over them.
+//!
+//! The shared tables have theirs in `ferri_core`, along with the column types
+//! ([`Timestamp`](ferri_core::db::Timestamp),
+//! [`JsonText`](ferri_core::db::JsonText)) and the
+//! [`transaction`](ferri_core::db::transaction) helper; see `ferri_core::db`.
+
+pub mod upload;
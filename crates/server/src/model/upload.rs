@@ -0,0 +1,125 @@
+//! `uploads`: resumable uploads in progress, and `upload_trash`: partial
+//! files left behind by deleted ones.
+
+use ferri_core::db::Timestamp;
+use ferri_core::error::Result;
+use serde::Serialize;
+use sqlx::{FromRow, SqliteConnection};
+
+/// A row of `uploads`.
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize)]
+pub struct Upload {
+    pub id: String,
+    /// `None` for an anonymous upload.
+    pub account_id: Option<i64>,
+    pub root_id: i64,
+    /// URL path of the target folder under `root_id`.
+    pub folder_path: String,
+    pub name: String,
+    /// Where the partial data sits, beside the target.
+    pub temp_path: String,
+    pub length: i64,
+    /// Bytes received so far.
+    pub offset: i64,
+    /// The raw `Upload-Metadata` header.
+    pub metadata: Option<String>,
+    pub created_at: Timestamp,
+    pub expires_at: Timestamp,
+}
+
+/// An upload that has not expired.
+pub async fn find(conn: &mut SqliteConnection, id: &str) -> Result<Option<Upload>> {
+    let now = Timestamp::now();
+    Ok(sqlx::query_as!(
+        Upload,
+        r#"SELECT id AS "id!", account_id, root_id, folder_path, name, temp_path, length,
+                  offset, metadata, created_at AS "created_at: Timestamp",
+                  expires_at AS "expires_at: Timestamp"
+           FROM uploads WHERE id = ? AND expires_at > ?"#,
+        id,
+        now
+    )
+    .fetch_optional(conn)
+    .await?)
+}
+
+/// `created_at` is set by the database.
+pub async fn insert(conn: &mut SqliteConnection, upload: &Upload) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO uploads (id, account_id, root_id, folder_path, name, temp_path, length,
+                offset, metadata, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        upload.id,
+        upload.account_id,
+        upload.root_id,
+        upload.folder_path,
+        upload.name,
+        upload.temp_path,
+        upload.length,
+        upload.offset,
+        upload.metadata,
+        upload.expires_at
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Record progress and push the expiry forward.
+pub async fn set_offset(
+    conn: &mut SqliteConnection,
+    id: &str,
+    offset: i64,
+    expires_at: Timestamp,
+) -> Result<bool> {
+    let done = sqlx::query!(
+        "UPDATE uploads SET offset = ?, expires_at = ? WHERE id = ?",
+        offset,
+        expires_at,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+pub async fn delete(conn: &mut SqliteConnection, id: &str) -> Result<bool> {
+    let done = sqlx::query!("DELETE FROM uploads WHERE id = ?", id)
+        .execute(conn)
+        .await?;
+    Ok(done.rows_affected() > 0)
+}
+
+/// Uploads that expired at or before `now`.
+pub async fn expired(conn: &mut SqliteConnection, now: Timestamp) -> Result<Vec<Upload>> {
+    Ok(sqlx::query_as!(
+        Upload,
+        r#"SELECT id AS "id!", account_id, root_id, folder_path, name, temp_path, length,
+                  offset, metadata, created_at AS "created_at: Timestamp",
+                  expires_at AS "expires_at: Timestamp"
+           FROM uploads WHERE expires_at <= ?"#,
+        now
+    )
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Partial files of deleted uploads that may still be on disk.
+///
+/// A trigger fills `upload_trash` whenever an `uploads` row goes, so this
+/// also covers rows removed along with their account or root node.
+pub async fn trash(conn: &mut SqliteConnection) -> Result<Vec<String>> {
+    Ok(
+        sqlx::query_scalar!(r#"SELECT temp_path AS "temp_path!" FROM upload_trash"#)
+            .fetch_all(conn)
+            .await?,
+    )
+}
+
+/// Forget a trashed partial file once it is gone from disk.
+pub async fn forget(conn: &mut SqliteConnection, temp_path: &str) -> Result<()> {
+    sqlx::query!("DELETE FROM upload_trash WHERE temp_path = ?", temp_path)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
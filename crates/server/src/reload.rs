@@ -0,0 +1,300 @@
+//! Reloading the config while the server runs.
+//!
+//! The config files, and the TLS certificates they name, are watched, and
+//! administrators can ask for a reload through the API. A new config is
+//! validated first and dropped whole if it has errors. Settings read as
+//! requests come in (limits, timeouts, network lists, the title, TLS
+//! certificates) and the log filter change at once. Those bound at startup
+//! (listen addresses, paths, identity providers, master keys, turning HTTPS on
+//! or off) keep their running values and are reported until a restart.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use ferri_core::config::{Config, Issue, Severity, config_files};
+use ferri_core::db::Timestamp;
+use ferri_core::logger::LogFilter;
+use ferri_core::net::NetPolicy;
+use notify::RecommendedWatcher;
+use notify::{RecursiveMode, Watcher};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::cmd::GlobalArgs;
+use crate::state::AppState;
+use crate::tls;
+
+/// Keys that only take effect on a restart.
+const RESTART_KEYS: &[&str] = &[
+    "addr",
+    "port",
+    "https_port",
+    "db_path",
+    "log_path",
+    "log_error_path",
+    "log_rotation",
+    "oidc_providers",
+    "master_key",
+    "master_key_file",
+    "old_master_keys",
+];
+
+/// Keys naming the HTTPS certificates; they take effect at once unless HTTPS
+/// is turned on or off.
+const TLS_KEYS: &[&str] = &["tls_cert", "tls_key", "tls_certs"];
+
+/// How long to wait for more events after a file changes; editors save in
+/// several steps.
+const SETTLE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    /// A config file changed.
+    File,
+    /// An administrator asked for it.
+    Admin,
+}
+
+/// What a reload did.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadReport {
+    pub at: Timestamp,
+    pub trigger: Trigger,
+    /// The new config was taken; not when it failed to load or has errors.
+    pub applied: bool,
+    /// Keys that changed and are in effect.
+    pub changed: Vec<String>,
+    /// Keys that changed but keep their running value until a restart.
+    pub restart_required: Vec<String>,
+    pub issues: Vec<Issue>,
+    /// Why the config could not be loaded or applied.
+    pub error: Option<String>,
+}
+
+/// Loads the config again the way it was loaded at startup.
+#[derive(Debug)]
+pub struct Reloader {
+    args: GlobalArgs,
+    log: LogFilter,
+    last: Mutex<Option<ReloadReport>>,
+    /// One reload at a time.
+    running: tokio::sync::Mutex<()>,
+}
+
+impl Reloader {
+    pub fn new(args: GlobalArgs, log: LogFilter) -> Self {
+        Self {
+            args,
+            log,
+            last: Mutex::default(),
+            running: tokio::sync::Mutex::default(),
+        }
+    }
+
+    /// The most recent reload, if any.
+    pub fn last(&self) -> Option<ReloadReport> {
+        self.last.lock().clone()
+    }
+
+    /// The config files read, made absolute to match watcher events.
+    fn files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let dirs = self.args.dirs()?;
+        config_files(&dirs, self.args.config.as_deref())
+            .iter()
+            .map(|f| Ok(std::path::absolute(f)?))
+            .collect()
+    }
+}
+
+/// Load, validate and apply the config files again.
+pub async fn reload(state: &AppState, trigger: Trigger) -> ReloadReport {
+    let reloader = &state.reloader;
+    let _running = reloader.running.lock().await;
+    let mut report = ReloadReport {
+        at: Timestamp::now(),
+        trigger,
+        applied: false,
+        changed: Vec::new(),
+        restart_required: Vec::new(),
+        issues: Vec::new(),
+        error: None,
+    };
+    // Reads files and may resolve `addr`.
+    let args = reloader.args.clone();
+    let loaded = tokio::task::spawn_blocking(move || {
+        let cfg = args.load_config()?;
+        let issues = cfg.validate();
+        anyhow::Ok((cfg, issues))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r);
+    match loaded {
+        Ok((cfg, issues)) => {
+            report.issues = issues;
+            if !report.issues.iter().any(|i| i.severity == Severity::Error)
+                && let Err(e) = apply(state, cfg, &mut report)
+            {
+                report.error = Some(format!("{e:#}"));
+            }
+        }
+        Err(e) => report.error = Some(format!("{e:#}")),
+    }
+    log(&report);
+    *reloader.last.lock() = Some(report.clone());
+    report
+}
+
+fn apply(state: &AppState, new: Config, report: &mut ReloadReport) -> anyhow::Result<()> {
+    let old = state.cfg();
+    let before = fields(&old)?;
+    let mut after = fields(&new)?;
+    // Starting or stopping the HTTPS listener needs a restart.
+    let https_toggled = state.certs.is_loaded() != new.tls_enabled();
+    for (key, value) in after.iter_mut() {
+        let was = before.get(key).unwrap_or(&Value::Null);
+        if value == was {
+            continue;
+        }
+        if RESTART_KEYS.contains(&key.as_str()) || https_toggled && TLS_KEYS.contains(&key.as_str())
+        {
+            report.restart_required.push(key.clone());
+            *value = was.clone();
+        } else {
+            report.changed.push(key.clone());
+        }
+    }
+    let live: Config = serde_json::from_value(Value::Object(after))?;
+    if live.log_level != old.log_level {
+        state.reloader.log.set(&live.log_level)?;
+    }
+    let net = NetPolicy::from_config(&live)?;
+    state.set_config(live, net);
+    report.applied = true;
+    if state.certs.is_loaded()
+        && report
+            .changed
+            .iter()
+            .any(|k| TLS_KEYS.contains(&k.as_str()))
+    {
+        tls::reload(state).context("config applied, but keeping the running certificates")?;
+    }
+    Ok(())
+}
+
+fn fields(cfg: &Config) -> anyhow::Result<Map<String, Value>> {
+    match serde_json::to_value(cfg)? {
+        Value::Object(map) => Ok(map),
+        _ => anyhow::bail!("config is not a table"),
+    }
+}
+
+fn log(report: &ReloadReport) {
+    if let Some(e) = &report.error {
+        error!(trigger = ?report.trigger, "config reload failed: {e}");
+    }
+    for issue in &report.issues {
+        match issue.severity {
+            Severity::Error => {
+                error!(key = issue.key, hint = %issue.hint, "config: {}", issue.message)
+            }
+            Severity::Warning => {
+                warn!(key = issue.key, hint = %issue.hint, "config: {}", issue.message)
+            }
+        }
+    }
+    if !report.applied {
+        if report.error.is_none() {
+            error!(trigger = ?report.trigger, "config has errors; keeping the running one");
+        }
+        return;
+    }
+    if report.changed.is_empty() {
+        debug!(trigger = ?report.trigger, "config reloaded, nothing changed");
+    } else {
+        info!(trigger = ?report.trigger, changed = ?report.changed, "config reloaded");
+    }
+    if !report.restart_required.is_empty() {
+        warn!(keys = ?report.restart_required, "config changes take effect after a restart");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Config,
+    Certs,
+}
+
+/// Reload whenever a config file changes, and the certificates whenever
+/// theirs do. Directories that do not exist yet are not watched.
+pub fn spawn_watcher(state: AppState) -> anyhow::Result<()> {
+    let files = state.reloader.files()?;
+    // Followed as reloads name other certificates.
+    let certs = Arc::new(RwLock::new(tls::files(&state.cfg())));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (config_files, cert_files) = (files.clone(), certs.clone());
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        if event.kind.is_access() {
+            return;
+        }
+        for path in &event.paths {
+            if config_files.contains(path) {
+                let _ = tx.send(Change::Config);
+            } else if cert_files.read().contains(path) {
+                let _ = tx.send(Change::Certs);
+            }
+        }
+    })?;
+    let mut watched = BTreeSet::new();
+    watch(&mut watcher, &mut watched, &files)?;
+    watch(&mut watcher, &mut watched, &certs.read())?;
+    tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            tokio::time::sleep(SETTLE).await;
+            let mut changes = vec![first];
+            while let Ok(change) = rx.try_recv() {
+                changes.push(change);
+            }
+            if changes.contains(&Change::Config) {
+                reload(&state, Trigger::File).await;
+            }
+            if changes.contains(&Change::Certs) && state.certs.is_loaded() {
+                match tls::reload(&state) {
+                    Ok(()) => info!("certificates reloaded"),
+                    Err(e) => error!("certificate reload failed, keeping the running ones: {e:#}"),
+                }
+            }
+            let now = tls::files(&state.cfg());
+            if let Err(e) = watch(&mut watcher, &mut watched, &now) {
+                warn!("certificate changes will not be picked up: {e:#}");
+            }
+            *certs.write() = now;
+        }
+    });
+    Ok(())
+}
+
+/// Watch the directories of `files` not watched yet. Directories rather than
+/// files, which editors and certificate renewals replace.
+fn watch(
+    watcher: &mut RecommendedWatcher,
+    watched: &mut BTreeSet<PathBuf>,
+    files: &[PathBuf],
+) -> anyhow::Result<()> {
+    let dirs: BTreeSet<&Path> = files.iter().filter_map(|f| f.parent()).collect();
+    for dir in dirs.into_iter().filter(|d| d.is_dir()) {
+        if watched.insert(dir.to_path_buf()) {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            debug!(dir = %dir.display(), "watching for changes");
+        }
+    }
+    Ok(())
+}
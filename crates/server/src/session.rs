@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use ferri_core::account::{self, Principal};
+use ferri_core::db::Timestamp;
+use ferri_core::error::Result;
+use ferri_core::session::{self, hash_token};
+use parking_lot::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::api::auth::{SESSION_COOKIE, clear_cookie};
+use crate::error::ApiError;
+use crate::host::{ClientIp, RequestHost};
+use crate::state::AppState;
+
+/// What a session that must change its password can still reach; setting a
+/// new one takes an SRP exchange proving the current one.
+const PASSWORD_CHANGE_PATHS: &[&str] = &[
+    "/api/auth/srp/start",
+    "/api/account/password",
+    "/api/auth/logout",
+];
+
+/// Activity not yet written to `sessions.last_seen_at`, by `id_hash`.
+static SEEN: LazyLock<Mutex<HashMap<String, Timestamp>>> = LazyLock::new(Default::default);
+
+/// The session a request was authenticated with.
+///
+/// Inserted into request extensions by [`authenticate`] next to the [`Principal`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrentSession {
+    pub id_hash: String,
+}
+
+/// Middleware that turns a session cookie into a [`Principal`].
+///
+/// Requests without a valid session stay anonymous; a stale cookie is cleared.
+pub async fn authenticate(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let Some(token) = cookie(req.headers(), SESSION_COOKIE) else {
+        return next.run(req).await;
+    };
+    let id_hash = hash_token(&token);
+
+    match load(&state, &id_hash).await {
+        Ok(Some(mut who)) => {
+            // Out of its allowed networks the session still exists, but does not apply.
+            who.ip = req.extensions().get::<ClientIp>().and_then(|c| c.0);
+            if !who.net_allowed(who.ip) {
+                debug!(account = ?who.username, ip = ?who.ip, "session used from a refused address");
+                return next.run(req).await;
+            }
+            if who.must_change_password && !PASSWORD_CHANGE_PATHS.contains(&req.uri().path()) {
+                return ApiError::PasswordChangeRequired.into_response();
+            }
+            req.extensions_mut().insert(who);
+            req.extensions_mut().insert(CurrentSession { id_hash });
+            next.run(req).await
+        }
+        Ok(None) => {
+            let secure = state.cfg().cookie_secure.unwrap_or_else(|| {
+                req.extensions()
+                    .get::<RequestHost>()
+                    .is_some_and(|h| h.secure)
+            });
+            let mut res = next.run(req).await;
+            if let Ok(v) = HeaderValue::from_str(&clear_cookie(secure)) {
+                res.headers_mut().append(header::SET_COOKIE, v);
+            }
+            res
+        }
+        Err(e) => {
+            warn!("session lookup failed: {e}");
+            next.run(req).await
+        }
+    }
+}
+
+/// Resolve a session, ending it if it has expired, and note the activity.
+async fn load(state: &AppState, id_hash: &str) -> Result<Option<Principal>> {
+    let Some(row) = session::find(&state.db, id_hash).await? else {
+        return Ok(None);
+    };
+    let now = Timestamp::now();
+    let last_seen = SEEN
+        .lock()
+        .get(id_hash)
+        .copied()
+        .unwrap_or(row.last_seen_at)
+        .max(row.last_seen_at);
+    if row.is_expired(now, last_seen, state.cfg().session_idle_timeout) {
+        debug!(account_id = row.account_id, "session expired");
+        SEEN.lock().remove(id_hash);
+        session::delete(&state.db, id_hash).await?;
+        return Ok(None);
+    }
+
+    let Some(who) = Principal::load(&state.db, row.account_id).await? else {
+        session::delete(&state.db, id_hash).await?;
+        return Ok(None);
+    };
+    SEEN.lock().insert(id_hash.to_string(), now);
+    Ok(Some(who))
+}
+
+/// Periodically write batched activity and drop expired sessions.
+pub fn spawn_flush(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = flush(&state).await {
+                warn!("session maintenance failed: {e}");
+            }
+            // Read on every round, so a reloaded config takes effect.
+            let every = state.cfg().session_touch_interval.max(1);
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+async fn flush(state: &AppState) -> Result<()> {
+    let seen: Vec<(String, Timestamp)> = SEEN.lock().drain().collect();
+    if !seen.is_empty() {
+        session::touch_many(&state.db, &seen).await?;
+    }
+    let purged = session::purge_expired(&state.db, state.cfg().session_idle_timeout).await?;
+    if purged > 0 {
+        debug!(purged, "expired sessions removed");
+    }
+    Ok(())
+}
+
+/// Periodically disable accounts past their `expire`, ending their sessions.
+pub fn spawn_account_sweep(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            match account::disable_expired(&state.db).await {
+                Ok(ids) if !ids.is_empty() => info!(?ids, "expired accounts disabled"),
+                Ok(_) => {}
+                Err(e) => warn!("account sweep failed: {e}"),
+            }
+            let every = state.cfg().account_sweep_interval.max(1);
+            tokio::time::sleep(Duration::from_secs(every)).await;
+        }
+    });
+}
+
+/// Drop pending activity for a session that was just deleted.
+pub fn forget(id_hash: &str) {
+    SEEN.lock().remove(id_hash);
+}
+
+/// Value of a cookie from the `Cookie` header(s).
+pub(crate) fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.trim_matches('"').to_string())
+        .filter(|v| !v.is_empty())
+}
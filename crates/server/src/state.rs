@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use ferri_core::config::Config;
+use ferri_core::envelope::Keyring;
+use ferri_core::net::NetPolicy;
+use ferri_core::oidc::Oidc;
+use ferri_core::vfs::Vfs;
+use parking_lot::RwLock;
+use sqlx::{Pool, Sqlite};
+
+use crate::reload::Reloader;
+use crate::tls::Certs;
+
+/// Shared state handed to every handler.
+#[derive(Debug, Clone)]
+pub struct AppState {
+    pub db: Pool<Sqlite>,
+    /// OpenID Connect providers from the config.
+    pub oidc: Arc<Oidc>,
+    /// Master keys for secrets stored in the database.
+    pub keys: Arc<Keyring>,
+    /// Reloads the config; see [`crate::reload`].
+    pub reloader: Arc<Reloader>,
+    /// Certificates for HTTPS; see [`crate::tls`].
+    pub certs: Arc<Certs>,
+    cfg: Arc<RwLock<Arc<Config>>>,
+    net: Arc<RwLock<Arc<NetPolicy>>>,
+    vfs: Arc<RwLock<Arc<Vfs>>>,
+}
+
+impl AppState {
+    /// Build the state and load the initial VFS snapshot.
+    pub async fn new(cfg: Config, db: Pool<Sqlite>, reloader: Reloader) -> anyhow::Result<Self> {
+        let vfs = Vfs::load(&db).await?;
+        let oidc = Oidc::new(&cfg.oidc_providers)?;
+        let keys = Keyring::from_config(&cfg)?;
+        let net = NetPolicy::from_config(&cfg)?;
+        Ok(Self {
+            db,
+            oidc: Arc::new(oidc),
+            keys: Arc::new(keys),
+            reloader: Arc::new(reloader),
+            certs: Arc::default(),
+            cfg: Arc::new(RwLock::new(Arc::new(cfg))),
+            net: Arc::new(RwLock::new(Arc::new(net))),
+            vfs: Arc::new(RwLock::new(Arc::new(vfs))),
+        })
+    }
+
+    /// Config in effect. Like [`AppState::vfs`], hold it for one request.
+    pub fn cfg(&self) -> Arc<Config> {
+        self.cfg.read().clone()
+    }
+
+    /// Trusted proxies and the global allow/deny lists.
+    pub fn net(&self) -> Arc<NetPolicy> {
+        self.net.read().clone()
+    }
+
+    /// Swap in a reloaded config and the network lists built from it.
+    pub fn set_config(&self, cfg: Config, net: NetPolicy) {
+        *self.net.write() = Arc::new(net);
+        *self.cfg.write() = Arc::new(cfg);
+    }
+
+    /// Current VFS snapshot. Cheap to call; hold it for the length of a request.
+    pub fn vfs(&self) -> Arc<Vfs> {
+        self.vfs.read().clone()
+    }
+
+    /// Load the VFS tables again and swap the snapshot; requests already
+    /// holding the old one finish with it.
+    pub async fn reload_vfs(&self) -> anyhow::Result<()> {
+        let vfs = Vfs::load(&self.db).await?;
+        *self.vfs.write() = Arc::new(vfs);
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! HTTPS on `https_port`.
+//!
+//! The certificate for a connection is picked from the host name the client
+//! asks for (SNI): `tls_cert` and `tls_certs` are matched against the names
+//! they were issued for, and `tls_cert` is served when none matches. They are
+//! loaded again when their files change, without dropping connections.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::Router;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Redirect, Response};
+use ferri_core::config::Config;
+use ferri_core::vfs::Vfs;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use parking_lot::RwLock;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::{CryptoProvider, ring};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+use webpki::EndEntityCert;
+
+use crate::host::RequestHost;
+use crate::state::AppState;
+
+/// Clients that have not finished the handshake by then are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ACME HTTP-01 challenges must be answered over plain HTTP.
+const ACME_CHALLENGE: &str = "/.well-known/acme-challenge/";
+
+/// Marks requests that came in through the HTTPS listener.
+///
+/// Inserted into request extensions by [`serve`].
+#[derive(Debug, Clone, Copy)]
+pub struct TlsConnection;
+
+/// A certificate chain with its key, and the host names it is valid for.
+#[derive(Debug)]
+struct Entry {
+    /// Lower-cased; wildcards (`*.example.com`) included.
+    names: Vec<String>,
+    key: Arc<CertifiedKey>,
+}
+
+/// Certificates served on `https_port`; empty when HTTPS is off.
+#[derive(Debug, Default)]
+pub struct Certs {
+    /// `tls_cert` first, then `tls_certs` in order.
+    entries: RwLock<Arc<Vec<Entry>>>,
+}
+
+impl Certs {
+    /// HTTPS is being served.
+    pub fn is_loaded(&self) -> bool {
+        !self.entries.read().is_empty()
+    }
+
+    /// Load the certificates `cfg` names and swap them in. When any fails,
+    /// the ones in use are kept.
+    fn load(&self, cfg: &Config) -> anyhow::Result<()> {
+        let (Some(cert), Some(key)) = (&cfg.tls_cert, &cfg.tls_key) else {
+            anyhow::bail!("tls_cert and tls_key are not set");
+        };
+        let pairs =
+            std::iter::once((cert, key)).chain(cfg.tls_certs.iter().map(|p| (&p.cert, &p.key)));
+        let entries = pairs
+            .map(|(cert, key)| {
+                let entry = load_pair(cert, key)?;
+                debug!(file = cert, names = ?entry.names, "certificate loaded");
+                Ok(entry)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        *self.entries.write() = Arc::new(entries);
+        Ok(())
+    }
+
+    /// The certificate for `host`: one issued for that exact name, else one
+    /// with a matching wildcard, else `tls_cert`.
+    fn pick(&self, host: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let entries = self.entries.read().clone();
+        let found = host.and_then(|host| {
+            let has = |matches: fn(&str, &str) -> bool| {
+                entries
+                    .iter()
+                    .find(|e| e.names.iter().any(|n| matches(n, host)))
+            };
+            has(|n, h| n.eq_ignore_ascii_case(h)).or_else(|| has(wildcard_matches))
+        });
+        found.or(entries.first()).map(|e| e.key.clone())
+    }
+
+    /// Host masks of `vfs` roots that no certificate is issued for; clients
+    /// asking for them get `tls_cert`.
+    fn uncovered(&self, vfs: &Vfs) -> Vec<String> {
+        let entries = self.entries.read().clone();
+        let names = || entries.iter().flat_map(|e| &e.names);
+        vfs.roots()
+            .iter()
+            .map(|r| r.host_mask.as_str())
+            .filter(|mask| !mask.chars().all(|c| c == '*'))
+            .filter(|mask| {
+                if mask.contains(['*', '?']) {
+                    // Only a certificate for the same wildcard covers them all.
+                    !names().any(|n| n.eq_ignore_ascii_case(mask))
+                } else {
+                    !names().any(|n| n.eq_ignore_ascii_case(mask) || wildcard_matches(n, mask))
+                }
+            })
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl ResolvesServerCert for Certs {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.pick(hello.server_name())
+    }
+}
+
+/// `*.example.com` matches `a.example.com`, but neither `example.com` nor
+/// `a.b.example.com`.
+fn wildcard_matches(name: &str, host: &str) -> bool {
+    let Some(domain) = name.strip_prefix("*.") else {
+        return false;
+    };
+    host.split_once('.')
+        .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(domain))
+}
+
+fn load_pair(cert: &str, key: &str) -> anyhow::Result<Entry> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("cannot read certificates from {cert}"))?;
+    let Some(leaf) = chain.first() else {
+        anyhow::bail!("{cert} holds no certificate");
+    };
+    let names = EndEntityCert::try_from(leaf)
+        .with_context(|| format!("cannot parse the certificate in {cert}"))?
+        .valid_dns_names()
+        .map(str::to_ascii_lowercase)
+        .collect();
+    let private = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("cannot read a private key from {key}"))?;
+    let key = CertifiedKey::from_der(chain, private, &provider())
+        .with_context(|| format!("{key} is not the key for {cert}"))?;
+    Ok(Entry {
+        names,
+        key: Arc::new(key),
+    })
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Certificate and key files the config names, made absolute to match
+/// watcher events.
+pub fn files(cfg: &Config) -> Vec<PathBuf> {
+    let default = cfg.tls_cert.iter().zip(&cfg.tls_key);
+    default
+        .chain(cfg.tls_certs.iter().map(|p| (&p.cert, &p.key)))
+        .flat_map(|(cert, key)| [cert, key])
+        .filter_map(|f| std::path::absolute(f).ok())
+        .collect()
+}
+
+/// Load the certificates the config in effect names, and warn about VFS
+/// hosts none of them is issued for.
+pub fn reload(state: &AppState) -> anyhow::Result<()> {
+    state.certs.load(&state.cfg())?;
+    for mask in state.certs.uncovered(&state.vfs()) {
+        warn!(
+            host = mask,
+            "no certificate is issued for this host; tls_cert is served"
+        );
+    }
+    Ok(())
+}
+
+/// TLS settings for the listener, taking certificates from `certs`.
+pub fn acceptor(certs: Arc<Certs>) -> anyhow::Result<TlsAcceptor> {
+    let mut config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(certs);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serve `app` over HTTP/1.1 or HTTP/2, as the client picks, on TLS
+/// connections accepted from `listener`.
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    if let Ok(addr) = listener.local_addr() {
+        info!(%addr, "serving HTTPS");
+    }
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                // Out of file descriptors, most likely; let some close.
+                warn!("cannot accept an HTTPS connection: {e}");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!(%peer, "TLS handshake failed: {e}");
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(%peer, "TLS handshake timed out");
+                        return;
+                    }
+                };
+            let service = app.map_request(move |mut req: http::Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(peer));
+                req.extensions_mut().insert(TlsConnection);
+                req
+            });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(
+                    TokioIo::new(stream),
+                    TowerToHyperService::new(service),
+                )
+                .await
+            {
+                debug!(%peer, "HTTPS connection ended: {e}");
+            }
+        });
+    }
+}
+
+/// Middleware that sends plain-HTTP requests to HTTPS when `https_redirect`
+/// is on. Runs after [`crate::host::dispatch`], which tells whether the client
+/// used HTTPS.
+pub async fn redirect(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let cfg = state.cfg();
+    let host = req
+        .extensions()
+        .get::<RequestHost>()
+        .filter(|h| !h.secure)
+        .and_then(|h| h.host.as_deref());
+    let location = match host {
+        Some(host)
+            if cfg.https_redirect
+                && state.certs.is_loaded()
+                && !req.uri().path().starts_with(ACME_CHALLENGE) =>
+        {
+            let port = match cfg.https_port {
+                443 => String::new(),
+                port => format!(":{port}"),
+            };
+            let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            Some(format!("https://{host}{port}{path}"))
+        }
+        _ => None,
+    };
+    match location {
+        Some(location) => Redirect::permanent(&location).into_response(),
+        None => next.run(req).await,
+    }
+}
@@ -0,0 +1,16 @@
+-- Resumable uploads (tus protocol) that survive a restart
+CREATE TABLE uploads (
+    id          TEXT PRIMARY KEY,              -- random; part of the upload URL
+    account_id  INTEGER REFERENCES accounts(id) ON DELETE CASCADE,  -- NULL = anonymous
+    root_id     INTEGER NOT NULL REFERENCES vfs_nodes(id) ON DELETE CASCADE,
+    folder_path TEXT    NOT NULL,              -- URL path of the target folder under root_id
+    name        TEXT    NOT NULL,              -- file name requested by the client
+    temp_path   TEXT    NOT NULL,              -- partial data on disk, beside the target
+    length      INTEGER NOT NULL,              -- Upload-Length
+    offset      INTEGER NOT NULL DEFAULT 0,    -- bytes received so far
+    metadata    TEXT,                          -- raw Upload-Metadata header
+    created_at  INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    expires_at  INTEGER NOT NULL               -- pushed forward on every PATCH
+);
+
+CREATE INDEX idx_uploads_expires ON uploads(expires_at);
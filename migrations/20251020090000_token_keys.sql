@@ -0,0 +1,7 @@
+-- Envelope encryption for provider_tokens: each row's data key, wrapped by
+-- the master key named in key_id.
+ALTER TABLE provider_tokens ADD COLUMN key_id TEXT;
+ALTER TABLE provider_tokens ADD COLUMN wrapped_key BLOB;
+
+CREATE INDEX idx_provider_tokens_identity ON provider_tokens(account_identity_id);
+CREATE INDEX idx_provider_tokens_key ON provider_tokens(key_id);
@@ -0,0 +1,3 @@
+-- Per-node network allow/deny lists, applying to the node and everything below it
+ALTER TABLE vfs_nodes ADD COLUMN allow_net TEXT;   -- network mask(s); NULL = anyone
+ALTER TABLE vfs_nodes ADD COLUMN deny_net  TEXT;   -- network mask(s); NULL = no one
@@ -0,0 +1,17 @@
+-- Administrative changes, newest last
+CREATE TABLE audit_log (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    at          INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    actor_id    INTEGER REFERENCES accounts(id) ON DELETE SET NULL,
+    actor       TEXT,                 -- username at the time; kept when the account goes
+    ip          TEXT,
+    action      TEXT    NOT NULL,     -- e.g. account.create, account.update, membership.add
+    target_kind TEXT    NOT NULL,     -- account | vfs_node | ...
+    target_id   INTEGER,
+    target      TEXT,                 -- name of the target at the time
+    detail      TEXT                  -- JSON
+);
+
+CREATE INDEX idx_audit_at     ON audit_log(at);
+CREATE INDEX idx_audit_target ON audit_log(target_kind, target_id);
+CREATE INDEX idx_audit_actor  ON audit_log(actor_id);
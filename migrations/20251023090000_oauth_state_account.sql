@@ -0,0 +1,3 @@
+-- The account signed in when a sign-in started, if any; finishing it can only
+-- link an identity to that account.
+ALTER TABLE oauth_states ADD COLUMN account_id INTEGER REFERENCES accounts(id) ON DELETE CASCADE;
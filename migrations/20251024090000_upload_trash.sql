@@ -0,0 +1,11 @@
+-- Partial files left by uploads whose row is gone, including rows removed by
+-- a cascade from accounts or vfs_nodes; the upload GC deletes the files.
+CREATE TABLE upload_trash (
+    temp_path TEXT PRIMARY KEY
+);
+
+CREATE TRIGGER trg_uploads_trash
+AFTER DELETE ON uploads
+FOR EACH ROW BEGIN
+    INSERT OR IGNORE INTO upload_trash (temp_path) VALUES (OLD.temp_path);
+END;